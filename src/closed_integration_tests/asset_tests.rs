@@ -0,0 +1,185 @@
+use super::*;
+
+#[test]
+fn test_that_only_admin_can_add_assets() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: caller,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: vault_id,
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+
+    let tx_result = _add_asset(&pic, vault_id, caller, details);
+
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can add assets" }));
+    assert_eq!(_list_assets(&pic, vault_id).len(), 1);
+}
+
+#[test]
+fn test_that_asset_metadata_is_fetched_after_install() {
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    for _ in 0..5 {
+        pic.tick();
+    }
+
+    let details = _get_liquidity_manager_details(&pic, vault_id, INITIAL_ASSET_ID).unwrap();
+    let metadata = details.metadata.unwrap();
+
+    assert_eq!(metadata.asset.decimals, 8);
+    assert_eq!(metadata.asset.symbol, "ICP".to_string());
+    assert_eq!(metadata.virtual_asset.decimals, metadata.asset.decimals);
+    assert_eq!(
+        _get_asset_metadata_status(&pic, vault_id, INITIAL_ASSET_ID),
+        Some(MetadataStatus::Validated)
+    );
+
+    let (_, _, asset_id) = _setup_asset(&pic, vault_id, 0);
+
+    let details = _get_liquidity_manager_details(&pic, vault_id, asset_id).unwrap();
+    assert!(details.metadata.is_some());
+}
+
+#[test]
+fn test_that_vault_must_mint_the_virtual_asset() {
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let token_wasm = fs::read(TOKEN_WASM).expect("Wasm file not found, run 'dfx build'.");
+
+    let mut ledger_ids = vec![];
+    for _ in 0..2 {
+        let ledger_id = pic.create_canister();
+        pic.add_cycles(ledger_id, 2_000_000_000_000); // 2T Cycles
+        pic.install_canister(
+            ledger_id,
+            token_wasm.clone(),
+            encode_one(LedgerArg::Init(create_args(Principal::anonymous()))).unwrap(),
+            Some(Principal::anonymous()),
+        );
+        ledger_ids.push(ledger_id);
+    }
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: ledger_ids[0],
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: ledger_ids[1],
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+
+    let tx_result = _add_asset(&pic, vault_id, Principal::anonymous(), details);
+
+    assert!(tx_result
+        .is_err_and(|err| { err == "Vault is not the minting account of the virtual asset" }));
+    assert_eq!(_list_assets(&pic, vault_id).len(), 1);
+}
+
+#[test]
+fn test_that_asset_ledgers_can_not_be_reused() {
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    let (_, new_vtoken_id, _) = _setup_asset(&pic, vault_id, 0);
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: token_id,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: new_vtoken_id,
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+
+    let tx_result = _add_asset(&pic, vault_id, Principal::anonymous(), details);
+
+    assert!(tx_result.is_err_and(|err| { err == "Asset ledger already used" }));
+}
+
+#[test]
+fn test_that_assets_have_separate_vaults_and_balances() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    let (new_token_id, _, asset_id) = _setup_asset(&pic, vault_id, 0);
+
+    assert_eq!(asset_id, INITIAL_ASSET_ID + 1);
+    let assets = _list_assets(&pic, vault_id);
+    assert_eq!(assets.len(), 2);
+    assert_eq!(assets[0].1.asset.ledger_id, token_id);
+    assert_eq!(assets[1].1.asset.ledger_id, new_token_id);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let args = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: caller,
+            subaccount: None,
+        },
+        amount: Nat::from(1000000000000000000u128),
+        fee: None,
+        memo: None,
+    };
+    let _ = _icrc1_transfer(&pic, new_token_id, args, Principal::anonymous());
+
+    let deposit_amount = 5000000u128;
+    _approve_spending(&pic, new_token_id, deposit_amount, caller, vault_id);
+
+    let tx_result = _fund_asset_account(&pic, vault_id, asset_id, deposit_amount, caller);
+    assert!(tx_result.is_ok());
+
+    assert_eq!(
+        _get_asset_margin_balance(&pic, vault_id, asset_id, caller),
+        deposit_amount
+    );
+
+    let amount_utilised = 1000000u128;
+    let tx_result = _provide_asset_leverage(&pic, vault_id, asset_id, amount_utilised, caller);
+    assert!(tx_result.is_ok());
+
+    let new_vault = _get_asset_vault(&pic, vault_id, asset_id).unwrap();
+    assert_eq!(new_vault.free_liquidity, amount_utilised);
+
+    // the initial asset is left unchanged
+    assert_eq!(_get_vault(&pic, vault_id, caller).free_liquidity, 0);
+    assert_eq!(
+        _get_user_margin_balance(&pic, vault_id, caller),
+        10000000000u128
+    );
+
+    let locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(locks.len(), 1);
+    assert_eq!(locks[0].1.asset_id, asset_id);
+
+    assert!(_get_asset_vault(&pic, vault_id, asset_id + 1).is_none());
+}
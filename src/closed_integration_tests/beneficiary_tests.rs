@@ -0,0 +1,111 @@
+use super::*;
+
+#[test]
+fn test_that_locking_for_invalid_beneficiary_fails() {
+    let caller = _get_principals()[1];
+    let beneficiary = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let amount_utilised = 1000000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount_utilised, caller);
+
+    _approve_spending(&pic, vtoken_id, amount_utilised, caller, vault_id);
+
+    let beneficiary_account = Account {
+        owner: beneficiary,
+        subaccount: Some([1; 32]),
+    };
+
+    let tx_result = _stake_for(
+        &pic,
+        caller,
+        vault_id,
+        amount_utilised,
+        MONTH2_SPAN_ID,
+        beneficiary_account,
+    );
+
+    assert!(tx_result.is_err_and(|err| { err == "Invalid beneficiary" }));
+}
+
+#[test]
+fn test_that_lock_belongs_to_beneficiary() {
+    let caller = _get_principals()[1];
+    let beneficiary = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let amount_utilised = 1000000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount_utilised, caller);
+
+    _approve_spending(&pic, vtoken_id, amount_utilised, caller, vault_id);
+
+    let beneficiary_account = Account {
+        owner: beneficiary,
+        subaccount: None,
+    };
+
+    let tx_result = _stake_for(
+        &pic,
+        caller,
+        vault_id,
+        amount_utilised,
+        MONTH2_SPAN_ID,
+        beneficiary_account,
+    );
+    assert!(tx_result.is_ok_and(|amount| amount == amount_utilised));
+
+    // only the caller's instant lock is left with the caller
+    assert_eq!(_get_user_stakes(&pic, vault_id, caller).len(), 1);
+
+    let beneficiary_locks = _get_user_stakes(&pic, vault_id, beneficiary);
+    assert_eq!(beneficiary_locks.len(), 1);
+
+    let (_, lock, _) = beneficiary_locks[0];
+    assert_eq!(lock.span_id, MONTH2_SPAN_ID);
+    assert_eq!(lock.amount, amount_utilised);
+    assert_eq!(lock.funded_by, Some(caller));
+}
+
+#[test]
+fn test_that_lending_for_beneficiary_mints_to_beneficiary() {
+    let caller = _get_principals()[1];
+    let beneficiary = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let amount_utilised = 1000000u128;
+
+    let beneficiary_account = Account {
+        owner: beneficiary,
+        subaccount: None,
+    };
+
+    let tx_result =
+        _provide_leverage_for(&pic, vault_id, amount_utilised, caller, beneficiary_account);
+    assert!(tx_result.is_ok());
+
+    let balance = _icrc1_balance_of(&pic, vtoken_id, beneficiary_account, beneficiary);
+    assert_eq!(balance, Nat::from(amount_utilised));
+
+    assert!(_get_user_stakes(&pic, vault_id, caller).is_empty());
+
+    let (_, lock, _) = _get_user_stakes(&pic, vault_id, beneficiary)[0];
+    assert_eq!(lock.span_id, INSTANT_SPAN_ID);
+    assert_eq!(lock.funded_by, Some(caller));
+}
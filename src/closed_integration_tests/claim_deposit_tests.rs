@@ -0,0 +1,93 @@
+use super::*;
+
+fn _mint(pic: &PocketIc, token_id: Principal, to: Principal, amount: Amount) {
+    let args = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: to,
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+    };
+    let _ = _icrc1_transfer(pic, token_id, args, Principal::anonymous());
+}
+
+#[test]
+fn test_that_transferred_deposit_is_claimed_once() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    let deposit_amount = 10000000000u128;
+    _mint(&pic, token_id, user, deposit_amount);
+    let block_index = _transfer_to_vault(&pic, vault_id, token_id, deposit_amount, user);
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, block_index, user);
+    assert!(tx_result.is_ok_and(|amount| amount == deposit_amount));
+
+    assert_eq!(
+        _get_user_margin_balance(&pic, vault_id, user),
+        deposit_amount
+    );
+    assert_eq!(
+        _get_deposit_claimant(&pic, vault_id, INITIAL_ASSET_ID, block_index),
+        Some(user)
+    );
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, block_index, user);
+    assert!(tx_result.is_err_and(|err| { err == "Deposit already claimed" }));
+
+    assert_eq!(
+        _get_user_margin_balance(&pic, vault_id, user),
+        deposit_amount
+    );
+}
+
+#[test]
+fn test_that_deposit_can_only_be_claimed_by_its_sender() {
+    let user = _get_principals()[1];
+    let other_user = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    let deposit_amount = 10000000000u128;
+    _mint(&pic, token_id, user, deposit_amount);
+    let block_index = _transfer_to_vault(&pic, vault_id, token_id, deposit_amount, user);
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, block_index, other_user);
+    assert!(tx_result.is_err_and(|err| { err == "Deposit was not sent by caller" }));
+
+    assert_eq!(_get_user_margin_balance(&pic, vault_id, other_user), 0);
+    assert_eq!(
+        _get_deposit_claimant(&pic, vault_id, INITIAL_ASSET_ID, block_index),
+        None
+    );
+}
+
+#[test]
+fn test_that_non_deposit_blocks_can_not_be_claimed() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    // blocks 0 to 2 are the mint,the approval and the vault's transfer from
+    _mint_approve_and_fund_account(&pic, vault_id, user, token_id);
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, 0, user);
+    assert!(tx_result.is_err_and(|err| { err == "Block is not a transfer" }));
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, 2, user);
+    assert!(tx_result.is_err_and(|err| { err == "Deposit already claimed" }));
+
+    let tx_result = _claim_deposit(&pic, vault_id, INITIAL_ASSET_ID, 100, user);
+    assert!(tx_result.is_err_and(|err| { err == "Block not found" }));
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+#[test]
+fn test_that_instant_locks_can_not_compound() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let _ = _provide_leverage(&pic, vault_id, 1000000u128, caller);
+
+    let user_locks = _get_user_stakes(&pic, vault_id, caller);
+
+    let tx_result = _set_lock_compounding(&pic, vault_id, caller, user_locks[0].0, true);
+
+    assert!(tx_result.is_err_and(|err| { err == "Can not compound instant locks" }));
+}
+
+#[test]
+fn test_that_compounding_rolls_earnings_into_lock_amount() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let amount_utilised = 1000000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount_utilised, caller);
+
+    _approve_spending(&pic, vtoken_id, amount_utilised, caller, vault_id);
+
+    let _ = _stake(
+        &pic,
+        caller,
+        vault_id,
+        amount_utilised,
+        MONTH2_SPAN_ID,
+        None,
+    );
+
+    let (lock_timestamp, _, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    // nothing was compounding before so nothing gets rolled in
+    let tx_result = _set_lock_compounding(&pic, vault_id, caller, lock_timestamp, true);
+    assert!(tx_result.is_ok_and(|compounded| compounded == 0));
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, 10000);
+
+    // let the whole fee be credited to the spans
+    pic.advance_time(FEE_STREAM_WINDOW);
+
+    let (_, lock_before, earnings) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert!(earnings > 0);
+
+    let tx_result = _set_lock_compounding(&pic, vault_id, caller, lock_timestamp, true);
+    assert!(tx_result.is_ok_and(|compounded| compounded == earnings));
+
+    let (_, lock_after, earnings_after) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    assert_eq!(lock_after.amount, lock_before.amount + earnings);
+    assert_eq!(earnings_after, 0);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&MONTH2_SPAN_ID].total_locked, lock_after.amount);
+}
@@ -0,0 +1,65 @@
+use super::*;
+use crate::core_lib::lock::_ONE_PERCENT;
+
+#[test]
+fn test_that_only_admin_can_set_early_unlock_penalty() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let tx_result =
+        _set_early_unlock_penalty(&pic, vault_id, caller, YEAR_SPAN_ID, 10 * _ONE_PERCENT);
+
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can set early unlock penalty" }));
+}
+
+#[test]
+fn test_that_early_unlock_returns_principal_minus_penalty() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    let tx_result = _set_early_unlock_penalty(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        YEAR_SPAN_ID,
+        10 * _ONE_PERCENT,
+    );
+    assert!(tx_result.is_ok());
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let amount_utilised = 1000000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount_utilised, caller);
+
+    _approve_spending(&pic, vtoken_id, amount_utilised, caller, vault_id);
+
+    let _ = _stake(&pic, caller, vault_id, amount_utilised, YEAR_SPAN_ID, None);
+
+    let (lock_timestamp, _, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    let preview = _preview_early_unlock(&pic, vault_id, caller, lock_timestamp).unwrap();
+
+    // penalty can be at most 10 percent of the principal
+    assert!(preview.penalty > 0 && preview.penalty <= amount_utilised / 10);
+    assert_eq!(
+        preview.principal_returned,
+        amount_utilised - preview.penalty
+    );
+
+    let tx_result = _early_unlock(&pic, vault_id, caller, lock_timestamp);
+
+    assert!(tx_result.is_ok_and(|amount| amount >= preview.principal_returned + preview.earnings));
+
+    let user_locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(user_locks.len(), 1);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&YEAR_SPAN_ID].total_locked, 0);
+}
@@ -0,0 +1,155 @@
+use super::*;
+
+/// Sets up a vault with a funded user holding a single Month2 lock of `amount`
+///
+/// Returns the vault's ids,the user and the lock's timestamp
+fn _setup_vault_with_lock(
+    pic: &PocketIc,
+    amount: Amount,
+) -> (Principal, Principal, Principal, Time) {
+    let caller = _get_principals()[1];
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(pic, 0);
+
+    _mint_approve_and_fund_account(pic, vault_id, caller, token_id);
+
+    let _ = _provide_leverage(pic, vault_id, amount, caller);
+
+    _approve_spending(pic, vtoken_id, amount, caller, vault_id);
+
+    let _ = _stake(pic, caller, vault_id, amount, MONTH2_SPAN_ID, None);
+
+    let (lock_timestamp, _, _) = _get_user_stakes(pic, vault_id, caller)[1];
+
+    (vtoken_id, vault_id, caller, lock_timestamp)
+}
+
+/// Advances time past the expiry of a Month2 lock and lets the expiry sweep run
+fn _pass_lock_expiry(pic: &PocketIc) {
+    pic.advance_time(std::time::Duration::from_secs(61 * 24 * 60 * 60));
+    for _ in 0..5 {
+        pic.tick();
+    }
+}
+
+#[test]
+fn test_that_instant_locks_can_not_set_expiry_action() {
+    let pic = PocketIc::new();
+
+    let (_, vault_id, caller, _) = _setup_vault_with_lock(&pic, 1000000u128);
+
+    let (instant_lock_timestamp, _, _) = _get_user_stakes(&pic, vault_id, caller)[0];
+
+    let tx_result = _set_lock_expiry_action(
+        &pic,
+        vault_id,
+        caller,
+        instant_lock_timestamp,
+        ExpiryAction::Renew,
+    );
+
+    assert!(tx_result.is_err_and(|err| { err == "Can not set expiry action of instant locks" }));
+}
+
+#[test]
+fn test_that_renewing_lock_is_locked_again_at_expiry() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) = _setup_vault_with_lock(&pic, amount);
+
+    let tx_result =
+        _set_lock_expiry_action(&pic, vault_id, caller, lock_timestamp, ExpiryAction::Renew);
+    assert!(tx_result.is_ok());
+
+    let (_, lock_before, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    _pass_lock_expiry(&pic);
+
+    let (_, lock_after, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    assert!(lock_after.expiry_time > lock_before.expiry_time);
+    assert!(lock_after.expiry_time > _get_current_time(&pic));
+    assert_eq!(lock_after.amount, amount);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&MONTH2_SPAN_ID].total_locked, amount);
+}
+
+#[test]
+fn test_that_lock_moved_to_instant_is_locked_in_instant_span_at_expiry() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (vtoken_id, vault_id, caller, lock_timestamp) = _setup_vault_with_lock(&pic, amount);
+
+    let tx_result = _set_lock_expiry_action(
+        &pic,
+        vault_id,
+        caller,
+        lock_timestamp,
+        ExpiryAction::MoveToInstant,
+    );
+    assert!(tx_result.is_ok());
+
+    let account = Account {
+        owner: caller,
+        subaccount: None,
+    };
+    let balance_before = _icrc1_balance_of(&pic, vtoken_id, account, caller);
+
+    _pass_lock_expiry(&pic);
+
+    // the lock is kept in the instant span and nothing is transferred
+    let (moved_lock_timestamp, moved_lock, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert_eq!(moved_lock_timestamp, lock_timestamp);
+    assert_eq!(moved_lock.span_id, INSTANT_SPAN_ID);
+    assert_eq!(moved_lock.amount, amount);
+    assert_eq!(
+        _icrc1_balance_of(&pic, vtoken_id, account, caller),
+        balance_before
+    );
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&MONTH2_SPAN_ID].total_locked, 0);
+
+    // the held amount is returned when the instant lock is unlocked
+    let tx_result = _unlock(&pic, vault_id, caller, lock_timestamp);
+    assert!(tx_result.is_ok_and(|sent| sent == amount));
+    assert_eq!(
+        _icrc1_balance_of(&pic, vtoken_id, account, caller),
+        balance_before + Nat::from(amount)
+    );
+}
+
+#[test]
+fn test_that_renewing_lock_is_kept_when_span_is_over_capacity() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) = _setup_vault_with_lock(&pic, amount);
+
+    let tx_result =
+        _set_lock_expiry_action(&pic, vault_id, caller, lock_timestamp, ExpiryAction::Renew);
+    assert!(tx_result.is_ok());
+
+    let (_, mut config) = _get_lock_spans(&pic, vault_id)[0];
+    config.max_capacity = Some(amount / 2);
+    let _ = _set_lock_span(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        MONTH2_SPAN_ID,
+        config,
+    );
+
+    let (_, lock_before, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    _pass_lock_expiry(&pic);
+
+    let (_, lock_after, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert_eq!(lock_after.expiry_time, lock_before.expiry_time);
+}
@@ -0,0 +1,90 @@
+use super::*;
+
+/// Sets up a vault with an approved market and a lender that provided `amount`
+///
+/// Returns the vault's ids,the lender and the market
+fn _setup_vault_with_lender(
+    pic: &PocketIc,
+    amount: Amount,
+) -> (Principal, Principal, Principal, Principal) {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let (token_id, _, vault_id) = _setup_vault(pic, 0);
+
+    _mint_approve_and_fund_account(pic, vault_id, caller, token_id);
+    _approve_market(pic, vault_id, market);
+
+    let _ = _provide_leverage(pic, vault_id, amount, caller);
+
+    (token_id, vault_id, caller, market)
+}
+
+#[test]
+fn test_that_fees_are_credited_over_the_stream_window() {
+    let pic = PocketIc::new();
+
+    let (_, vault_id, caller, market) = _setup_vault_with_lender(&pic, 1000000u128);
+
+    let fees = 10000u128;
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, fees);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert!(vault.fee_stream.undistributed_fees > fees / 2);
+
+    let (_, _, earnings) = _get_user_stakes(&pic, vault_id, caller)[0];
+    assert!(earnings < fees / 2);
+
+    pic.advance_time(FEE_STREAM_WINDOW);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.fee_stream.undistributed_fees, 0);
+
+    let (_, _, earnings) = _get_user_stakes(&pic, vault_id, caller)[0];
+    assert!(earnings > fees / 2);
+}
+
+#[test]
+fn test_that_lenders_joining_after_a_fee_earn_less_of_it() {
+    let late_lender = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (token_id, vault_id, caller, market) = _setup_vault_with_lender(&pic, amount);
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, 10000);
+
+    _mint_approve_and_fund_account(&pic, vault_id, late_lender, token_id);
+    let _ = _provide_leverage(&pic, vault_id, amount, late_lender);
+
+    pic.advance_time(FEE_STREAM_WINDOW);
+
+    let (_, _, earnings) = _get_user_stakes(&pic, vault_id, caller)[0];
+    let (_, _, late_earnings) = _get_user_stakes(&pic, vault_id, late_lender)[0];
+
+    assert!(late_earnings < earnings);
+}
+
+#[test]
+fn test_that_fees_are_credited_at_once_without_stream_window() {
+    let pic = PocketIc::new();
+
+    let (_, vault_id, caller, market) = _setup_vault_with_lender(&pic, 1000000u128);
+
+    let tx_result = _set_fee_stream_window(&pic, vault_id, caller, 0);
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can set fee stream window" }));
+
+    let tx_result = _set_fee_stream_window(&pic, vault_id, Principal::anonymous(), 0);
+    assert!(tx_result.is_ok());
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, 10000);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.fee_stream.undistributed_fees, 0);
+
+    let (_, _, earnings) = _get_user_stakes(&pic, vault_id, caller)[0];
+    assert!(earnings > 0);
+}
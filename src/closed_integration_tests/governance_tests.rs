@@ -0,0 +1,49 @@
+use super::*;
+
+#[test]
+fn test_that_governance_executes_validated_proposals() {
+    let admin = _get_principals()[0];
+    let governance = _get_principals()[1];
+    let market = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault_with_admin(&pic, 0, admin);
+
+    let settings = GovernanceSettings {
+        governance_canister: Some(governance),
+        mode: GovernanceMode::Governance,
+    };
+    _set_governance(&pic, vault_id, admin, settings).unwrap();
+
+    let args = candid::encode_args((market, "Perps".to_string())).unwrap();
+    let description = _validate(&pic, vault_id, "approveMarket", args.clone());
+    assert_eq!(
+        description,
+        Ok(format!("Approve market {} named \"Perps\"", market))
+    );
+
+    // the admin path is disabled,the proposal is executed by the governance canister
+    let Ok(WasmResult::Reply(val)) =
+        pic.update_call(vault_id, admin, "approveMarket", args.clone())
+    else {
+        panic!("Approve market failed")
+    };
+    let tx_result: Result<(), String> = decode_one(&val).unwrap();
+    assert!(tx_result.is_err_and(|err| { err == "Only market manager can approve markets" }));
+
+    let Ok(WasmResult::Reply(val)) =
+        pic.update_call(vault_id, governance, "approveMarket", args.clone())
+    else {
+        panic!("Approve market failed")
+    };
+    let tx_result: Result<(), String> = decode_one(&val).unwrap();
+    assert!(tx_result.is_ok());
+    assert_eq!(_list_markets(&pic, vault_id)[0].1.approved_by, governance);
+
+    let description = _validate(&pic, vault_id, "approveMarket", args);
+    assert!(description.is_err_and(|err| { err == "Market already approved" }));
+
+    let tx_result = _set_governance(&pic, vault_id, admin, GovernanceSettings::default());
+    assert!(tx_result.is_err_and(|err| { err == "Only admin can set governance" }));
+}
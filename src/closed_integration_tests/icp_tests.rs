@@ -0,0 +1,218 @@
+use super::*;
+
+#[test]
+fn test_that_icp_is_deposited_with_icrc2_approval() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let initial_balance = 10_000_000u64;
+    let (icp_ledger_id, asset_id) = _setup_icp_asset(&pic, vault_id, vec![(user, initial_balance)]);
+
+    let deposit_amount = 1_000_000u128;
+    _approve_spending(
+        &pic,
+        icp_ledger_id,
+        deposit_amount + ICP_FEE,
+        user,
+        vault_id,
+    );
+
+    let tx_result = _fund_asset_account(&pic, vault_id, asset_id, deposit_amount, user);
+    assert!(tx_result.is_ok_and(|amount| amount == deposit_amount));
+
+    assert_eq!(
+        _get_asset_margin_balance(&pic, vault_id, asset_id, user),
+        deposit_amount
+    );
+
+    // the approval and the transfer are both charged a fee
+    let user_balance = _icrc1_balance_of(
+        &pic,
+        icp_ledger_id,
+        Account {
+            owner: user,
+            subaccount: None,
+        },
+        user,
+    );
+    assert_eq!(
+        user_balance,
+        Nat::from(initial_balance as u128 - deposit_amount - 2 * ICP_FEE)
+    );
+
+    let vault_balance = _icrc1_balance_of(
+        &pic,
+        icp_ledger_id,
+        Account {
+            owner: vault_id,
+            subaccount: None,
+        },
+        user,
+    );
+    assert_eq!(vault_balance, Nat::from(deposit_amount));
+}
+
+#[test]
+fn test_that_icp_deposit_without_approval_fails() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let (_, asset_id) = _setup_icp_asset(&pic, vault_id, vec![(user, 10_000_000)]);
+
+    let tx_result = _fund_asset_account(&pic, vault_id, asset_id, 1_000_000, user);
+
+    assert!(tx_result.is_err());
+    assert_eq!(_get_asset_margin_balance(&pic, vault_id, asset_id, user), 0);
+}
+
+#[test]
+fn test_that_icp_is_withdrawn_with_icrc1_transfer() {
+    let user = _get_principals()[1];
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    // the vault holds some ICP to pay the fees of outgoing transfers
+    let (icp_ledger_id, asset_id) = _setup_icp_asset(
+        &pic,
+        vault_id,
+        vec![(user, 10_000_000), (vault_id, 100_000)],
+    );
+
+    let deposit_amount = 1_000_000u128;
+    _approve_spending(
+        &pic,
+        icp_ledger_id,
+        deposit_amount + ICP_FEE,
+        user,
+        vault_id,
+    );
+    let _ = _fund_asset_account(&pic, vault_id, asset_id, deposit_amount, user);
+
+    let tx_result = _withdraw_asset_from_account(&pic, vault_id, asset_id, deposit_amount, user);
+    assert!(tx_result.is_ok());
+
+    assert_eq!(_get_asset_margin_balance(&pic, vault_id, asset_id, user), 0);
+
+    // the fees of the withdrawal are paid by the vault
+    let user_balance = _icrc1_balance_of(
+        &pic,
+        icp_ledger_id,
+        Account {
+            owner: user,
+            subaccount: None,
+        },
+        user,
+    );
+    assert_eq!(user_balance, Nat::from(10_000_000u128 - 2 * ICP_FEE));
+
+    let tx_result = _withdraw_to_account_identifier(
+        &pic,
+        vault_id,
+        asset_id,
+        1,
+        user,
+        AccountIdentifier::new(&receiver, &DEFAULT_SUBACCOUNT),
+    );
+    assert!(tx_result.is_err_and(|err| err == "Insufficient margin balance"));
+}
+
+#[test]
+fn test_that_icp_is_withdrawn_to_account_identifier() {
+    let user = _get_principals()[1];
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let (icp_ledger_id, asset_id) = _setup_icp_asset(
+        &pic,
+        vault_id,
+        vec![(user, 10_000_000), (vault_id, 100_000)],
+    );
+
+    let deposit_amount = 1_000_000u128;
+    _approve_spending(
+        &pic,
+        icp_ledger_id,
+        deposit_amount + ICP_FEE,
+        user,
+        vault_id,
+    );
+    let _ = _fund_asset_account(&pic, vault_id, asset_id, deposit_amount, user);
+
+    let receiver_account_identifier = AccountIdentifier::new(&receiver, &DEFAULT_SUBACCOUNT);
+
+    let tx_result = _withdraw_to_account_identifier(
+        &pic,
+        vault_id,
+        asset_id,
+        deposit_amount,
+        user,
+        receiver_account_identifier,
+    );
+    assert!(tx_result.is_ok_and(|amount| amount == deposit_amount));
+
+    assert_eq!(_get_asset_margin_balance(&pic, vault_id, asset_id, user), 0);
+    assert_eq!(
+        _icp_account_balance(&pic, icp_ledger_id, receiver_account_identifier),
+        deposit_amount
+    );
+}
+
+#[test]
+fn test_that_non_icp_asset_can_not_be_withdrawn_to_account_identifier() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, user, token_id);
+
+    let tx_result = _withdraw_to_account_identifier(
+        &pic,
+        vault_id,
+        INITIAL_ASSET_ID,
+        1000,
+        user,
+        AccountIdentifier::new(&user, &DEFAULT_SUBACCOUNT),
+    );
+
+    assert!(tx_result.is_err_and(|err| err == "Asset is not ICP"));
+}
+
+#[test]
+fn test_that_icp_transfer_is_claimed_as_deposit() {
+    let user = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let (icp_ledger_id, asset_id) = _setup_icp_asset(&pic, vault_id, vec![(user, 10_000_000)]);
+
+    let deposit_amount = 1_000_000u128;
+    let block_index = _transfer_to_vault(&pic, vault_id, icp_ledger_id, deposit_amount, user);
+
+    let tx_result = _claim_deposit(&pic, vault_id, asset_id, block_index, user);
+    assert!(tx_result.is_ok_and(|amount| amount == deposit_amount));
+
+    assert_eq!(
+        _get_asset_margin_balance(&pic, vault_id, asset_id, user),
+        deposit_amount
+    );
+    assert_eq!(
+        _get_deposit_claimant(&pic, vault_id, asset_id, block_index),
+        Some(user)
+    );
+}
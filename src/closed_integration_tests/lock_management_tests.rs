@@ -0,0 +1,156 @@
+use super::*;
+
+/// Sets up a vault with a funded user holding a single lock of `amount` in `span`
+///
+/// Returns the vault's ids,the user and the lock's timestamp
+fn _setup_vault_with_lock(
+    pic: &PocketIc,
+    amount: Amount,
+    span: SpanId,
+) -> (Principal, Principal, Principal, Time) {
+    let caller = _get_principals()[1];
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(pic, 0);
+
+    _mint_approve_and_fund_account(pic, vault_id, caller, token_id);
+
+    // lend twice the amount so the user holds extra virtual tokens
+    let _ = _provide_leverage(pic, vault_id, 2 * amount, caller);
+
+    _approve_spending(pic, vtoken_id, 2 * amount, caller, vault_id);
+
+    let _ = _stake(pic, caller, vault_id, amount, span, None);
+
+    let (lock_timestamp, _, _) = _get_user_stakes(pic, vault_id, caller)[1];
+
+    (vtoken_id, vault_id, caller, lock_timestamp)
+}
+
+#[test]
+fn test_that_extending_lock_to_shorter_span_fails() {
+    let pic = PocketIc::new();
+
+    let (_, vault_id, caller, lock_timestamp) =
+        _setup_vault_with_lock(&pic, 1000000u128, MONTH6_SPAN_ID);
+
+    let tx_result = _extend_lock(&pic, vault_id, caller, lock_timestamp, MONTH2_SPAN_ID);
+
+    assert!(tx_result.is_err_and(|err| { err == "Can not move lock to a shorter span" }));
+}
+
+#[test]
+fn test_that_extending_lock_moves_amount_to_new_span() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) =
+        _setup_vault_with_lock(&pic, amount, MONTH2_SPAN_ID);
+
+    let (_, lock_before, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    let tx_result = _extend_lock(&pic, vault_id, caller, lock_timestamp, YEAR_SPAN_ID);
+    assert!(tx_result.is_ok());
+
+    let (timestamp, lock_after, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    assert_eq!(timestamp, lock_timestamp);
+    assert_eq!(lock_after.span_id, YEAR_SPAN_ID);
+    assert_eq!(lock_after.amount, amount);
+    assert!(lock_after.expiry_time > lock_before.expiry_time);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&MONTH2_SPAN_ID].total_locked, 0);
+    assert_eq!(vault.spans[&YEAR_SPAN_ID].total_locked, amount);
+}
+
+#[test]
+fn test_that_increasing_lock_adds_to_amount() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) =
+        _setup_vault_with_lock(&pic, amount, MONTH6_SPAN_ID);
+
+    let tx_result = _increase_lock(&pic, vault_id, caller, lock_timestamp, amount);
+    assert!(tx_result.is_ok_and(|new_amount| new_amount == 2 * amount));
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.spans[&MONTH6_SPAN_ID].total_locked, 2 * amount);
+}
+
+#[test]
+fn test_that_partial_unlock_fails_before_expiry() {
+    let pic = PocketIc::new();
+
+    let (_, vault_id, caller, lock_timestamp) =
+        _setup_vault_with_lock(&pic, 1000000u128, MONTH2_SPAN_ID);
+
+    let tx_result = _partial_unlock(&pic, vault_id, caller, lock_timestamp, 1000u128);
+
+    assert!(tx_result.is_err_and(|err| { err == "Expiry time in the future" }));
+}
+
+#[test]
+fn test_that_partial_unlock_returns_part_of_expired_lock() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) =
+        _setup_vault_with_lock(&pic, amount, MONTH2_SPAN_ID);
+
+    pic.advance_time(std::time::Duration::from_secs(61 * 24 * 60 * 60));
+
+    let tx_result = _partial_unlock(&pic, vault_id, caller, lock_timestamp, amount / 4);
+    assert!(tx_result.is_ok_and(|returned| returned >= amount / 4));
+
+    let (_, lock, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert_eq!(lock.amount, amount - amount / 4);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(
+        vault.spans[&MONTH2_SPAN_ID].total_locked,
+        amount - amount / 4
+    );
+}
+
+#[test]
+fn test_that_split_locks_can_be_merged_back() {
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (_, vault_id, caller, lock_timestamp) = _setup_vault_with_lock(&pic, amount, YEAR_SPAN_ID);
+
+    let tx_result = _split_lock(
+        &pic,
+        vault_id,
+        caller,
+        lock_timestamp,
+        vec![amount / 4, amount / 4],
+    );
+    assert!(tx_result.is_ok());
+
+    let new_timestamps = tx_result.unwrap();
+    assert_eq!(new_timestamps.len(), 2);
+
+    // the instant lock and the three year locks
+    let user_locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(user_locks.len(), 4);
+    assert!(user_locks
+        .iter()
+        .skip(1)
+        .all(|(_, lock, _)| lock.span_id == YEAR_SPAN_ID));
+
+    let mut lock_timestamps = vec![lock_timestamp];
+    lock_timestamps.extend(new_timestamps);
+
+    let tx_result = _merge_locks(&pic, vault_id, caller, lock_timestamps);
+    assert!(tx_result.is_ok_and(|merged_amount| merged_amount == amount));
+
+    let user_locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(user_locks.len(), 2);
+    assert_eq!(user_locks[1].0, lock_timestamp);
+}
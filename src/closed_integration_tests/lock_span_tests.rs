@@ -0,0 +1,90 @@
+use super::*;
+
+#[test]
+fn test_that_default_lock_spans_are_set_on_init() {
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let lock_spans = _get_lock_spans(&pic, vault_id);
+
+    let span_ids: Vec<SpanId> = lock_spans.iter().map(|(span_id, _)| *span_id).collect();
+    assert_eq!(span_ids, vec![MONTH2_SPAN_ID, MONTH6_SPAN_ID, YEAR_SPAN_ID]);
+
+    let weights: Vec<u64> = lock_spans.iter().map(|(_, config)| config.weight).collect();
+    assert_eq!(weights, vec![2, 6, 12]);
+}
+
+#[test]
+fn test_that_only_admin_can_set_lock_spans() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let config = LockSpanConfig {
+        duration: 1_000_000_000,
+        weight: 1,
+        enabled: true,
+        max_capacity: None,
+    };
+
+    let tx_result = _set_lock_span(&pic, vault_id, caller, 1, config);
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can set lock spans" }));
+
+    let tx_result = _set_lock_span(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        INSTANT_SPAN_ID,
+        config,
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Span id is reserved for the instant span" }));
+}
+
+#[test]
+fn test_that_locking_respects_span_config() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let amount_utilised = 1000000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount_utilised, caller);
+
+    _approve_spending(&pic, vtoken_id, amount_utilised, caller, vault_id);
+
+    let span_id = 3;
+    let mut config = LockSpanConfig {
+        duration: 1_000_000_000,
+        weight: 3,
+        enabled: false,
+        max_capacity: Some(amount_utilised / 2),
+    };
+
+    let _ = _set_lock_span(&pic, vault_id, Principal::anonymous(), span_id, config);
+
+    let tx_result = _stake(&pic, caller, vault_id, amount_utilised, span_id, None);
+    assert!(tx_result.is_err_and(|err| { err == "Lock span is disabled" }));
+
+    config.enabled = true;
+    let _ = _set_lock_span(&pic, vault_id, Principal::anonymous(), span_id, config);
+
+    let tx_result = _stake(&pic, caller, vault_id, amount_utilised, span_id, None);
+    assert!(tx_result.is_err_and(|err| { err == "Lock span capacity exceeded" }));
+
+    let tx_result = _stake(&pic, caller, vault_id, amount_utilised / 2, span_id, None);
+    assert!(tx_result.is_ok());
+
+    let (lock_timestamp, lock, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert_eq!(lock.span_id, span_id);
+
+    // the lock is already counted in its span,so extending it there needs no extra capacity
+    let tx_result = _extend_lock(&pic, vault_id, caller, lock_timestamp, span_id);
+    assert!(tx_result.is_ok());
+}
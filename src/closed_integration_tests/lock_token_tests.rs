@@ -0,0 +1,142 @@
+use super::*;
+
+use crate::core_lib::icrc7::TransferError as LockTransferError;
+
+/// Sets up a vault with a funded user holding a single lock of `amount` in `span`
+///
+/// Returns the vault's id,the user and the lock's token id
+fn _setup_vault_with_lock_token(
+    pic: &PocketIc,
+    amount: Amount,
+    span: SpanId,
+) -> (Principal, Principal, Nat) {
+    let caller = _get_principals()[1];
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(pic, 0);
+
+    _mint_approve_and_fund_account(pic, vault_id, caller, token_id);
+
+    let _ = _provide_leverage(pic, vault_id, amount, caller);
+
+    _approve_spending(pic, vtoken_id, amount, caller, vault_id);
+
+    let _ = _stake(pic, caller, vault_id, amount, span, None);
+
+    let lock_tokens = _icrc7_tokens_of(pic, vault_id, caller);
+    assert_eq!(lock_tokens.len(), 1);
+
+    (vault_id, caller, lock_tokens[0].clone())
+}
+
+#[test]
+fn test_that_locks_are_issued_tokens() {
+    let pic = PocketIc::new();
+
+    let (vault_id, caller, lock_token_id) =
+        _setup_vault_with_lock_token(&pic, 1000000u128, MONTH2_SPAN_ID);
+
+    let (_, instant_lock, _) = _get_user_stakes(&pic, vault_id, caller)[0];
+    assert!(instant_lock.token_id.is_none());
+
+    let (_, lock, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+    assert_eq!(lock.token_id.map(Nat::from), Some(lock_token_id.clone()));
+
+    let owner = _icrc7_owner_of(&pic, vault_id, lock_token_id);
+    assert_eq!(owner.map(|account| account.owner), Some(caller));
+}
+
+#[test]
+fn test_that_only_token_owner_can_transfer_lock() {
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (vault_id, _, lock_token_id) =
+        _setup_vault_with_lock_token(&pic, 1000000u128, MONTH2_SPAN_ID);
+
+    let tx_result = _icrc7_transfer(&pic, vault_id, receiver, lock_token_id, receiver);
+    assert_eq!(tx_result, Err(LockTransferError::Unauthorized));
+}
+
+#[test]
+fn test_that_transferred_lock_is_unlocked_by_new_owner() {
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (vault_id, caller, lock_token_id) =
+        _setup_vault_with_lock_token(&pic, amount, MONTH2_SPAN_ID);
+
+    let (lock_timestamp, _, _) = _get_user_stakes(&pic, vault_id, caller)[1];
+
+    let tx_result = _icrc7_transfer(&pic, vault_id, caller, lock_token_id.clone(), receiver);
+    assert!(tx_result.is_ok());
+
+    // only the instant lock is left with the previous owner
+    assert_eq!(_get_user_stakes(&pic, vault_id, caller).len(), 1);
+    assert!(_icrc7_tokens_of(&pic, vault_id, caller).is_empty());
+
+    let owner = _icrc7_owner_of(&pic, vault_id, lock_token_id.clone());
+    assert_eq!(owner.map(|account| account.owner), Some(receiver));
+
+    let receiver_locks = _get_user_stakes(&pic, vault_id, receiver);
+    assert_eq!(receiver_locks.len(), 1);
+
+    let (receiver_lock_timestamp, receiver_lock, _) = receiver_locks[0];
+    assert_eq!(receiver_lock.amount, amount);
+
+    pic.advance_time(std::time::Duration::from_secs(61 * 24 * 60 * 60));
+
+    let tx_result = _unlock(&pic, vault_id, caller, lock_timestamp);
+    assert!(tx_result.is_err_and(|err| { err == "Lock not found" }));
+
+    let tx_result = _unlock(&pic, vault_id, receiver, receiver_lock_timestamp);
+    assert!(tx_result.is_ok_and(|amount_returned| amount_returned >= amount));
+
+    // the lock's token is burnt with it
+    assert!(_icrc7_owner_of(&pic, vault_id, lock_token_id).is_none());
+}
+
+#[test]
+fn test_that_lock_transfers_are_rejected_while_transfers_are_paused() {
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (vault_id, caller, lock_token_id) =
+        _setup_vault_with_lock_token(&pic, 1000000u128, MONTH2_SPAN_ID);
+
+    _pause(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        OperationFamily::Transfers,
+        "Incident",
+    )
+    .unwrap();
+
+    let arg = crate::core_lib::icrc7::TransferArg {
+        from_subaccount: None,
+        to: Account {
+            owner: receiver,
+            subaccount: None,
+        },
+        token_id: lock_token_id.clone(),
+        memo: None,
+        created_at_time: None,
+    };
+    let Ok(WasmResult::Reject(message)) = pic.update_call(
+        vault_id,
+        caller,
+        "icrc7_transfer",
+        encode_one(vec![arg]).unwrap(),
+    ) else {
+        panic!("Paused transfer was not rejected")
+    };
+    assert!(message.contains("Operation is paused: Incident"));
+
+    let owner = _icrc7_owner_of(&pic, vault_id, lock_token_id);
+    assert_eq!(owner.map(|account| account.owner), Some(caller));
+}
@@ -0,0 +1,253 @@
+use super::*;
+
+fn _liquidity_change_validity_check(
+    pic: &PocketIc,
+    vault_id: Principal,
+    market: Principal,
+    user: Principal,
+    debt: Amount,
+) -> WasmResult {
+    pic.update_call(
+        vault_id,
+        market,
+        "liquidityChangeValidityCheck",
+        candid::encode_args((INITIAL_ASSET_ID, user, 0u128, debt)).unwrap(),
+    )
+    .unwrap()
+}
+
+fn _repay_debt(
+    pic: &PocketIc,
+    vault_id: Principal,
+    market: Principal,
+    user: Principal,
+    debt: Amount,
+    fees: Amount,
+) {
+    let params = ManageDebtParams {
+        initial_debt: debt,
+        net_debt: 0,
+        amount_repaid: debt + fees,
+    };
+
+    let Ok(WasmResult::Reply(_)) = pic.update_call(
+        vault_id,
+        market,
+        "managePositionUpdate",
+        candid::encode_args((INITIAL_ASSET_ID, user, 0u128, params)).unwrap(),
+    ) else {
+        panic!("Manage position update failed")
+    };
+}
+
+#[test]
+fn test_that_approved_markets_are_listed() {
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    _approve_market(&pic, vault_id, market);
+
+    let markets = _list_markets(&pic, vault_id);
+    assert_eq!(markets.len(), 1);
+
+    let (listed_market, details) = &markets[0];
+    assert_eq!(*listed_market, market);
+    assert_eq!(details.name, "Market".to_string());
+    assert_eq!(details.status, MarketStatus::Active);
+    assert_eq!(details.approved_by, Principal::anonymous());
+    assert_eq!(details.added_at, _get_current_time(&pic));
+
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        Principal::anonymous(),
+        "approveMarket",
+        candid::encode_args((market, "Market".to_string())).unwrap(),
+    ) else {
+        panic!("Approve market failed")
+    };
+    let tx_result: Result<(), String> = decode_one(&val).unwrap();
+    assert!(tx_result.is_err_and(|err| { err == "Market already approved" }));
+}
+
+#[test]
+fn test_that_suspended_markets_can_only_repay_debt() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let amount = 1000000u128;
+    let debt = 100000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, amount, caller);
+
+    let WasmResult::Reply(val) =
+        _liquidity_change_validity_check(&pic, vault_id, market, caller, debt)
+    else {
+        panic!("Liquidity change validity check failed")
+    };
+    let (valid, _): (bool, u32) = candid::decode_args(&val).unwrap();
+    assert!(valid);
+
+    let tx_result = _set_market_state(&pic, vault_id, caller, "suspendMarket", market);
+    assert!(tx_result.is_err_and(|err| { err == "Only market manager can suspend markets" }));
+
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "suspendMarket",
+        market,
+    )
+    .unwrap();
+    assert_eq!(
+        _list_markets(&pic, vault_id)[0].1.status,
+        MarketStatus::Suspended
+    );
+
+    let WasmResult::Reply(val) =
+        _liquidity_change_validity_check(&pic, vault_id, market, caller, debt)
+    else {
+        panic!("Liquidity change validity check failed")
+    };
+    let (valid, _): (bool, u32) = candid::decode_args(&val).unwrap();
+    assert!(!valid);
+
+    _repay_debt(&pic, vault_id, market, caller, debt, 1000);
+
+    let vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(vault.debt, 0);
+    assert!(vault.free_liquidity > amount);
+
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "resumeMarket",
+        market,
+    )
+    .unwrap();
+
+    let WasmResult::Reply(val) =
+        _liquidity_change_validity_check(&pic, vault_id, market, caller, debt)
+    else {
+        panic!("Liquidity change validity check failed")
+    };
+    let (valid, _): (bool, u32) = candid::decode_args(&val).unwrap();
+    assert!(valid);
+}
+
+#[test]
+fn test_that_revoked_markets_can_not_call_the_vault() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    _approve_market(&pic, vault_id, market);
+
+    let tx_result = _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "revokeMarket",
+        market,
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Market must be suspended before it is revoked" }));
+
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "suspendMarket",
+        market,
+    )
+    .unwrap();
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "revokeMarket",
+        market,
+    )
+    .unwrap();
+    assert!(_list_markets(&pic, vault_id).is_empty());
+
+    let WasmResult::Reject(message) =
+        _liquidity_change_validity_check(&pic, vault_id, market, caller, 0)
+    else {
+        panic!("Revoked market was not rejected")
+    };
+    assert!(message.contains("Caller not an approved market"));
+
+    let tx_result = _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "revokeMarket",
+        market,
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Market not found" }));
+}
+
+#[test]
+fn test_that_markets_with_debt_can_not_be_revoked() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let debt = 100000u128;
+
+    let _ = _provide_leverage(&pic, vault_id, 1000000u128, caller);
+    let _ = _liquidity_change_validity_check(&pic, vault_id, market, caller, debt);
+
+    let (_, details) = &_list_markets(&pic, vault_id)[0];
+    assert_eq!(details.debts.get(&INITIAL_ASSET_ID), Some(&debt));
+
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "suspendMarket",
+        market,
+    )
+    .unwrap();
+
+    let tx_result = _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "revokeMarket",
+        market,
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Market still has outstanding debt" }));
+
+    _repay_debt(&pic, vault_id, market, caller, debt, 1000);
+    assert!(_list_markets(&pic, vault_id)[0].1.debts.is_empty());
+
+    _set_market_state(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "revokeMarket",
+        market,
+    )
+    .unwrap();
+    assert!(_list_markets(&pic, vault_id).is_empty());
+}
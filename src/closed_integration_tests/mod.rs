@@ -1,5 +1,6 @@
 use candid::{decode_one, encode_one, CandidType, Nat, Principal};
 
+use ic_ledger_types::{AccountBalanceArgs, Tokens, DEFAULT_SUBACCOUNT};
 use icrc_ledger_types::{
     icrc1::transfer::{TransferArg, TransferError},
     icrc2::approve::{ApproveArgs, ApproveError},
@@ -9,19 +10,68 @@ use std::fs;
 
 use super::*;
 
-use crate::types::LiquidityManagerDetails;
+use crate::types::{
+    AdminAction, GovernanceMode, GovernanceSettings, LiquidityManagerDetails, MarketDetails,
+    MarketStatus, MetadataStatus, OperationFamily, PauseDetails, PendingAdminAction, Role,
+    RoleAssignments,
+};
 
 use crate::core_lib::asset::{Asset, AssetType};
 
 const TOKEN_WASM: &str = "target/wasm32-unknown-unknown/release/token.wasm";
 const VAULT_WASM: &str = "target/wasm32-unknown-unknown/release/liquidity_manager.wasm";
+const ICP_LEDGER_WASM: &str = "target/wasm32-unknown-unknown/release/icp_ledger.wasm";
+// vault built from the first release of the canister,used to test upgrades of deployed vaults
+const BASELINE_VAULT_WASM: &str =
+    "target/wasm32-unknown-unknown/release/liquidity_manager_baseline.wasm";
+
+// fee of the ICP ledger installed in tests,in e8s
+const ICP_FEE: u128 = 10_000;
+
+// ids of the default lock spans
+const MONTH2_SPAN_ID: SpanId = 2;
+const MONTH6_SPAN_ID: SpanId = 6;
+const YEAR_SPAN_ID: SpanId = 12;
+
+// window over which fees are credited to the spans by default
+const FEE_STREAM_WINDOW: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
 
+pub mod asset_tests;
+pub mod beneficiary_tests;
+pub mod claim_deposit_tests;
+pub mod compounding_tests;
 pub mod deposit_test;
+pub mod early_unlock_tests;
+pub mod expiry_action_tests;
+pub mod fee_stream_tests;
+pub mod governance_tests;
+pub mod icp_tests;
+pub mod lock_management_tests;
+pub mod lock_span_tests;
+pub mod lock_token_tests;
+pub mod market_tests;
+pub mod pause_tests;
+pub mod qtoken_tests;
+pub mod roles_tests;
 pub mod staking;
 pub mod test_providing_leverage;
+pub mod timelock_tests;
+pub mod upgrade_tests;
+pub mod vesting_tests;
+pub mod voting_power_tests;
 pub mod withdrawal_tests;
+pub mod yield_tests;
 
 pub fn _setup_vault(init_pic: &PocketIc, min_amount: u128) -> (Principal, Principal, Principal) {
+    _setup_vault_with_admin(init_pic, min_amount, Principal::anonymous())
+}
+
+/// Sets up a vault installed by `admin`,who becomes the vault's admin
+pub fn _setup_vault_with_admin(
+    init_pic: &PocketIc,
+    min_amount: u128,
+    admin: Principal,
+) -> (Principal, Principal, Principal) {
     // Create new PocketIC instance
     let pic = init_pic;
 
@@ -71,18 +121,70 @@ pub fn _setup_vault(init_pic: &PocketIc, min_amount: u128) -> (Principal, Princi
         },
 
         min_amount,
+        metadata: None,
     };
     // let vault_wasm = fs::read(VAULT_WASM).expect("Wasm file not found, run 'dfx build'.");
     pic.install_canister(
         vault_id,
         vault_wasm,
         encode_one(vauilt_args).unwrap(),
-        Some(Principal::anonymous()),
+        Some(admin),
     );
 
+    // lending is rejected until the initial asset's metadata is fetched after init
+    for _ in 0..5 {
+        pic.tick();
+    }
+
     (token_id, vtoken_id, vault_id)
 }
 
+/// Sets up a vault whose QTokens are held in its built-in QToken ledger
+///
+/// Returns the ids of the asset's ledger and of the vault
+pub fn _setup_qtoken_vault(pic: &PocketIc) -> (Principal, Principal) {
+    let token_id = pic.create_canister();
+    pic.add_cycles(token_id, 2_000_000_000_000); // 2T Cycles
+
+    let token_wasm = fs::read(TOKEN_WASM).expect("Wasm file not found, run 'dfx build'.");
+    pic.install_canister(
+        token_id,
+        token_wasm,
+        encode_one(LedgerArg::Init(create_args(Principal::anonymous()))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let vault_id = pic.create_canister();
+    pic.add_cycles(vault_id, 2_000_000_000_000); // 2T Cycles
+
+    let vault_args = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: token_id,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::QToken,
+            ledger_id: vault_id,
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+    let vault_wasm = fs::read(VAULT_WASM).expect("Wasm file not found, run 'dfx build'.");
+    pic.install_canister(
+        vault_id,
+        vault_wasm,
+        encode_one(vault_args).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    // lending is rejected until the initial asset's metadata is fetched after init
+    for _ in 0..5 {
+        pic.tick();
+    }
+
+    (token_id, vault_id)
+}
+
 //////////////////////////////////////////////////////////////////
 ///
 
@@ -91,7 +193,7 @@ pub fn _get_user_margin_balance(pic: &PocketIc, vault_id: Principal, user: Princ
         vault_id,
         Principal::anonymous(),
         "getUserMarginBalance",
-        encode_one(user).unwrap(),
+        candid::encode_args((INITIAL_ASSET_ID, user)).unwrap(),
     ) else {
         panic!("Could not get user margin balance")
     };
@@ -106,9 +208,12 @@ pub fn _provide_leverage(
     amount: Amount,
     caller: Principal,
 ) -> Result<bool, String> {
-    let Ok(WasmResult::Reply(val)) =
-        pic.update_call(vault_id, caller, "lendToVault", encode_one(amount).unwrap())
-    else {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "lendToVault",
+        candid::encode_args((INITIAL_ASSET_ID, amount, None::<Account>)).unwrap(),
+    ) else {
         panic!("Could not lend to vault")
     };
 
@@ -189,12 +294,12 @@ pub fn _get_vault(pic: &PocketIc, vault_id: Principal, caller: Principal) -> Vau
         vault_id,
         caller,
         "getVault",
-        candid::encode_args(()).unwrap(),
+        encode_one(INITIAL_ASSET_ID).unwrap(),
     ) {
         Ok(reply) => {
             if let WasmResult::Reply(val) = reply {
-                let response = candid::decode_one(&val).unwrap();
-                return response;
+                let response: Option<Vault> = candid::decode_one(&val).unwrap();
+                response.unwrap()
             } else {
                 panic!("error occurred in canister");
             }
@@ -237,14 +342,21 @@ pub fn _stake(
     caller: Principal,
     vault_id: Principal,
     amount: Amount,
-    span: LockSpan,
+    span: SpanId,
     from_subaccount: Option<Subaccount>,
 ) -> Result<Amount, String> {
     let Ok(WasmResult::Reply(val)) = pic.update_call(
         vault_id,
         caller,
         "lockQTokens",
-        candid::encode_args((amount, span, from_subaccount)).unwrap(),
+        candid::encode_args((
+            INITIAL_ASSET_ID,
+            amount,
+            span,
+            from_subaccount,
+            None::<Account>,
+        ))
+        .unwrap(),
     ) else {
         panic!("create lock failed")
     };
@@ -271,7 +383,7 @@ pub fn _fund_account(
         vault_id,
         sender,
         "fundAccount",
-        candid::encode_args((amount, from_subaccount, receiver)).unwrap(),
+        candid::encode_args((INITIAL_ASSET_ID, amount, from_subaccount, receiver)).unwrap(),
     ) else {
         panic!("Fund account failed")
     };
@@ -298,6 +410,7 @@ pub fn _withdraw_from_account(
         receiver,
         "withdrawFromAccount",
         candid::encode_args((
+            INITIAL_ASSET_ID,
             amount,
             Account {
                 owner: receiver,
@@ -320,149 +433,1320 @@ pub fn _withdraw_from_account(
     }
 }
 
-////////////////////////////////////////////////////////////////////////////////////////////////
-pub fn _approve_spending(
+pub fn _approve_market(pic: &PocketIc, vault_id: Principal, market: Principal) {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        Principal::anonymous(),
+        "approveMarket",
+        candid::encode_args((market, "Market".to_string())).unwrap(),
+    ) else {
+        panic!("Approve market failed")
+    };
+
+    let reply: Result<(), String> = decode_one(&val).unwrap();
+    assert!(reply.is_ok());
+}
+
+pub fn _set_market_state(
     pic: &PocketIc,
-    token_id: Principal,
-    amount: Amount,
+    vault_id: Principal,
     caller: Principal,
-    spender_id: Principal,
-) {
-    let args = ApproveArgs {
-        from_subaccount: None,
-        created_at_time: None,
-        expected_allowance: None,
-        fee: None,
-        memo: None,
-        spender: Account {
-            owner: spender_id,
-            subaccount: None,
-        },
-        amount: Nat::from(amount),
-        expires_at: None,
+    method: &str,
+    market: Principal,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) =
+        pic.update_call(vault_id, caller, method, encode_one(market).unwrap())
+    else {
+        panic!("{} failed", method)
     };
-    let _ = _icrc2_approve(pic, token_id, args, caller);
+
+    decode_one(&val).unwrap()
 }
-///
-///
-///
-pub fn _mint_approve_and_fund_account(
+
+pub fn _list_markets(pic: &PocketIc, vault_id: Principal) -> Vec<(Principal, MarketDetails)> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "listMarkets",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("List markets failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Simulates a market opening a position with `debt` as leverage and closing it
+/// with `fees` paid on top of the debt
+pub fn _simulate_position_fees(
     pic: &PocketIc,
     vault_id: Principal,
-    to_principal: Principal,
-    token_id: Principal,
+    market: Principal,
+    user: Principal,
+    debt: Amount,
+    fees: Amount,
 ) {
-    let account0 = to_principal;
-
-    let args = TransferArg {
-        from_subaccount: None,
-        created_at_time: None,
-        to: Account {
-            owner: to_principal,
-            subaccount: None,
-        },
-        amount: Nat::from(1000000000000000000u128),
-        fee: None,
-        memo: None,
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        market,
+        "liquidityChangeValidityCheck",
+        candid::encode_args((INITIAL_ASSET_ID, user, 0u128, debt)).unwrap(),
+    ) else {
+        panic!("Liquidity change validity check failed")
     };
 
-    let _ = _icrc1_transfer(&pic, token_id, args, Principal::anonymous());
+    let (valid, _): (bool, u32) = candid::decode_args(&val).unwrap();
+    assert!(valid);
 
-    let deposit_ampount = 10000000000u128;
+    let params = ManageDebtParams {
+        initial_debt: debt,
+        net_debt: 0,
+        amount_repaid: debt + fees,
+    };
 
-    _approve_spending(pic, token_id, deposit_ampount, account0, vault_id);
+    let Ok(WasmResult::Reply(_)) = pic.update_call(
+        vault_id,
+        market,
+        "managePositionUpdate",
+        candid::encode_args((INITIAL_ASSET_ID, user, 0u128, params)).unwrap(),
+    ) else {
+        panic!("Manage position update failed")
+    };
+}
 
-    let tx_result = _fund_account(&pic, vault_id, deposit_ampount, None, account0, account0);
+pub fn _set_lock_compounding(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    compounding: bool,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setLockCompounding",
+        candid::encode_args((lock_timestamp, compounding)).unwrap(),
+    ) else {
+        panic!("Set lock compounding failed")
+    };
 
-    assert!(tx_result.is_ok());
+    decode_one(&val).unwrap()
+}
 
-    let balance = _get_user_margin_balance(&pic, vault_id, account0);
+pub fn _set_early_unlock_penalty(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    span: SpanId,
+    penalty: u64,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setEarlyUnlockPenalty",
+        candid::encode_args((INITIAL_ASSET_ID, span, penalty)).unwrap(),
+    ) else {
+        panic!("Set early unlock penalty failed")
+    };
 
-    assert_eq!(balance, deposit_ampount);
+    decode_one(&val).unwrap()
 }
 
-pub fn _get_principals() -> Vec<Principal> {
-    return vec![
-        Principal::from_text("hpp6o-wqx72-gol5b-3bmzw-lyryb-62yoi-pjoll-mtsh7-swdzi-jkf2v-rqe")
-            .unwrap(),
-        Principal::from_text("cvwul-djb3r-e6krd-nbnfl-tuhox-n4omu-kejey-3lku7-ae3bx-icbu7-yae")
-            .unwrap(),
-        Principal::from_text("az6yt-a3f5b-k342j-5jncd-csa66-xfgvb-6f52c-jw5nh-o7g4k-bbf4q-vqe")
-            .unwrap(),
-        Principal::from_text("kmgqw-63fcp-ugexu-qgkyy-p3vjk-e4jh5-kyrdx-skajz-o3qra-wcyrb-qae")
-            .unwrap(),
-    ];
-}
+pub fn _preview_early_unlock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    user: Principal,
+    lock_timestamp: Time,
+) -> Result<EarlyUnlockPreview, String> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        user,
+        "previewEarlyUnlock",
+        candid::encode_args((user, lock_timestamp)).unwrap(),
+    ) else {
+        panic!("Preview early unlock failed")
+    };
 
-#[derive(CandidType)]
-pub enum LedgerArg {
-    Init(InitArgs),
-    Upgrade(UpgradeArgs),
+    decode_one(&val).unwrap()
 }
 
-pub fn create_args(minting_id: Principal) -> InitArgs {
-    InitArgs {
-        decimals: Some(8),
-        token_symbol: "ICP".to_string(),
-        transfer_fee: 0,
-        metadata: vec![],
-        minting_account: Account {
-            owner: minting_id,
-            subaccount: None,
-        },
-        initial_balances: vec![],
-        maximum_number_of_accounts: None,
-        accounts_overflow_trim_quantity: None,
-        fee_collector_account: None,
-        archive_options: ArchiveOptions {
-            num_blocks_to_archive: 1000,
-            max_transactions_per_response: None,
-            trigger_threshold: 1000,
-            max_message_size_bytes: None,
-            cycles_for_archive_creation: None,
-            node_max_memory_size_bytes: None,
-            controller_id: Principal::anonymous(),
-        },
-        max_memo_length: None,
-        token_name: "Internet Computer".to_string(),
-        feature_flags: Some(FeatureFlags { icrc2: true }),
-    }
-}
+pub fn _early_unlock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "earlyUnlock",
+        encode_one(lock_timestamp).unwrap(),
+    ) else {
+        panic!("Early unlock failed")
+    };
 
-#[derive(CandidType, Clone)]
-pub struct UpgradeArgs {
-    pub token_symbol: Option<String>,
-    pub transfer_fee: Option<u128>,
-    pub metadata: Option<Vec<(String, MetadataValue)>>,
-    pub maximum_number_of_accounts: Option<u64>,
-    pub accounts_overflow_trim_quantity: Option<u64>,
-    pub change_fee_collector: Option<ChangeFeeCollector>,
-    pub max_memo_length: Option<u16>,
-    pub token_name: Option<String>,
-    pub feature_flags: Option<FeatureFlags>,
+    decode_one(&val).unwrap()
 }
 
-#[derive(Debug, Clone, CandidType)]
-pub enum ChangeFeeCollector {
-    Unset,
-    Set(Account),
+pub fn _extend_lock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    new_span: SpanId,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "extendLock",
+        candid::encode_args((lock_timestamp, new_span)).unwrap(),
+    ) else {
+        panic!("Extend lock failed")
+    };
+
+    decode_one(&val).unwrap()
 }
 
-#[derive(CandidType, Clone)]
-pub struct InitArgs {
-    pub decimals: Option<u8>,
-    pub token_symbol: String,
-    pub transfer_fee: u128,
-    pub metadata: Vec<(String, MetadataValue)>,
-    pub minting_account: Account,
-    pub initial_balances: Vec<(Account, u128)>,
-    pub maximum_number_of_accounts: Option<u64>,
-    pub accounts_overflow_trim_quantity: Option<u64>,
-    pub fee_collector_account: Option<Account>,
-    pub archive_options: ArchiveOptions,
-    pub max_memo_length: Option<u16>,
-    pub token_name: String,
-    pub feature_flags: Option<FeatureFlags>,
+pub fn _increase_lock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    amount: Amount,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "increaseLock",
+        candid::encode_args((lock_timestamp, amount, None::<Subaccount>)).unwrap(),
+    ) else {
+        panic!("Increase lock failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _partial_unlock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    amount: Amount,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "partialUnlock",
+        candid::encode_args((lock_timestamp, amount)).unwrap(),
+    ) else {
+        panic!("Partial unlock failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _split_lock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    amounts: Vec<Amount>,
+) -> Result<Vec<Time>, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "splitLock",
+        candid::encode_args((lock_timestamp, amounts)).unwrap(),
+    ) else {
+        panic!("Split lock failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _merge_locks(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamps: Vec<Time>,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "mergeLocks",
+        encode_one(lock_timestamps).unwrap(),
+    ) else {
+        panic!("Merge locks failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _set_lock_span(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    span_id: SpanId,
+    config: LockSpanConfig,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setLockSpan",
+        candid::encode_args((span_id, config)).unwrap(),
+    ) else {
+        panic!("Set lock span failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_lock_spans(pic: &PocketIc, vault_id: Principal) -> Vec<(SpanId, LockSpanConfig)> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getLockSpans",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get lock spans failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _unlock(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "unlockQTokens",
+        encode_one(lock_timestamp).unwrap(),
+    ) else {
+        panic!("Unlock failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _icrc7_tokens_of(pic: &PocketIc, vault_id: Principal, owner: Principal) -> Vec<Nat> {
+    let account = Account {
+        owner,
+        subaccount: None,
+    };
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "icrc7_tokens_of",
+        candid::encode_args((account, None::<Nat>, None::<Nat>)).unwrap(),
+    ) else {
+        panic!("Get tokens of failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _icrc7_owner_of(pic: &PocketIc, vault_id: Principal, token_id: Nat) -> Option<Account> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "icrc7_owner_of",
+        encode_one(vec![token_id]).unwrap(),
+    ) else {
+        panic!("Get owner of failed")
+    };
+
+    let owners: Vec<Option<Account>> = decode_one(&val).unwrap();
+    owners[0]
+}
+
+pub fn _icrc7_transfer(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    token_id: Nat,
+    to: Principal,
+) -> crate::core_lib::icrc7::TransferResult {
+    let arg = crate::core_lib::icrc7::TransferArg {
+        from_subaccount: None,
+        to: Account {
+            owner: to,
+            subaccount: None,
+        },
+        token_id,
+        memo: None,
+        created_at_time: None,
+    };
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "icrc7_transfer",
+        encode_one(vec![arg]).unwrap(),
+    ) else {
+        panic!("Transfer failed")
+    };
+
+    let results: Vec<Option<crate::core_lib::icrc7::TransferResult>> = decode_one(&val).unwrap();
+    results[0].clone().unwrap()
+}
+
+pub fn _set_fee_stream_window(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    window: Time,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setFeeStreamWindow",
+        candid::encode_args((INITIAL_ASSET_ID, window)).unwrap(),
+    ) else {
+        panic!("Set fee stream window failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _set_lock_expiry_action(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+    expiry_action: ExpiryAction,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setLockExpiryAction",
+        candid::encode_args((lock_timestamp, expiry_action)).unwrap(),
+    ) else {
+        panic!("Set lock expiry action failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_span_yields(pic: &PocketIc, vault_id: Principal) -> Vec<SpanYield> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getSpanYields",
+        encode_one(INITIAL_ASSET_ID).unwrap(),
+    ) else {
+        panic!("Get span yields failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _provide_leverage_for(
+    pic: &PocketIc,
+    vault_id: Principal,
+    amount: Amount,
+    caller: Principal,
+    beneficiary: Account,
+) -> Result<bool, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "lendToVault",
+        candid::encode_args((INITIAL_ASSET_ID, amount, Some(beneficiary))).unwrap(),
+    ) else {
+        panic!("Could not lend to vault")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _stake_for(
+    pic: &PocketIc,
+    caller: Principal,
+    vault_id: Principal,
+    amount: Amount,
+    span: SpanId,
+    beneficiary: Account,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "lockQTokens",
+        candid::encode_args((
+            INITIAL_ASSET_ID,
+            amount,
+            span,
+            None::<Subaccount>,
+            Some(beneficiary),
+        ))
+        .unwrap(),
+    ) else {
+        panic!("create lock failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _create_vesting_locks(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    grants: Vec<VestingGrant>,
+) -> Result<Vec<Time>, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "createVestingLocks",
+        candid::encode_args((INITIAL_ASSET_ID, grants, None::<Subaccount>)).unwrap(),
+    ) else {
+        panic!("Create vesting locks failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _claim_vested(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    lock_timestamp: Time,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "claimVested",
+        encode_one(lock_timestamp).unwrap(),
+    ) else {
+        panic!("Claim vested failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_voting_power(
+    pic: &PocketIc,
+    vault_id: Principal,
+    user: Principal,
+    at_time: Time,
+) -> Amount {
+    let account = Account {
+        owner: user,
+        subaccount: None,
+    };
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getVotingPower",
+        candid::encode_args((INITIAL_ASSET_ID, account, at_time)).unwrap(),
+    ) else {
+        panic!("Get voting power failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_total_voting_power(pic: &PocketIc, vault_id: Principal, at_time: Time) -> Amount {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getTotalVotingPower",
+        candid::encode_args((INITIAL_ASSET_ID, at_time)).unwrap(),
+    ) else {
+        panic!("Get total voting power failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Installs a token and a virtual token minted by the vault and adds them as an asset
+pub fn _setup_asset(
+    pic: &PocketIc,
+    vault_id: Principal,
+    min_amount: u128,
+) -> (Principal, Principal, AssetId) {
+    let token_wasm = fs::read(TOKEN_WASM).expect("Wasm file not found, run 'dfx build'.");
+
+    let token_id = pic.create_canister();
+    pic.add_cycles(token_id, 2_000_000_000_000); // 2T Cycles
+    pic.install_canister(
+        token_id,
+        token_wasm.clone(),
+        encode_one(LedgerArg::Init(create_args(Principal::anonymous()))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let vtoken_id = pic.create_canister();
+    pic.add_cycles(vtoken_id, 2_000_000_000_000); // 2T Cycles
+    pic.install_canister(
+        vtoken_id,
+        token_wasm,
+        encode_one(LedgerArg::Init(create_args(vault_id))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: token_id,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: vtoken_id,
+        },
+        min_amount,
+        metadata: None,
+    };
+    let asset_id = _add_asset(pic, vault_id, Principal::anonymous(), details).unwrap();
+
+    (token_id, vtoken_id, asset_id)
+}
+
+pub fn _add_asset(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    details: LiquidityManagerDetails,
+) -> Result<AssetId, String> {
+    let Ok(WasmResult::Reply(val)) =
+        pic.update_call(vault_id, caller, "addAsset", encode_one(details).unwrap())
+    else {
+        panic!("Add asset failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_asset_metadata_status(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+) -> Option<MetadataStatus> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getAssetMetadataStatus",
+        encode_one(asset_id).unwrap(),
+    ) else {
+        panic!("Could not get asset metadata status")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_liquidity_manager_details(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+) -> Option<LiquidityManagerDetails> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getLiquidityManagerDetails",
+        encode_one(asset_id).unwrap(),
+    ) else {
+        panic!("Could not get liquidity manager details")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _list_assets(
+    pic: &PocketIc,
+    vault_id: Principal,
+) -> Vec<(AssetId, LiquidityManagerDetails)> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "listAssets",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("List assets failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_asset_margin_balance(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    user: Principal,
+) -> Amount {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getUserMarginBalance",
+        candid::encode_args((asset_id, user)).unwrap(),
+    ) else {
+        panic!("Could not get user margin balance")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_asset_vault(pic: &PocketIc, vault_id: Principal, asset_id: AssetId) -> Option<Vault> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getVault",
+        encode_one(asset_id).unwrap(),
+    ) else {
+        panic!("Could not get vault")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _fund_asset_account(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    amount: Amount,
+    user: Principal,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        user,
+        "fundAccount",
+        candid::encode_args((asset_id, amount, None::<Subaccount>, user)).unwrap(),
+    ) else {
+        panic!("Fund account failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _provide_asset_leverage(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    amount: Amount,
+    caller: Principal,
+) -> Result<bool, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "lendToVault",
+        candid::encode_args((asset_id, amount, None::<Account>)).unwrap(),
+    ) else {
+        panic!("Could not lend to vault")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Installs an ICP ledger with an initial balance for each of `holders` and adds it as an asset
+/// with a virtual token minted by the vault
+pub fn _setup_icp_asset(
+    pic: &PocketIc,
+    vault_id: Principal,
+    holders: Vec<(Principal, u64)>,
+) -> (Principal, AssetId) {
+    let icp_ledger_wasm =
+        fs::read(ICP_LEDGER_WASM).expect("Wasm file not found, download the ICP ledger wasm.");
+
+    let icp_ledger_id = pic.create_canister();
+    pic.add_cycles(icp_ledger_id, 2_000_000_000_000); // 2T Cycles
+
+    let minting_account = AccountIdentifier::new(&Principal::anonymous(), &DEFAULT_SUBACCOUNT);
+    let initial_values = holders
+        .into_iter()
+        .map(|(owner, e8s)| {
+            (
+                AccountIdentifier::new(&owner, &DEFAULT_SUBACCOUNT).to_hex(),
+                Tokens::from_e8s(e8s),
+            )
+        })
+        .collect();
+
+    let args = IcpLedgerArg::Init(IcpInitArgs {
+        minting_account: minting_account.to_hex(),
+        initial_values,
+        send_whitelist: vec![],
+        transfer_fee: Some(Tokens::from_e8s(ICP_FEE as u64)),
+        token_symbol: Some("ICP".to_string()),
+        token_name: Some("Internet Computer".to_string()),
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+    });
+    pic.install_canister(
+        icp_ledger_id,
+        icp_ledger_wasm,
+        encode_one(args).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let token_wasm = fs::read(TOKEN_WASM).expect("Wasm file not found, run 'dfx build'.");
+
+    let vtoken_id = pic.create_canister();
+    pic.add_cycles(vtoken_id, 2_000_000_000_000); // 2T Cycles
+    pic.install_canister(
+        vtoken_id,
+        token_wasm,
+        encode_one(LedgerArg::Init(create_args(vault_id))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICP,
+            ledger_id: icp_ledger_id,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: vtoken_id,
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+    let asset_id = _add_asset(pic, vault_id, Principal::anonymous(), details).unwrap();
+
+    (icp_ledger_id, asset_id)
+}
+
+pub fn _withdraw_to_account_identifier(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    amount: Amount,
+    caller: Principal,
+    to: AccountIdentifier,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "withdrawToAccountIdentifier",
+        candid::encode_args((asset_id, amount, to)).unwrap(),
+    ) else {
+        panic!("Withdraw to account identifier failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _withdraw_asset_from_account(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    amount: Amount,
+    caller: Principal,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "withdrawFromAccount",
+        candid::encode_args((
+            asset_id,
+            amount,
+            Account {
+                owner: caller,
+                subaccount: None,
+            },
+        ))
+        .unwrap(),
+    ) else {
+        panic!("Withdrawl from Account Failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _claim_deposit(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    block_index: u64,
+    caller: Principal,
+) -> Result<Amount, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "claimDeposit",
+        candid::encode_args((asset_id, block_index, None::<Subaccount>)).unwrap(),
+    ) else {
+        panic!("Claim deposit failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_deposit_claimant(
+    pic: &PocketIc,
+    vault_id: Principal,
+    asset_id: AssetId,
+    block_index: u64,
+) -> Option<Principal> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getDepositClaimant",
+        candid::encode_args((asset_id, block_index)).unwrap(),
+    ) else {
+        panic!("Could not get deposit claimant")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Transfers tokens from `from` to the default account of the vault without an approval
+///
+/// Returns the index of the transfer's block
+pub fn _transfer_to_vault(
+    pic: &PocketIc,
+    vault_id: Principal,
+    token_id: Principal,
+    amount: Amount,
+    from: Principal,
+) -> u64 {
+    let args = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: vault_id,
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+    };
+
+    let block_index = _icrc1_transfer(pic, token_id, args, from).unwrap();
+    u64::try_from(block_index.0).unwrap()
+}
+
+pub fn _icp_account_balance(
+    pic: &PocketIc,
+    icp_ledger_id: Principal,
+    account: AccountIdentifier,
+) -> Amount {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        icp_ledger_id,
+        Principal::anonymous(),
+        "account_balance",
+        encode_one(AccountBalanceArgs { account }).unwrap(),
+    ) else {
+        panic!("Could not get account balance")
+    };
+
+    let balance: Tokens = decode_one(&val).unwrap();
+    balance.e8s() as Amount
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////
+pub fn _propose_admin(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    new_admin: Principal,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "proposeAdmin",
+        encode_one(new_admin).unwrap(),
+    ) else {
+        panic!("Propose admin failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _accept_admin(pic: &PocketIc, vault_id: Principal, caller: Principal) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "acceptAdmin",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Accept admin failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _grant_role(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    role: Role,
+    user: Principal,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "grantRole",
+        candid::encode_args((role, user)).unwrap(),
+    ) else {
+        panic!("Grant role failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _revoke_role(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    role: Role,
+    user: Principal,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "revokeRole",
+        candid::encode_args((role, user)).unwrap(),
+    ) else {
+        panic!("Revoke role failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_roles(pic: &PocketIc, vault_id: Principal) -> RoleAssignments {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getRoles",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get roles failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _pause(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    family: OperationFamily,
+    reason: &str,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "pause",
+        candid::encode_args((family, reason.to_string())).unwrap(),
+    ) else {
+        panic!("Pause failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _unpause(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    family: OperationFamily,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) =
+        pic.update_call(vault_id, caller, "unpause", encode_one(family).unwrap())
+    else {
+        panic!("Unpause failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_pause_state(
+    pic: &PocketIc,
+    vault_id: Principal,
+) -> Vec<(OperationFamily, PauseDetails)> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getPauseState",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get pause state failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _set_governance(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    settings: GovernanceSettings,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setGovernance",
+        encode_one(settings).unwrap(),
+    ) else {
+        panic!("Set governance failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Calls the SNS validator of an admin operation with the operation's encoded arguments
+pub fn _validate(
+    pic: &PocketIc,
+    vault_id: Principal,
+    method: &str,
+    args: Vec<u8>,
+) -> Result<String, String> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        &format!("validate_{}", method),
+        args,
+    ) else {
+        panic!("Validate failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _set_timelock_delay(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    delay: u64,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "setTimelockDelay",
+        encode_one(delay).unwrap(),
+    ) else {
+        panic!("Set timelock delay failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _queue_admin_action(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    action: AdminAction,
+) -> Result<u64, String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "queueAdminAction",
+        encode_one(action).unwrap(),
+    ) else {
+        panic!("Queue admin action failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _execute_admin_action(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    action_id: u64,
+) -> Result<(), String> {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        caller,
+        "executeAdminAction",
+        encode_one(action_id).unwrap(),
+    ) else {
+        panic!("Execute admin action failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _get_pending_admin_actions(
+    pic: &PocketIc,
+    vault_id: Principal,
+) -> Vec<(u64, PendingAdminAction)> {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getPendingAdminActions",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get pending admin actions failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+/// Upgrades the vault to the current wasm
+pub fn _upgrade_vault(pic: &PocketIc, vault_id: Principal) {
+    let vault_wasm = fs::read(VAULT_WASM).expect("Wasm file not found, run 'dfx build'.");
+    pic.upgrade_canister(
+        vault_id,
+        vault_wasm,
+        candid::encode_args(()).unwrap(),
+        Some(Principal::anonymous()),
+    )
+    .expect("Upgrade failed");
+}
+
+pub fn _get_schema_version(pic: &PocketIc, vault_id: Principal) -> u32 {
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        Principal::anonymous(),
+        "getSchemaVersion",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get schema version failed")
+    };
+
+    decode_one(&val).unwrap()
+}
+
+pub fn _approve_spending(
+    pic: &PocketIc,
+    token_id: Principal,
+    amount: Amount,
+    caller: Principal,
+    spender_id: Principal,
+) {
+    let args = ApproveArgs {
+        from_subaccount: None,
+        created_at_time: None,
+        expected_allowance: None,
+        fee: None,
+        memo: None,
+        spender: Account {
+            owner: spender_id,
+            subaccount: None,
+        },
+        amount: Nat::from(amount),
+        expires_at: None,
+    };
+    let _ = _icrc2_approve(pic, token_id, args, caller);
+}
+///
+///
+///
+pub fn _mint_approve_and_fund_account(
+    pic: &PocketIc,
+    vault_id: Principal,
+    to_principal: Principal,
+    token_id: Principal,
+) {
+    let account0 = to_principal;
+
+    let args = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: to_principal,
+            subaccount: None,
+        },
+        amount: Nat::from(1000000000000000000u128),
+        fee: None,
+        memo: None,
+    };
+
+    let _ = _icrc1_transfer(&pic, token_id, args, Principal::anonymous());
+
+    let deposit_ampount = 10000000000u128;
+
+    _approve_spending(pic, token_id, deposit_ampount, account0, vault_id);
+
+    let tx_result = _fund_account(&pic, vault_id, deposit_ampount, None, account0, account0);
+
+    assert!(tx_result.is_ok());
+
+    let balance = _get_user_margin_balance(&pic, vault_id, account0);
+
+    assert_eq!(balance, deposit_ampount);
+}
+
+pub fn _get_current_time(pic: &PocketIc) -> Time {
+    pic.get_time()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as Time
+}
+
+pub fn _get_principals() -> Vec<Principal> {
+    return vec![
+        Principal::from_text("hpp6o-wqx72-gol5b-3bmzw-lyryb-62yoi-pjoll-mtsh7-swdzi-jkf2v-rqe")
+            .unwrap(),
+        Principal::from_text("cvwul-djb3r-e6krd-nbnfl-tuhox-n4omu-kejey-3lku7-ae3bx-icbu7-yae")
+            .unwrap(),
+        Principal::from_text("az6yt-a3f5b-k342j-5jncd-csa66-xfgvb-6f52c-jw5nh-o7g4k-bbf4q-vqe")
+            .unwrap(),
+        Principal::from_text("kmgqw-63fcp-ugexu-qgkyy-p3vjk-e4jh5-kyrdx-skajz-o3qra-wcyrb-qae")
+            .unwrap(),
+    ];
+}
+
+#[derive(CandidType)]
+pub enum LedgerArg {
+    Init(InitArgs),
+    Upgrade(UpgradeArgs),
+}
+
+pub fn create_args(minting_id: Principal) -> InitArgs {
+    InitArgs {
+        decimals: Some(8),
+        token_symbol: "ICP".to_string(),
+        transfer_fee: 0,
+        metadata: vec![],
+        minting_account: Account {
+            owner: minting_id,
+            subaccount: None,
+        },
+        initial_balances: vec![],
+        maximum_number_of_accounts: None,
+        accounts_overflow_trim_quantity: None,
+        fee_collector_account: None,
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 1000,
+            max_transactions_per_response: None,
+            trigger_threshold: 1000,
+            max_message_size_bytes: None,
+            cycles_for_archive_creation: None,
+            node_max_memory_size_bytes: None,
+            controller_id: Principal::anonymous(),
+        },
+        max_memo_length: None,
+        token_name: "Internet Computer".to_string(),
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+    }
+}
+
+#[derive(CandidType, Clone)]
+pub struct UpgradeArgs {
+    pub token_symbol: Option<String>,
+    pub transfer_fee: Option<u128>,
+    pub metadata: Option<Vec<(String, MetadataValue)>>,
+    pub maximum_number_of_accounts: Option<u64>,
+    pub accounts_overflow_trim_quantity: Option<u64>,
+    pub change_fee_collector: Option<ChangeFeeCollector>,
+    pub max_memo_length: Option<u16>,
+    pub token_name: Option<String>,
+    pub feature_flags: Option<FeatureFlags>,
+}
+
+#[derive(Debug, Clone, CandidType)]
+pub enum ChangeFeeCollector {
+    Unset,
+    Set(Account),
+}
+
+#[derive(CandidType, Clone)]
+pub struct InitArgs {
+    pub decimals: Option<u8>,
+    pub token_symbol: String,
+    pub transfer_fee: u128,
+    pub metadata: Vec<(String, MetadataValue)>,
+    pub minting_account: Account,
+    pub initial_balances: Vec<(Account, u128)>,
+    pub maximum_number_of_accounts: Option<u64>,
+    pub accounts_overflow_trim_quantity: Option<u64>,
+    pub fee_collector_account: Option<Account>,
+    pub archive_options: ArchiveOptions,
+    pub max_memo_length: Option<u16>,
+    pub token_name: String,
+    pub feature_flags: Option<FeatureFlags>,
 }
 
 #[derive(Debug, Clone, CandidType)]
@@ -483,6 +1767,23 @@ pub struct ArchiveOptions {
     pub controller_id: Principal,
 }
 
+#[derive(CandidType)]
+pub enum IcpLedgerArg {
+    Init(IcpInitArgs),
+}
+
+/// Init args of the ICP ledger,optional fields that are left out default to null
+#[derive(CandidType)]
+pub struct IcpInitArgs {
+    pub minting_account: String,
+    pub initial_values: Vec<(String, Tokens)>,
+    pub send_whitelist: Vec<Principal>,
+    pub transfer_fee: Option<Tokens>,
+    pub token_symbol: Option<String>,
+    pub token_name: Option<String>,
+    pub feature_flags: Option<FeatureFlags>,
+}
+
 #[derive(Debug, Clone, CandidType)]
 pub struct FeatureFlags {
     pub icrc2: bool,
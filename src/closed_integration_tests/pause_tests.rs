@@ -0,0 +1,127 @@
+use super::*;
+
+#[test]
+fn test_that_paused_operations_are_rejected() {
+    let caller = _get_principals()[1];
+    let pauser = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+
+    let tx_result = _pause(
+        &pic,
+        vault_id,
+        pauser,
+        OperationFamily::Deposits,
+        "Incident",
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Only pauser can pause operations" }));
+
+    _grant_role(&pic, vault_id, Principal::anonymous(), Role::Pauser, pauser).unwrap();
+    _pause(
+        &pic,
+        vault_id,
+        pauser,
+        OperationFamily::Deposits,
+        "Incident",
+    )
+    .unwrap();
+
+    let pause_state = _get_pause_state(&pic, vault_id);
+    assert_eq!(pause_state.len(), 1);
+    let (family, details) = &pause_state[0];
+    assert_eq!(*family, OperationFamily::Deposits);
+    assert_eq!(details.reason, "Incident".to_string());
+    assert_eq!(details.paused_at, _get_current_time(&pic));
+    assert_eq!(details.paused_by, pauser);
+
+    _approve_spending(&pic, token_id, 1000, caller, vault_id);
+
+    let Ok(WasmResult::Reject(message)) = pic.update_call(
+        vault_id,
+        caller,
+        "fundAccount",
+        candid::encode_args((INITIAL_ASSET_ID, 1000u128, None::<Subaccount>, caller)).unwrap(),
+    ) else {
+        panic!("Paused deposit was not rejected")
+    };
+    assert!(message.contains("Operation is paused: Incident"));
+
+    // other families keep working
+    let tx_result = _withdraw_from_account(&pic, vault_id, 1000, caller);
+    assert!(tx_result.is_ok());
+
+    _unpause(&pic, vault_id, pauser, OperationFamily::Deposits).unwrap();
+    assert!(_get_pause_state(&pic, vault_id).is_empty());
+
+    let tx_result = _fund_account(&pic, vault_id, 1000, None, caller, caller);
+    assert!(tx_result.is_ok());
+
+    let tx_result = _unpause(&pic, vault_id, pauser, OperationFamily::Deposits);
+    assert!(tx_result.is_err_and(|err| { err == "Operation not paused" }));
+}
+
+#[test]
+fn test_that_repayments_are_allowed_while_new_debt_is_paused() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let _ = _provide_leverage(&pic, vault_id, 1000000u128, caller);
+
+    let debt = 100000u128;
+
+    let Ok(WasmResult::Reply(_)) = pic.update_call(
+        vault_id,
+        market,
+        "liquidityChangeValidityCheck",
+        candid::encode_args((INITIAL_ASSET_ID, caller, 0u128, debt)).unwrap(),
+    ) else {
+        panic!("Liquidity change validity check failed")
+    };
+
+    _pause(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        OperationFamily::NewDebt,
+        "Incident",
+    )
+    .unwrap();
+
+    let Ok(WasmResult::Reject(message)) = pic.update_call(
+        vault_id,
+        market,
+        "liquidityChangeValidityCheck",
+        candid::encode_args((INITIAL_ASSET_ID, caller, 0u128, debt)).unwrap(),
+    ) else {
+        panic!("Paused new debt was not rejected")
+    };
+    assert!(message.contains("Operation is paused: Incident"));
+
+    let params = ManageDebtParams {
+        initial_debt: debt,
+        net_debt: 0,
+        amount_repaid: debt + 1000,
+    };
+
+    let Ok(WasmResult::Reply(_)) = pic.update_call(
+        vault_id,
+        market,
+        "managePositionUpdate",
+        candid::encode_args((INITIAL_ASSET_ID, caller, 0u128, params)).unwrap(),
+    ) else {
+        panic!("Manage position update failed")
+    };
+
+    assert_eq!(_get_vault(&pic, vault_id, caller).debt, 0);
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+#[test]
+fn test_that_builtin_qtokens_are_minted_and_transferred() {
+    let user = _get_principals()[1];
+    let receiver = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vault_id) = _setup_qtoken_vault(&pic);
+
+    _mint_approve_and_fund_account(&pic, vault_id, user, token_id);
+
+    let lend_amount = 10000000000u128;
+    let tx_result = _provide_leverage(&pic, vault_id, lend_amount, user);
+    assert!(tx_result.is_ok());
+
+    // the vault serves the QToken ledger itself
+    let balance = _icrc1_balance_of(
+        &pic,
+        vault_id,
+        Account {
+            owner: user,
+            subaccount: None,
+        },
+        user,
+    );
+    assert_eq!(balance, Nat::from(lend_amount));
+
+    let args = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: receiver,
+            subaccount: None,
+        },
+        amount: Nat::from(lend_amount / 2),
+        fee: None,
+        memo: None,
+    };
+    let tx_result = _icrc1_transfer(&pic, vault_id, args, user);
+    assert!(tx_result.is_ok());
+
+    let balance = _icrc1_balance_of(
+        &pic,
+        vault_id,
+        Account {
+            owner: receiver,
+            subaccount: None,
+        },
+        receiver,
+    );
+    assert_eq!(balance, Nat::from(lend_amount / 2));
+
+    let details = _get_liquidity_manager_details(&pic, vault_id, INITIAL_ASSET_ID).unwrap();
+    let metadata = details.metadata.unwrap();
+    assert_eq!(metadata.virtual_asset.symbol, "qICP".to_string());
+    assert_eq!(metadata.virtual_asset.decimals, metadata.asset.decimals);
+}
@@ -0,0 +1,132 @@
+use super::*;
+
+#[test]
+fn test_that_admin_is_handed_over_in_two_steps() {
+    let admin = _get_principals()[0];
+    let new_admin = _get_principals()[1];
+    let other_user = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault_with_admin(&pic, 0, admin);
+
+    let tx_result = _propose_admin(&pic, vault_id, new_admin, new_admin);
+    assert!(tx_result.is_err_and(|err| { err == "Only admin can propose admin" }));
+
+    _propose_admin(&pic, vault_id, admin, new_admin).unwrap();
+
+    let roles = _get_roles(&pic, vault_id);
+    assert_eq!(roles.admin, admin);
+    assert_eq!(roles.pending_admin, Some(new_admin));
+
+    let tx_result = _accept_admin(&pic, vault_id, other_user);
+    assert!(tx_result.is_err_and(|err| { err == "Caller is not the proposed admin" }));
+
+    // the current admin keeps its rights until the handover is accepted
+    _grant_role(&pic, vault_id, admin, Role::Pauser, other_user).unwrap();
+
+    _accept_admin(&pic, vault_id, new_admin).unwrap();
+
+    let roles = _get_roles(&pic, vault_id);
+    assert_eq!(roles.admin, new_admin);
+    assert_eq!(roles.pending_admin, None);
+
+    let tx_result = _grant_role(&pic, vault_id, admin, Role::Pauser, admin);
+    assert!(tx_result.is_err_and(|err| { err == "Only admin can grant roles" }));
+
+    let tx_result = _accept_admin(&pic, vault_id, new_admin);
+    assert!(tx_result.is_err_and(|err| { err == "Caller is not the proposed admin" }));
+}
+
+#[test]
+fn test_that_proposing_anonymous_cancels_the_handover() {
+    let admin = _get_principals()[0];
+    let new_admin = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault_with_admin(&pic, 0, admin);
+
+    _propose_admin(&pic, vault_id, admin, new_admin).unwrap();
+    _propose_admin(&pic, vault_id, admin, Principal::anonymous()).unwrap();
+
+    assert_eq!(_get_roles(&pic, vault_id).pending_admin, None);
+
+    let tx_result = _accept_admin(&pic, vault_id, new_admin);
+    assert!(tx_result.is_err_and(|err| { err == "Caller is not the proposed admin" }));
+    assert_eq!(_get_roles(&pic, vault_id).admin, admin);
+}
+
+#[test]
+fn test_that_admin_endpoints_check_roles() {
+    let admin = _get_principals()[0];
+    let risk_manager = _get_principals()[1];
+    let market_manager = _get_principals()[2];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault_with_admin(&pic, 0, admin);
+
+    let config = LockSpanConfig {
+        duration: 1_000_000_000,
+        weight: 1,
+        enabled: true,
+        max_capacity: None,
+    };
+
+    let tx_result = _grant_role(
+        &pic,
+        vault_id,
+        risk_manager,
+        Role::RiskManager,
+        risk_manager,
+    );
+    assert!(tx_result.is_err_and(|err| { err == "Only admin can grant roles" }));
+
+    _grant_role(&pic, vault_id, admin, Role::RiskManager, risk_manager).unwrap();
+    _grant_role(&pic, vault_id, admin, Role::MarketManager, market_manager).unwrap();
+
+    let roles = _get_roles(&pic, vault_id);
+    assert!(roles
+        .roles
+        .contains(&(risk_manager, vec![Role::RiskManager])));
+    assert!(roles
+        .roles
+        .contains(&(market_manager, vec![Role::MarketManager])));
+
+    _set_lock_span(&pic, vault_id, risk_manager, 1, config).unwrap();
+
+    let tx_result = _set_lock_span(&pic, vault_id, market_manager, 1, config);
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can set lock spans" }));
+
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        risk_manager,
+        "approveMarket",
+        candid::encode_args((market, "Market".to_string())).unwrap(),
+    ) else {
+        panic!("Approve market failed")
+    };
+    let tx_result: Result<(), String> = decode_one(&val).unwrap();
+    assert!(tx_result.is_err_and(|err| { err == "Only market manager can approve markets" }));
+
+    let Ok(WasmResult::Reply(val)) = pic.update_call(
+        vault_id,
+        market_manager,
+        "approveMarket",
+        candid::encode_args((market, "Market".to_string())).unwrap(),
+    ) else {
+        panic!("Approve market failed")
+    };
+    let tx_result: Result<(), String> = decode_one(&val).unwrap();
+    assert!(tx_result.is_ok());
+
+    _revoke_role(&pic, vault_id, admin, Role::RiskManager, risk_manager).unwrap();
+
+    let tx_result = _set_lock_span(&pic, vault_id, risk_manager, 1, config);
+    assert!(tx_result.is_err_and(|err| { err == "Only risk manager can set lock spans" }));
+
+    let tx_result = _revoke_role(&pic, vault_id, admin, Role::RiskManager, risk_manager);
+    assert!(tx_result.is_err_and(|err| { err == "Role not granted" }));
+}
@@ -86,7 +86,7 @@ mod providing_leverage {
 
         let user_stake = user_stakes[0].1;
 
-        assert_eq!(user_stake.stake_span, LockSpan::Instant);
+        assert_eq!(user_stake.span_id, INSTANT_SPAN_ID);
         assert_eq!(user_stake.amount, amount_utilised)
     }
 }
@@ -0,0 +1,41 @@
+use super::*;
+
+#[test]
+fn test_that_market_approval_is_timelocked() {
+    let admin = _get_principals()[0];
+    let market = _get_principals()[1];
+    let keeper = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault_with_admin(&pic, 0, admin);
+
+    let delay = std::time::Duration::from_secs(2 * 24 * 60 * 60);
+    _set_timelock_delay(&pic, vault_id, admin, delay.as_nanos() as u64).unwrap();
+
+    let tx_result = _set_market_state(&pic, vault_id, admin, "revokeMarket", market);
+    assert!(tx_result.is_err_and(|err| { err == "Market not found" }));
+
+    let action = AdminAction::ApproveMarket {
+        market,
+        name: "Perps".to_string(),
+    };
+    let action_id = _queue_admin_action(&pic, vault_id, admin, action).unwrap();
+
+    let pending_actions = _get_pending_admin_actions(&pic, vault_id);
+    assert_eq!(pending_actions.len(), 1);
+    assert_eq!(pending_actions[0].1.queued_by, admin);
+
+    let tx_result = _execute_admin_action(&pic, vault_id, keeper, action_id);
+    assert!(tx_result.is_err_and(|err| { err == "Admin action is not executable yet" }));
+    assert!(_list_markets(&pic, vault_id).is_empty());
+
+    pic.advance_time(delay);
+
+    _execute_admin_action(&pic, vault_id, keeper, action_id).unwrap();
+
+    let markets = _list_markets(&pic, vault_id);
+    assert_eq!(markets[0].0, market);
+    assert_eq!(markets[0].1.approved_by, admin);
+    assert!(_get_pending_admin_actions(&pic, vault_id).is_empty());
+}
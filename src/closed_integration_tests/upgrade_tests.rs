@@ -0,0 +1,317 @@
+use super::*;
+
+#[test]
+fn test_that_state_survives_upgrade() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let amount = 1000000u128;
+    let _ = _provide_leverage(&pic, vault_id, amount, caller);
+
+    _approve_spending(&pic, vtoken_id, amount / 2, caller, vault_id);
+    let tx_result = _stake(&pic, caller, vault_id, amount / 2, MONTH2_SPAN_ID, None);
+    assert!(tx_result.is_ok());
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, 10000);
+
+    let schema_version = _get_schema_version(&pic, vault_id);
+    let margin_balance = _get_user_margin_balance(&pic, vault_id, caller);
+    let vault = _get_vault(&pic, vault_id, caller);
+    let locks = _get_user_stakes(&pic, vault_id, caller);
+    let lock_tokens = _icrc7_tokens_of(&pic, vault_id, caller);
+    let details = _get_liquidity_manager_details(&pic, vault_id, INITIAL_ASSET_ID).unwrap();
+
+    _upgrade_vault(&pic, vault_id);
+
+    assert_eq!(_get_schema_version(&pic, vault_id), schema_version);
+    assert_eq!(
+        _get_user_margin_balance(&pic, vault_id, caller),
+        margin_balance
+    );
+
+    let upgraded_vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(upgraded_vault.debt, vault.debt);
+    assert_eq!(upgraded_vault.free_liquidity, vault.free_liquidity);
+    assert_eq!(upgraded_vault.lifetime_fees, vault.lifetime_fees);
+
+    let upgraded_locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(upgraded_locks.len(), locks.len());
+    for ((timestamp, lock, earnings), (upgraded_timestamp, upgraded_lock, upgraded_earnings)) in
+        locks.iter().zip(upgraded_locks.iter())
+    {
+        assert_eq!(timestamp, upgraded_timestamp);
+        assert_eq!(lock.amount, upgraded_lock.amount);
+        assert_eq!(lock.span_id, upgraded_lock.span_id);
+        assert_eq!(lock.expiry_time, upgraded_lock.expiry_time);
+        assert_eq!(lock.token_id, upgraded_lock.token_id);
+        assert_eq!(earnings, upgraded_earnings);
+    }
+    assert_eq!(_icrc7_tokens_of(&pic, vault_id, caller), lock_tokens);
+
+    let upgraded_details =
+        _get_liquidity_manager_details(&pic, vault_id, INITIAL_ASSET_ID).unwrap();
+    assert_eq!(upgraded_details.asset.ledger_id, details.asset.ledger_id);
+    assert_eq!(upgraded_details.min_amount, details.min_amount);
+
+    let markets = _list_markets(&pic, vault_id);
+    assert_eq!(markets.len(), 1);
+    assert_eq!(markets[0].0, market);
+
+    // the vault keeps working after the upgrade
+    let tx_result = _withdraw_from_account(&pic, vault_id, margin_balance, caller);
+    assert!(tx_result.is_ok());
+}
+
+#[test]
+fn test_that_new_canisters_skip_migrations() {
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let schema_version = _get_schema_version(&pic, vault_id);
+    assert!(schema_version > 0);
+
+    _upgrade_vault(&pic, vault_id);
+    _upgrade_vault(&pic, vault_id);
+
+    assert_eq!(_get_schema_version(&pic, vault_id), schema_version);
+}
+
+/// Liquidity manager details as taken by the first release of the vault
+#[derive(CandidType)]
+struct BaselineLiquidityManagerDetails {
+    asset: Asset,
+    virtual_asset: Asset,
+    min_amount: Amount,
+}
+
+/// Lock spans of the first release of the vault
+#[derive(CandidType)]
+enum BaselineLockSpan {
+    #[allow(dead_code)]
+    Instant,
+    Month2,
+}
+
+/// The fields of a lock of the first release of the vault compared after the upgrade
+#[derive(CandidType, Deserialize)]
+struct BaselineLockDetails {
+    amount: Amount,
+    expiry_time: Time,
+}
+
+/// The fields of the vault of the first release compared after the upgrade
+#[derive(CandidType, Deserialize)]
+struct BaselineVault {
+    debt: Amount,
+    free_liquidity: Amount,
+}
+
+fn _baseline_call<T: for<'a> Deserialize<'a> + CandidType>(
+    pic: &PocketIc,
+    vault_id: Principal,
+    caller: Principal,
+    method: &str,
+    args: Vec<u8>,
+) -> T {
+    let Ok(WasmResult::Reply(val)) = pic.update_call(vault_id, caller, method, args) else {
+        panic!("{} failed", method)
+    };
+    decode_one(&val).unwrap()
+}
+
+#[test]
+fn test_that_baseline_vault_upgrades_with_its_state() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let token_wasm = fs::read(TOKEN_WASM).expect("Wasm file not found, run 'dfx build'.");
+    let baseline_wasm =
+        fs::read(BASELINE_VAULT_WASM).expect("Wasm file not found, build the baseline vault.");
+
+    let token_id = pic.create_canister();
+    pic.add_cycles(token_id, 2_000_000_000_000); // 2T Cycles
+    pic.install_canister(
+        token_id,
+        token_wasm.clone(),
+        encode_one(LedgerArg::Init(create_args(Principal::anonymous()))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let vault_id = pic.create_canister();
+    pic.add_cycles(vault_id, 2_000_000_000_000); // 2T Cycles
+
+    let vtoken_id = pic.create_canister();
+    pic.add_cycles(vtoken_id, 2_000_000_000_000); // 2T Cycles
+    pic.install_canister(
+        vtoken_id,
+        token_wasm,
+        encode_one(LedgerArg::Init(create_args(vault_id))).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    let vault_args = BaselineLiquidityManagerDetails {
+        asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: token_id,
+        },
+        virtual_asset: Asset {
+            asset_type: AssetType::ICRC,
+            ledger_id: vtoken_id,
+        },
+        min_amount: 0,
+    };
+    pic.install_canister(
+        vault_id,
+        baseline_wasm,
+        encode_one(vault_args).unwrap(),
+        Some(Principal::anonymous()),
+    );
+
+    // fund,lend,lock and take debt through the first release's interface
+    let deposit = 10000000000u128;
+    let amount = 1000000u128;
+    let debt = 100000u128;
+
+    let mint_arg = TransferArg {
+        from_subaccount: None,
+        created_at_time: None,
+        to: Account {
+            owner: caller,
+            subaccount: None,
+        },
+        amount: Nat::from(deposit),
+        fee: None,
+        memo: None,
+    };
+    let _ = _icrc1_transfer(&pic, token_id, mint_arg, Principal::anonymous());
+    _approve_spending(&pic, token_id, deposit, caller, vault_id);
+
+    let tx_result: Result<Amount, String> = _baseline_call(
+        &pic,
+        vault_id,
+        caller,
+        "fundAccount",
+        candid::encode_args((deposit, None::<Subaccount>, caller)).unwrap(),
+    );
+    assert!(tx_result.is_ok());
+
+    let tx_result: Result<bool, String> = _baseline_call(
+        &pic,
+        vault_id,
+        caller,
+        "lendToVault",
+        encode_one(amount).unwrap(),
+    );
+    assert!(tx_result.is_ok());
+
+    _approve_spending(&pic, vtoken_id, amount / 2, caller, vault_id);
+    let tx_result: Result<Amount, String> = _baseline_call(
+        &pic,
+        vault_id,
+        caller,
+        "lockQTokens",
+        candid::encode_args((amount / 2, BaselineLockSpan::Month2, None::<Subaccount>)).unwrap(),
+    );
+    assert!(tx_result.is_ok());
+
+    let tx_result: Result<(), String> = _baseline_call(
+        &pic,
+        vault_id,
+        Principal::anonymous(),
+        "approveMarket",
+        encode_one(market).unwrap(),
+    );
+    assert!(tx_result.is_ok());
+
+    let Ok(WasmResult::Reply(_)) = pic.update_call(
+        vault_id,
+        market,
+        "liquidityChangeValidityCheck",
+        candid::encode_args((caller, 0u128, debt)).unwrap(),
+    ) else {
+        panic!("Liquidity change validity check failed")
+    };
+
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        caller,
+        "getUserMarginBalance",
+        encode_one(caller).unwrap(),
+    ) else {
+        panic!("Get margin balance failed")
+    };
+    let margin_balance: Amount = decode_one(&val).unwrap();
+
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        caller,
+        "getVault",
+        candid::encode_args(()).unwrap(),
+    ) else {
+        panic!("Get vault failed")
+    };
+    let vault: BaselineVault = decode_one(&val).unwrap();
+
+    let Ok(WasmResult::Reply(val)) = pic.query_call(
+        vault_id,
+        caller,
+        "getUserLocks",
+        encode_one(caller).unwrap(),
+    ) else {
+        panic!("Get user locks failed")
+    };
+    let locks: Vec<(Time, BaselineLockDetails, Amount)> = decode_one(&val).unwrap();
+    assert_eq!(locks.len(), 2);
+
+    _upgrade_vault(&pic, vault_id);
+
+    for _ in 0..5 {
+        pic.tick();
+    }
+
+    assert!(_get_schema_version(&pic, vault_id) > 0);
+    assert_eq!(
+        _get_user_margin_balance(&pic, vault_id, caller),
+        margin_balance
+    );
+
+    let upgraded_vault = _get_vault(&pic, vault_id, caller);
+    assert_eq!(upgraded_vault.debt, vault.debt);
+    assert_eq!(upgraded_vault.free_liquidity, vault.free_liquidity);
+    assert_eq!(
+        upgraded_vault.spans[&MONTH2_SPAN_ID].total_locked,
+        amount / 2
+    );
+
+    let upgraded_locks = _get_user_stakes(&pic, vault_id, caller);
+    assert_eq!(upgraded_locks.len(), locks.len());
+    for ((timestamp, lock, _), (upgraded_timestamp, upgraded_lock, _)) in
+        locks.iter().zip(upgraded_locks.iter())
+    {
+        assert_eq!(timestamp, upgraded_timestamp);
+        assert_eq!(lock.amount, upgraded_lock.amount);
+        assert_eq!(lock.expiry_time, upgraded_lock.expiry_time);
+    }
+    assert_eq!(upgraded_locks[1].1.span_id, MONTH2_SPAN_ID);
+    assert_eq!(_icrc7_tokens_of(&pic, vault_id, caller).len(), 1);
+
+    let markets = _list_markets(&pic, vault_id);
+    assert_eq!(markets.len(), 1);
+    assert_eq!(markets[0].0, market);
+    assert_eq!(markets[0].1.debts.get(&INITIAL_ASSET_ID), Some(&debt));
+
+    // the upgraded vault keeps working and saves its values in the current format
+    let tx_result = _withdraw_from_account(&pic, vault_id, margin_balance, caller);
+    assert!(tx_result.is_ok());
+    assert_eq!(_get_user_margin_balance(&pic, vault_id, caller), 0);
+}
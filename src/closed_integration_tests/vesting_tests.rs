@@ -0,0 +1,118 @@
+use super::*;
+
+const DAY_NANOS: Time = 24 * 60 * 60 * 1_000_000_000;
+
+/// Sets up a vault whose admin holds `amount` virtual tokens approved for the vault
+///
+/// Returns the vault's id and the admin
+fn _setup_vault_with_funded_admin(pic: &PocketIc, amount: Amount) -> (Principal, Principal) {
+    let admin = _get_principals()[0];
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault_with_admin(pic, 0, admin);
+
+    _mint_approve_and_fund_account(pic, vault_id, admin, token_id);
+
+    let _ = _provide_leverage(pic, vault_id, amount, admin);
+
+    _approve_spending(pic, vtoken_id, amount, admin, vault_id);
+
+    (vault_id, admin)
+}
+
+/// A grant vesting over 100 days with a 10 day cliff starting at the current time
+fn _grant(pic: &PocketIc, beneficiary: Principal, amount: Amount) -> VestingGrant {
+    let start_time = _get_current_time(pic);
+    VestingGrant {
+        beneficiary,
+        amount,
+        span_id: MONTH2_SPAN_ID,
+        start_time,
+        cliff_time: start_time + 10 * DAY_NANOS,
+        end_time: start_time + 100 * DAY_NANOS,
+    }
+}
+
+#[test]
+fn test_that_only_admin_can_create_vesting_locks() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (vault_id, _) = _setup_vault_with_funded_admin(&pic, 1000000u128);
+
+    let grant = _grant(&pic, caller, 1000000u128);
+
+    let tx_result = _create_vesting_locks(&pic, vault_id, caller, vec![grant]);
+    assert!(tx_result.is_err_and(|err| { err == "Only treasurer can create vesting locks" }));
+}
+
+#[test]
+fn test_that_invalid_vesting_schedule_fails() {
+    let beneficiary = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (vault_id, admin) = _setup_vault_with_funded_admin(&pic, 1000000u128);
+
+    let mut grant = _grant(&pic, beneficiary, 1000000u128);
+    grant.cliff_time = grant.end_time + 1;
+
+    let tx_result = _create_vesting_locks(&pic, vault_id, admin, vec![grant]);
+    assert!(tx_result.is_err_and(|err| { err == "Invalid vesting schedule" }));
+}
+
+#[test]
+fn test_that_vested_principal_is_claimable_linearly() {
+    let beneficiary = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let amount = 1000000u128;
+
+    let (vault_id, admin) = _setup_vault_with_funded_admin(&pic, amount);
+
+    let grants = vec![
+        _grant(&pic, beneficiary, amount / 2),
+        _grant(&pic, beneficiary, amount / 2),
+    ];
+
+    let tx_result = _create_vesting_locks(&pic, vault_id, admin, grants);
+    let lock_timestamps = tx_result.unwrap();
+    assert_eq!(lock_timestamps.len(), 2);
+
+    let lock_timestamp = lock_timestamps[0];
+
+    let (_, lock, _) = _get_user_stakes(&pic, vault_id, beneficiary)[0];
+    assert_eq!(lock.amount, amount / 2);
+    assert_eq!(lock.funded_by, Some(admin));
+    assert!(lock.vesting.is_some());
+
+    let vault = _get_vault(&pic, vault_id, admin);
+    assert_eq!(vault.spans[&MONTH2_SPAN_ID].total_locked, amount);
+
+    // nothing is vested before the cliff
+    let tx_result = _claim_vested(&pic, vault_id, beneficiary, lock_timestamp);
+    assert!(tx_result.is_err_and(|err| { err == "Nothing vested to claim" }));
+
+    // vesting locks can not be unlocked early
+    let tx_result = _early_unlock(&pic, vault_id, beneficiary, lock_timestamp);
+    assert!(tx_result.is_err_and(|err| { err == "Can not early unlock vesting locks" }));
+
+    pic.advance_time(std::time::Duration::from_secs(50 * 24 * 60 * 60));
+
+    let tx_result = _claim_vested(&pic, vault_id, beneficiary, lock_timestamp);
+    assert!(tx_result.is_ok_and(|claimed| claimed >= amount / 4));
+
+    let (_, lock, _) = _get_user_stakes(&pic, vault_id, beneficiary)[0];
+    let claimed = lock.vesting.unwrap().claimed;
+    assert!(claimed >= amount / 4 && claimed < amount / 2);
+    assert_eq!(lock.amount + claimed, amount / 2);
+
+    pic.advance_time(std::time::Duration::from_secs(50 * 24 * 60 * 60));
+
+    let tx_result = _claim_vested(&pic, vault_id, beneficiary, lock_timestamp);
+    assert!(tx_result.is_ok());
+
+    // the fully vested lock is closed
+    assert_eq!(_get_user_stakes(&pic, vault_id, beneficiary).len(), 1);
+}
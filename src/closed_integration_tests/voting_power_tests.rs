@@ -0,0 +1,114 @@
+use super::*;
+
+/// Funds a user and locks `amount` in `span` for them
+fn _lock_for_user(
+    pic: &PocketIc,
+    token_id: Principal,
+    vtoken_id: Principal,
+    vault_id: Principal,
+    user: Principal,
+    amount: Amount,
+    span: SpanId,
+) {
+    _mint_approve_and_fund_account(pic, vault_id, user, token_id);
+
+    let _ = _provide_leverage(pic, vault_id, amount, user);
+
+    _approve_spending(pic, vtoken_id, amount, user, vault_id);
+
+    let tx_result = _stake(pic, user, vault_id, amount, span, None);
+    assert!(tx_result.is_ok());
+}
+
+#[test]
+fn test_that_voting_power_scales_with_remaining_lock_time() {
+    let caller = _get_principals()[1];
+    let other_user = _get_principals()[2];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    let amount = 1000000u128;
+
+    _lock_for_user(
+        &pic,
+        token_id,
+        vtoken_id,
+        vault_id,
+        caller,
+        amount,
+        YEAR_SPAN_ID,
+    );
+    _lock_for_user(
+        &pic,
+        token_id,
+        vtoken_id,
+        vault_id,
+        other_user,
+        amount,
+        MONTH6_SPAN_ID,
+    );
+
+    let lock_time = _get_current_time(&pic);
+
+    // a year lock has about its full amount as voting power,a six month lock about half
+    let voting_power = _get_voting_power(&pic, vault_id, caller, lock_time);
+    assert!(voting_power <= amount && voting_power > amount * 99 / 100);
+
+    let other_voting_power = _get_voting_power(&pic, vault_id, other_user, lock_time);
+    assert!(other_voting_power <= amount / 2 && other_voting_power > amount * 49 / 100);
+
+    let total_voting_power = _get_total_voting_power(&pic, vault_id, lock_time);
+    assert_eq!(total_voting_power, voting_power + other_voting_power);
+}
+
+#[test]
+fn test_that_voting_power_decays_and_history_is_kept() {
+    let caller = _get_principals()[1];
+
+    let pic = PocketIc::new();
+
+    let (token_id, vtoken_id, vault_id) = _setup_vault(&pic, 0);
+
+    let amount = 1000000u128;
+
+    _lock_for_user(
+        &pic,
+        token_id,
+        vtoken_id,
+        vault_id,
+        caller,
+        amount,
+        MONTH6_SPAN_ID,
+    );
+
+    let lock_time = _get_current_time(&pic);
+    let voting_power_at_lock = _get_voting_power(&pic, vault_id, caller, lock_time);
+
+    // nothing was locked before the lock was created
+    assert_eq!(
+        _get_voting_power(&pic, vault_id, caller, lock_time - 1_000_000_000_000),
+        0
+    );
+
+    pic.advance_time(std::time::Duration::from_secs(91 * 24 * 60 * 60));
+
+    let current_time = _get_current_time(&pic);
+    let voting_power = _get_voting_power(&pic, vault_id, caller, current_time);
+    assert!(voting_power < voting_power_at_lock * 51 / 100);
+    assert!(voting_power > voting_power_at_lock * 49 / 100);
+
+    // past voting power is unchanged
+    assert_eq!(
+        _get_voting_power(&pic, vault_id, caller, lock_time),
+        voting_power_at_lock
+    );
+
+    // no voting power is left after expiry
+    pic.advance_time(std::time::Duration::from_secs(92 * 24 * 60 * 60));
+
+    let current_time = _get_current_time(&pic);
+    assert_eq!(_get_voting_power(&pic, vault_id, caller, current_time), 0);
+    assert_eq!(_get_total_voting_power(&pic, vault_id, current_time), 0);
+}
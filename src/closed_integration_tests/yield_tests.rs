@@ -0,0 +1,61 @@
+use super::*;
+
+#[test]
+fn test_that_yields_are_none_without_enough_history() {
+    let pic = PocketIc::new();
+
+    let (_, _, vault_id) = _setup_vault(&pic, 0);
+
+    let span_yields = _get_span_yields(&pic, vault_id);
+
+    let span_ids: Vec<SpanId> = span_yields
+        .iter()
+        .map(|span_yield| span_yield.span_id)
+        .collect();
+    assert_eq!(
+        span_ids,
+        vec![
+            INSTANT_SPAN_ID,
+            MONTH2_SPAN_ID,
+            MONTH6_SPAN_ID,
+            YEAR_SPAN_ID
+        ]
+    );
+
+    for span_yield in span_yields {
+        assert!(span_yield.apy_7d.is_none());
+        assert!(span_yield.apy_30d.is_none());
+        assert!(span_yield.apy_90d.is_none());
+    }
+}
+
+#[test]
+fn test_that_lender_yield_reflects_fees_paid() {
+    let caller = _get_principals()[1];
+    let market = _get_principals()[3];
+
+    let pic = PocketIc::new();
+
+    let (token_id, _, vault_id) = _setup_vault(&pic, 0);
+
+    _mint_approve_and_fund_account(&pic, vault_id, caller, token_id);
+    _approve_market(&pic, vault_id, market);
+
+    let _ = _provide_leverage(&pic, vault_id, 1000000u128, caller);
+
+    _simulate_position_fees(&pic, vault_id, market, caller, 100000, 10000);
+
+    pic.advance_time(std::time::Duration::from_secs(8 * 24 * 60 * 60));
+    pic.tick();
+
+    let span_yields = _get_span_yields(&pic, vault_id);
+
+    let lender_yield = span_yields[0];
+    assert_eq!(lender_yield.span_id, INSTANT_SPAN_ID);
+    assert!(lender_yield.apy_7d.is_some_and(|apy| apy > 0));
+    assert!(lender_yield.apy_90d.is_none());
+
+    // nothing is locked so the locked spans earn nothing
+    let locked_yield = span_yields[1];
+    assert!(locked_yield.apy_7d.is_some_and(|apy| apy == 0));
+}
@@ -1,29 +1,39 @@
-use candid::{CandidType, Nat, Principal};
-use ic_cdk;
+use candid::{CandidType, Principal};
 
-use icrc_ledger_types::{
-    icrc1::{
-        account::{Account, Subaccount},
-        transfer::{TransferArg, TransferError},
-    },
-    icrc2::transfer_from::{TransferFromArgs, TransferFromError},
-};
+use icrc_ledger_types::{icrc::generic_metadata_value::MetadataValue, icrc1::account::Account};
 
-use ic_ledger_types::{
-    transfer, AccountIdentifier, Memo, Subaccount as ICSubaccount, Tokens,
-    TransferArgs as ICRCTransferArgs, DEFAULT_FEE, DEFAULT_SUBACCOUNT,
-};
 use serde::{Deserialize, Serialize};
 
+#[cfg(test)]
+use super::ledger::MockLedger;
+use super::ledger::{IcpLedger, IcrcLedger, Ledger, LedgerBackend, QTokenLedger};
+
 type Amount = u128;
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Copy)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug)]
 pub enum AssetType {
     ICP,
     ICRC,
+    /// The QToken ledger built into the vault canister,the ledger id is the vault's id
+    QToken,
+    /// An in-memory ledger used in unit tests
+    #[cfg(test)]
+    Mock,
+}
+
+/// Token Metadata
+///
+/// The metadata of an asset's ledger as returned by `icrc1_metadata`
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub fee: Amount,
+    pub logo: Option<String>,
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Copy)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Debug)]
 pub struct Asset {
     pub ledger_id: Principal,
     pub asset_type: AssetType,
@@ -38,6 +48,34 @@ impl Default for Asset {
 }
 
 impl Asset {
+    /// Gets the ledger the asset is held in
+    pub fn ledger(&self) -> LedgerBackend {
+        match self.asset_type {
+            AssetType::ICP => LedgerBackend::Icp(IcpLedger {
+                ledger_id: self.ledger_id,
+            }),
+            AssetType::ICRC => LedgerBackend::Icrc(IcrcLedger {
+                ledger_id: self.ledger_id,
+            }),
+            AssetType::QToken => LedgerBackend::QToken(QTokenLedger),
+            #[cfg(test)]
+            AssetType::Mock => LedgerBackend::Mock(MockLedger {
+                ledger_id: self.ledger_id,
+            }),
+        }
+    }
+
+    /// Moves the asset between accounts
+    ///
+    /// # Arguments
+    /// * `amount` - Amount of tokens to move
+    /// * `from_account` - Source account,a subaccount of the canister when moving out
+    /// * `to_account` - Destination account
+    /// * `out` - True to transfer out of the canister,false to transfer from an account that
+    ///   approved the canister
+    ///
+    /// # Returns
+    /// * `bool` - True if the transfer succeeded, false otherwise
     pub async fn move_asset(
         &self,
         amount: Amount,
@@ -45,164 +83,52 @@ impl Asset {
         to_account: Account,
         out: bool,
     ) -> bool {
-        match self.asset_type {
-            AssetType::ICP => {
-                move_asset_icp(amount, self.ledger_id, from_account.subaccount, to_account).await
-            }
-            AssetType::ICRC => {
-                if out {
-                    send_asset_out_icrc(amount, self.ledger_id, from_account.subaccount, to_account)
-                        .await
-                } else {
-                    send_asset_in_asset_icrc(amount, self.ledger_id, from_account, to_account).await
-                }
-            }
-        }
-    }
-}
-
-/// Transfers ICP tokens between accounts on the Internet Computer
-///
-/// # Arguments
-/// * `amount` - Amount of ICP tokens to transfer (in e8s)
-/// * `ledger_id` - Principal ID of the ICP ledger canister
-/// * `from_sub` - Optional subaccount to transfer from
-/// * `to_account` - Destination account details including owner and subaccount
-///
-/// # Returns
-/// * `bool` - True if transfer succeeded, false otherwise
-///
-/// # Notes
-/// - Returns early with true if amount is 0
-/// - Uses default fee and memo(0) for all transfers
-/// - Handles nested Result types from IC ledger response
-async fn move_asset_icp(
-    amount: Amount,
-    ledger_id: Principal,
-    from_sub: Option<Subaccount>,
-    to_account: Account,
-) -> bool {
-    if amount == 0 {
-        return true;
-    }
-
-    let args = ICRCTransferArgs {
-        amount: Tokens::from_e8s(amount as u64),
-        memo: Memo(0),
-        fee: DEFAULT_FEE,
-        from_subaccount: Some(_to_ic_subaccount(from_sub)),
-        to: AccountIdentifier::new(&to_account.owner, &_to_ic_subaccount(to_account.subaccount)),
-        created_at_time: None,
-    };
-
-    match transfer(ledger_id, args).await {
-        Ok(res) => {
-            if let Ok(_) = res {
-                return true;
-            } else {
-                return false;
-            }
-        }
-        Err(_) => return false,
-    };
-}
-
-/// Transfers ICRC tokens from the canister to an external account
-///
-/// # Arguments
-/// * `amount` - Amount of tokens to transfer
-/// * `ledger_id` - Principal ID of the token's ledger canister
-/// * `from_subaccount` - Optional subaccount to transfer from
-/// * `to_account` - Destination account details
-///
-/// # Returns
-/// * `bool` - True if transfer succeeded, false otherwise
-///
-/// # Notes
-/// - Uses ICRC1 standard transfer call
-/// - Does not specify fee, memo or timestamp (all None)
-/// - Returns false on any error in the transfer
-/// - Handles nested Result types from IC ledger response
-async fn send_asset_out_icrc(
-    amount: Amount,
-    ledger_id: Principal,
-    from_subaccount: Option<Subaccount>,
-    to_account: Account,
-) -> bool {
-    // Error: Typo in struct name ICRCTransferrgs -> ICRCTransferArgs
-    let args = TransferArg {
-        amount: Nat::from(amount),
-        from_subaccount,
-        to: to_account,
-        fee: None,
-        created_at_time: None,
-        memo: None,
-    };
-
-    let tx_result: Result<Nat, TransferError>;
-
-    if let Ok((result,)) = ic_cdk::call(ledger_id, "icrc1_transfer", (args,)).await {
-        tx_result = result;
-        if let Ok(_) = tx_result {
-            return true;
+        let ledger = self.ledger();
+        let tx_result = if out {
+            ledger
+                .transfer(amount, from_account.subaccount, to_account)
+                .await
         } else {
-            return false;
-        }
-    } else {
-        return false;
+            ledger.transfer_from(amount, from_account, to_account).await
+        };
+        tx_result.is_ok()
     }
-}
 
-/// Transfers ICRC2 tokens from one account to another using the spender's allowance
-///
-/// # Arguments
-/// * `amount` - Amount of tokens to transfer
-/// * `ledger_id` - Principal ID of the token's ledger canister
-/// * `from_account` - Source account to transfer from
-/// * `to_account` - Destination account to transfer to
-///
-/// # Returns
-/// * `bool` - True if transfer succeeded, false otherwise
-///
-/// # Notes
-/// - Uses ICRC2 standard transferFrom call
-/// - Requires prior approval/allowance from source account for the None subaccount of the canister
-/// - Does not specify fee, memo, timestamp or spender subaccount (all None)
-/// - Returns false on any error in the transfer
-/// - Handles nested Result types from IC ledger response
-pub async fn send_asset_in_asset_icrc(
-    amount: Amount,
-    ledger_id: Principal,
-    from_account: Account,
-    to_account: Account,
-) -> bool {
-    let args = TransferFromArgs {
-        spender_subaccount: None,
-        from: from_account,
-        to: to_account,
-        amount: Nat::from(amount),
-        fee: None,
-        memo: None,
-        created_at_time: None,
-    };
-
-    let tx_result: Result<Nat, TransferFromError>;
+    /// Fetches the metadata of the asset's ledger
+    ///
+    /// The fee is queried from the ledger when it is not part of the metadata
+    pub async fn fetch_metadata(&self) -> Result<TokenMetadata, String> {
+        let ledger = self.ledger();
+        let entries = ledger.metadata().await?;
+
+        let text = |key: &str| {
+            entries.iter().find_map(|(entry_key, value)| match value {
+                MetadataValue::Text(text) if entry_key == key => Some(text.clone()),
+                _ => None,
+            })
+        };
+        let nat = |key: &str| {
+            entries.iter().find_map(|(entry_key, value)| match value {
+                MetadataValue::Nat(nat) if entry_key == key => u128::try_from(nat.0.clone()).ok(),
+                _ => None,
+            })
+        };
 
-    if let Ok((result,)) = ic_cdk::call(ledger_id, "icrc2_transfer_from", (args,)).await {
-        tx_result = result;
-        if let Ok(_) = tx_result {
-            return true;
-        } else {
-            return false;
-        }
-    } else {
-        return false;
-    }
-}
+        let Some(decimals) = nat("icrc1:decimals").and_then(|decimals| u8::try_from(decimals).ok())
+        else {
+            return Err("Ledger metadata has no decimals".to_string());
+        };
+        let fee = match nat("icrc1:fee") {
+            Some(fee) => fee,
+            None => ledger.fee().await?,
+        };
 
-fn _to_ic_subaccount(sub: Option<Subaccount>) -> ICSubaccount {
-    match sub {
-        Some(res) => return ICSubaccount(res),
-        None => return DEFAULT_SUBACCOUNT,
+        Ok(TokenMetadata {
+            name: text("icrc1:name").unwrap_or_default(),
+            symbol: text("icrc1:symbol").unwrap_or_default(),
+            decimals,
+            fee,
+            logo: text("icrc1:logo"),
+        })
     }
 }
@@ -0,0 +1,93 @@
+use candid::Principal;
+
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
+
+type Time = u64;
+
+// the system API is only available inside a canister,unit tests set the environment instead
+#[cfg(test)]
+thread_local! {
+    static TIME: Cell<Time> = const { Cell::new(0) };
+
+    static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+
+    static CANISTER_ID: Cell<Principal> = const { Cell::new(Principal::management_canister()) };
+
+    static CERTIFIED_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Gets the current time in nanoseconds
+#[cfg(not(test))]
+pub fn time() -> Time {
+    ic_cdk::api::time()
+}
+
+/// Gets the principal calling the current method
+#[cfg(not(test))]
+pub fn caller() -> Principal {
+    ic_cdk::caller()
+}
+
+/// Gets the principal of the canister
+#[cfg(not(test))]
+pub fn canister_id() -> Principal {
+    ic_cdk::id()
+}
+
+/// Sets the data certified by the subnet for the canister
+#[cfg(not(test))]
+pub fn set_certified_data(data: &[u8]) {
+    ic_cdk::api::set_certified_data(data)
+}
+
+/// Gets the certificate of the canister's certified data,only available in queries
+#[cfg(not(test))]
+pub fn data_certificate() -> Option<Vec<u8>> {
+    ic_cdk::api::data_certificate()
+}
+
+#[cfg(test)]
+pub fn time() -> Time {
+    TIME.get()
+}
+
+#[cfg(test)]
+pub fn caller() -> Principal {
+    CALLER.get()
+}
+
+#[cfg(test)]
+pub fn canister_id() -> Principal {
+    CANISTER_ID.get()
+}
+
+#[cfg(test)]
+pub fn set_certified_data(data: &[u8]) {
+    CERTIFIED_DATA.with_borrow_mut(|reference| *reference = data.to_vec());
+}
+
+#[cfg(test)]
+pub fn data_certificate() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+pub fn _certified_data() -> Vec<u8> {
+    CERTIFIED_DATA.with_borrow(|reference| reference.clone())
+}
+
+#[cfg(test)]
+pub fn _set_time(time: Time) {
+    TIME.set(time);
+}
+
+#[cfg(test)]
+pub fn _set_caller(caller: Principal) {
+    CALLER.set(caller);
+}
+
+#[cfg(test)]
+pub fn _set_canister_id(canister_id: Principal) {
+    CANISTER_ID.set(canister_id);
+}
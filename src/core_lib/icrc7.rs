@@ -0,0 +1,128 @@
+use candid::{CandidType, Nat};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use super::versioned::{_from_versioned_bytes, _to_versioned_bytes, Versioned};
+
+/// Identifier of a lock token,tokens are issued in increasing order starting from 1
+pub type TokenId = u64;
+
+/// The name of the lock token collection
+pub const COLLECTION_NAME: &str = "Riverr Vault Locks";
+
+/// The symbol of the lock token collection
+pub const COLLECTION_SYMBOL: &str = "RVL";
+
+/// Max number of token ids returned by a single `icrc7_tokens_of` call
+pub const MAX_TAKE_VALUE: u64 = 100;
+
+/// Number of token ids returned by `icrc7_tokens_of` when no take is given
+pub const DEFAULT_TAKE_VALUE: u64 = 100;
+
+/// Max number of tokens or accounts in a single query call
+pub const MAX_QUERY_BATCH_SIZE: u64 = 100;
+
+/// Max number of transfers in a single `icrc7_transfer` call
+pub const MAX_UPDATE_BATCH_SIZE: u64 = 20;
+
+/// Max size of the memo of a transfer in bytes
+pub const MAX_TOKEN_MEMO_SIZE: u64 = 32;
+
+/// The standards implemented by the lock token collection with the url of each
+pub const SUPPORTED_TOKEN_STANDARDS: [(&str, &str); 2] = [
+    (
+        "ICRC-7",
+        "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-7",
+    ),
+    (
+        "ICRC-10",
+        "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-10",
+    ),
+];
+
+/// Standard Record
+///
+/// A standard supported by the canister as returned by `icrc10_supported_standards` and
+/// `icrc1_supported_standards`
+#[derive(Clone, Deserialize, Debug, CandidType)]
+pub struct StandardRecord {
+    pub name: String,
+    pub url: String,
+}
+
+/// Lock Tokens State
+///
+/// Counters of the lock token collection
+#[derive(Copy, Clone, Default, Deserialize, Debug, CandidType)]
+pub struct LockTokensState {
+    /// The id of the last token issued
+    pub last_token_id: TokenId,
+    /// The number of transfers made,used as the transaction index of a transfer
+    pub transfer_count: u64,
+}
+
+impl LockTokensState {
+    /// Issues the next token id
+    pub fn _next_token_id(&mut self) -> TokenId {
+        self.last_token_id += 1;
+        self.last_token_id
+    }
+
+    /// Records a transfer and returns its transaction index
+    pub fn _record_transfer(&mut self) -> Nat {
+        let index = self.transfer_count;
+        self.transfer_count += 1;
+        Nat::from(index)
+    }
+}
+
+impl Storable for LockTokensState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for LockTokensState {
+    const VERSION: u8 = 1;
+}
+
+/// Transfer Arg
+///
+/// A single transfer of an `icrc7_transfer` call as defined by ICRC-7
+#[derive(Clone, Deserialize, Debug, CandidType)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Subaccount>,
+    pub to: Account,
+    pub token_id: Nat,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Deserialize, Debug, CandidType, PartialEq, Eq)]
+pub enum TransferError {
+    NonExistingTokenId,
+    InvalidRecipient,
+    Unauthorized,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+pub type TransferResult = Result<Nat, TransferError>;
+
+/// Converts a token id received through the ICRC-7 interface,ids that do not fit
+/// a `TokenId` can not exist
+pub fn _to_token_id(token_id: &Nat) -> Option<TokenId> {
+    u64::try_from(token_id.0.clone()).ok()
+}
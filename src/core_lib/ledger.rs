@@ -0,0 +1,788 @@
+use candid::{Nat, Principal};
+use ic_cdk;
+
+use icrc_ledger_types::{
+    icrc::{generic_metadata_value::MetadataValue, generic_value::ICRC3Value},
+    icrc1::{
+        account::{Account, Subaccount},
+        transfer::{TransferArg, TransferError},
+    },
+    icrc2::transfer_from::{TransferFromArgs, TransferFromError},
+    icrc3::blocks::{GetBlocksRequest, GetBlocksResult},
+};
+
+use ic_ledger_types::{
+    account_balance, query_archived_blocks, query_blocks, transfer, AccountBalanceArgs,
+    AccountIdentifier, GetBlocksArgs, Memo, Operation, Subaccount as ICSubaccount, Tokens,
+    TransferArgs as ICRCTransferArgs, DEFAULT_FEE, DEFAULT_SUBACCOUNT,
+};
+
+#[cfg(test)]
+use std::{cell::RefCell, collections::BTreeMap};
+
+use super::env;
+use super::qtoken::QTOKEN_FEE;
+
+type Amount = u128;
+
+/// Ledger Transfer
+///
+/// A transfer read from a ledger block,accounts are account identifiers since ICP blocks do
+/// not record the owner of an account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedgerTransfer {
+    pub from: AccountIdentifier,
+    pub to: AccountIdentifier,
+    /// The account that made the transfer on behalf of `from`,None for direct transfers
+    pub spender: Option<AccountIdentifier>,
+    pub amount: Amount,
+}
+
+/// Ledger
+///
+/// The operations the vault performs on a token ledger,implemented for each kind of ledger
+/// an asset can be held in
+#[allow(async_fn_in_trait)]
+pub trait Ledger {
+    /// Transfers tokens out of a subaccount of the canister
+    ///
+    /// Returns the index of the transfer's block
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String>;
+
+    /// Transfers tokens from an account that approved the canister as spender
+    ///
+    /// Returns the index of the transfer's block
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String>;
+
+    /// Gets the balance of an account
+    async fn balance_of(&self, account: Account) -> Result<Amount, String>;
+
+    /// Gets the fee charged for a transfer
+    async fn fee(&self) -> Result<Amount, String>;
+
+    /// Gets the metadata of the token
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String>;
+
+    /// Gets the account tokens are minted from,None if the token has no minting account
+    async fn minting_account(&self) -> Result<Option<Account>, String>;
+
+    /// Gets the transfer recorded in a block,following the ledger's archives for blocks the
+    /// ledger no longer holds
+    ///
+    /// Returns None if the block records something other than a transfer (e.g a mint)
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String>;
+}
+
+/// Ledger Backend
+///
+/// The ledger an asset is held in,selected by the asset's type
+pub enum LedgerBackend {
+    Icrc(IcrcLedger),
+    Icp(IcpLedger),
+    QToken(QTokenLedger),
+    #[cfg(test)]
+    Mock(MockLedger),
+}
+
+impl Ledger for LedgerBackend {
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => {
+                ledger.transfer(amount, from_subaccount, to_account).await
+            }
+            LedgerBackend::Icp(ledger) => {
+                ledger.transfer(amount, from_subaccount, to_account).await
+            }
+            LedgerBackend::QToken(ledger) => {
+                ledger.transfer(amount, from_subaccount, to_account).await
+            }
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => {
+                ledger.transfer(amount, from_subaccount, to_account).await
+            }
+        }
+    }
+
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => {
+                ledger.transfer_from(amount, from_account, to_account).await
+            }
+            LedgerBackend::Icp(ledger) => {
+                ledger.transfer_from(amount, from_account, to_account).await
+            }
+            LedgerBackend::QToken(ledger) => {
+                ledger.transfer_from(amount, from_account, to_account).await
+            }
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => {
+                ledger.transfer_from(amount, from_account, to_account).await
+            }
+        }
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<Amount, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => ledger.balance_of(account).await,
+            LedgerBackend::Icp(ledger) => ledger.balance_of(account).await,
+            LedgerBackend::QToken(ledger) => ledger.balance_of(account).await,
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => ledger.balance_of(account).await,
+        }
+    }
+
+    async fn fee(&self) -> Result<Amount, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => ledger.fee().await,
+            LedgerBackend::Icp(ledger) => ledger.fee().await,
+            LedgerBackend::QToken(ledger) => ledger.fee().await,
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => ledger.fee().await,
+        }
+    }
+
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => ledger.metadata().await,
+            LedgerBackend::Icp(ledger) => ledger.metadata().await,
+            LedgerBackend::QToken(ledger) => ledger.metadata().await,
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => ledger.metadata().await,
+        }
+    }
+
+    async fn minting_account(&self) -> Result<Option<Account>, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => ledger.minting_account().await,
+            LedgerBackend::Icp(ledger) => ledger.minting_account().await,
+            LedgerBackend::QToken(ledger) => ledger.minting_account().await,
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => ledger.minting_account().await,
+        }
+    }
+
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String> {
+        match self {
+            LedgerBackend::Icrc(ledger) => ledger.get_transfer(block_index).await,
+            LedgerBackend::Icp(ledger) => ledger.get_transfer(block_index).await,
+            LedgerBackend::QToken(ledger) => ledger.get_transfer(block_index).await,
+            #[cfg(test)]
+            LedgerBackend::Mock(ledger) => ledger.get_transfer(block_index).await,
+        }
+    }
+}
+
+/// ICRC Ledger
+///
+/// A ledger implementing the ICRC-1 and ICRC-2 standards
+pub struct IcrcLedger {
+    pub ledger_id: Principal,
+}
+
+impl Ledger for IcrcLedger {
+    /// Uses the ICRC-1 transfer call,fee, memo and timestamp are not specified
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let args = TransferArg {
+            amount: Nat::from(amount),
+            from_subaccount,
+            to: to_account,
+            fee: None,
+            created_at_time: None,
+            memo: None,
+        };
+
+        let (result,): (Result<Nat, TransferError>,) =
+            ic_cdk::call(self.ledger_id, "icrc1_transfer", (args,))
+                .await
+                .map_err(|(_, message)| message)?;
+
+        result.map_err(|error| format!("{:?}", error))
+    }
+
+    /// Uses the ICRC-2 transfer from call,the allowance must be given to the default
+    /// subaccount of the canister
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let args = TransferFromArgs {
+            spender_subaccount: None,
+            from: from_account,
+            to: to_account,
+            amount: Nat::from(amount),
+            fee: None,
+            memo: None,
+            created_at_time: None,
+        };
+
+        let (result,): (Result<Nat, TransferFromError>,) =
+            ic_cdk::call(self.ledger_id, "icrc2_transfer_from", (args,))
+                .await
+                .map_err(|(_, message)| message)?;
+
+        result.map_err(|error| format!("{:?}", error))
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<Amount, String> {
+        let (balance,): (Nat,) = ic_cdk::call(self.ledger_id, "icrc1_balance_of", (account,))
+            .await
+            .map_err(|(_, message)| message)?;
+
+        _to_amount(balance)
+    }
+
+    async fn fee(&self) -> Result<Amount, String> {
+        let (fee,): (Nat,) = ic_cdk::call(self.ledger_id, "icrc1_fee", ())
+            .await
+            .map_err(|(_, message)| message)?;
+
+        _to_amount(fee)
+    }
+
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        let (metadata,): (Vec<(String, MetadataValue)>,) =
+            ic_cdk::call(self.ledger_id, "icrc1_metadata", ())
+                .await
+                .map_err(|(_, message)| message)?;
+
+        Ok(metadata)
+    }
+
+    async fn minting_account(&self) -> Result<Option<Account>, String> {
+        let (minting_account,): (Option<Account>,) =
+            ic_cdk::call(self.ledger_id, "icrc1_minting_account", ())
+                .await
+                .map_err(|(_, message)| message)?;
+
+        Ok(minting_account)
+    }
+
+    /// Uses the ICRC-3 get blocks call
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String> {
+        let args = vec![GetBlocksRequest {
+            start: Nat::from(block_index),
+            length: Nat::from(1u64),
+        }];
+
+        let (mut result,): (GetBlocksResult,) =
+            ic_cdk::call(self.ledger_id, "icrc3_get_blocks", (args,))
+                .await
+                .map_err(|(_, message)| message)?;
+
+        if let Some(archived) = result.archived_blocks.into_iter().next() {
+            (result,) = ic_cdk::call(
+                archived.callback.canister_id,
+                &archived.callback.method,
+                (archived.args,),
+            )
+            .await
+            .map_err(|(_, message)| message)?;
+        }
+
+        let Some(block) = result
+            .blocks
+            .into_iter()
+            .find(|block| block.id == block_index)
+        else {
+            return Err("Block not found".to_string());
+        };
+
+        Ok(_parse_icrc3_transfer(&block.block))
+    }
+}
+
+/// ICP Ledger
+///
+/// The ICP ledger,accessed through its ICRC-1 and ICRC-2 interface with its legacy account
+/// identifier interface kept for transfers to account identifiers
+pub struct IcpLedger {
+    pub ledger_id: Principal,
+}
+
+impl IcpLedger {
+    fn _icrc_ledger(&self) -> IcrcLedger {
+        IcrcLedger {
+            ledger_id: self.ledger_id,
+        }
+    }
+
+    /// Transfers ICP from a subaccount of the canister to an account identifier,for accounts
+    /// that can only be given as an account identifier (e.g exchange deposit addresses)
+    ///
+    /// Returns early if amount is 0,uses the default fee and memo(0)
+    pub async fn transfer_to_account_identifier(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to: AccountIdentifier,
+    ) -> Result<Nat, String> {
+        if amount == 0 {
+            return Ok(Nat::from(0u64));
+        }
+
+        let args = ICRCTransferArgs {
+            amount: Tokens::from_e8s(amount as u64),
+            memo: Memo(0),
+            fee: DEFAULT_FEE,
+            from_subaccount: Some(_to_ic_subaccount(from_subaccount)),
+            to,
+            created_at_time: None,
+        };
+
+        transfer(self.ledger_id, args)
+            .await
+            .map_err(|(_, message)| message)?
+            .map(Nat::from)
+            .map_err(|error| error.to_string())
+    }
+}
+
+impl Ledger for IcpLedger {
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        if amount == 0 {
+            return Ok(Nat::from(0u64));
+        }
+        self._icrc_ledger()
+            .transfer(amount, from_subaccount, to_account)
+            .await
+    }
+
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        if amount == 0 {
+            return Ok(Nat::from(0u64));
+        }
+        self._icrc_ledger()
+            .transfer_from(amount, from_account, to_account)
+            .await
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<Amount, String> {
+        let args = AccountBalanceArgs {
+            account: _to_account_identifier(account),
+        };
+
+        let balance = account_balance(self.ledger_id, args)
+            .await
+            .map_err(|(_, message)| message)?;
+
+        Ok(balance.e8s() as Amount)
+    }
+
+    async fn fee(&self) -> Result<Amount, String> {
+        self._icrc_ledger().fee().await
+    }
+
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        self._icrc_ledger().metadata().await
+    }
+
+    async fn minting_account(&self) -> Result<Option<Account>, String> {
+        self._icrc_ledger().minting_account().await
+    }
+
+    /// Uses the legacy query blocks call since the ICP ledger does not serve ICRC-3 blocks
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String> {
+        let args = GetBlocksArgs {
+            start: block_index,
+            length: 1,
+        };
+
+        let response = query_blocks(self.ledger_id, args.clone())
+            .await
+            .map_err(|(_, message)| message)?;
+
+        let archived_range = response
+            .archived_blocks
+            .into_iter()
+            .find(|range| range.start <= block_index && block_index < range.start + range.length);
+
+        let block = match archived_range {
+            Some(range) => query_archived_blocks(&range.callback, args)
+                .await
+                .map_err(|(_, message)| message)?
+                .map_err(|error| format!("{:?}", error))?
+                .blocks
+                .into_iter()
+                .next(),
+            None => response.blocks.into_iter().next(),
+        };
+
+        let Some(block) = block else {
+            return Err("Block not found".to_string());
+        };
+
+        match block.transaction.operation {
+            Some(Operation::Transfer {
+                from, to, amount, ..
+            }) => Ok(Some(LedgerTransfer {
+                from,
+                to,
+                spender: None,
+                amount: amount.e8s() as Amount,
+            })),
+            Some(Operation::TransferFrom {
+                from,
+                to,
+                spender,
+                amount,
+                ..
+            }) => Ok(Some(LedgerTransfer {
+                from,
+                to,
+                spender: Some(spender),
+                amount: amount.e8s() as Amount,
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// QToken Ledger
+///
+/// The QToken ledger built into the vault canister,served from the canister's stable memory so
+/// its calls complete without inter-canister calls
+pub struct QTokenLedger;
+
+impl Ledger for QTokenLedger {
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let from = Account {
+            owner: env::canister_id(),
+            subaccount: from_subaccount,
+        };
+        crate::_qtoken_transfer(None, from, to_account, amount, None, None, None)
+            .map(Nat::from)
+            .map_err(|error| format!("{:?}", error))
+    }
+
+    /// The vault acts on behalf of its caller,so moving the caller's own tokens needs no
+    /// allowance while moving other accounts' tokens spends the allowance given to the canister
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let spender =
+            (from_account.owner != env::caller()).then(|| Account::from(env::canister_id()));
+        crate::_qtoken_transfer(spender, from_account, to_account, amount, None, None, None)
+            .map(Nat::from)
+            .map_err(|error| format!("{:?}", error))
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<Amount, String> {
+        Ok(crate::_qtoken_balance_of(account))
+    }
+
+    async fn fee(&self) -> Result<Amount, String> {
+        Ok(QTOKEN_FEE)
+    }
+
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        Ok(crate::_qtoken_metadata())
+    }
+
+    async fn minting_account(&self) -> Result<Option<Account>, String> {
+        Ok(Some(Account::from(env::canister_id())))
+    }
+
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String> {
+        let Some(block) = crate::_qtoken_get_block(block_index) else {
+            return Err("Block not found".to_string());
+        };
+        Ok(_parse_icrc3_transfer(&block.0))
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // balances and allowances of the mock ledgers keyed by ledger id
+    static MOCK_BALANCES: RefCell<BTreeMap<(Principal, Account), Amount>> = const { RefCell::new(BTreeMap::new()) };
+
+    static MOCK_ALLOWANCES: RefCell<BTreeMap<(Principal, Account, Principal), Amount>> = const { RefCell::new(BTreeMap::new()) };
+
+    static MOCK_BLOCK_COUNT: RefCell<u64> = const { RefCell::new(0) };
+
+    // transfers recorded by the mock ledgers keyed by ledger id and block index
+    static MOCK_TRANSFERS: RefCell<BTreeMap<(Principal, u64), LedgerTransfer>> = const { RefCell::new(BTreeMap::new()) };
+
+    // minting accounts of the mock ledgers,transfers from them mint and transfers to them burn
+    static MOCK_MINTERS: RefCell<BTreeMap<Principal, Account>> = const { RefCell::new(BTreeMap::new()) };
+
+    // ledgers failing every transfer
+    static MOCK_FAILING_LEDGERS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Mock Ledger
+///
+/// An in-memory ledger without fees used in unit tests,the canister is the spender of
+/// every transfer from
+#[cfg(test)]
+pub struct MockLedger {
+    pub ledger_id: Principal,
+}
+
+#[cfg(test)]
+impl MockLedger {
+    /// Mints tokens to an account
+    pub fn _mint(&self, account: Account, amount: Amount) {
+        MOCK_BALANCES.with_borrow_mut(|balances| {
+            *balances.entry((self.ledger_id, account)).or_default() += amount;
+        });
+    }
+
+    /// Approves the canister to spend tokens of an account
+    pub fn _approve(&self, account: Account, spender: Principal, amount: Amount) {
+        MOCK_ALLOWANCES.with_borrow_mut(|allowances| {
+            allowances.insert((self.ledger_id, account, spender), amount);
+        });
+    }
+
+    /// Sets the minting account of the ledger
+    pub fn _set_minter(&self, minter: Account) {
+        MOCK_MINTERS.with_borrow_mut(|minters| minters.insert(self.ledger_id, minter));
+    }
+
+    /// Makes every transfer of the ledger fail or succeed again
+    pub fn _set_failing(&self, failing: bool) {
+        MOCK_FAILING_LEDGERS.with_borrow_mut(|ledgers| {
+            ledgers.retain(|ledger_id| *ledger_id != self.ledger_id);
+            if failing {
+                ledgers.push(self.ledger_id);
+            }
+        });
+    }
+
+    pub fn _balance(&self, account: Account) -> Amount {
+        MOCK_BALANCES.with_borrow(|balances| {
+            balances
+                .get(&(self.ledger_id, account))
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Transfers tokens between accounts without a spender,as a user transferring to the
+    /// canister would
+    pub fn _transfer(&self, amount: Amount, from: Account, to: Account) -> Result<Nat, String> {
+        self._move(amount, from, to, None)
+    }
+
+    fn _move(
+        &self,
+        amount: Amount,
+        from: Account,
+        to: Account,
+        spender: Option<Account>,
+    ) -> Result<Nat, String> {
+        if MOCK_FAILING_LEDGERS.with_borrow(|ledgers| ledgers.contains(&self.ledger_id)) {
+            return Err("Ledger unavailable".to_string());
+        }
+        let minter = MOCK_MINTERS.with_borrow(|minters| minters.get(&self.ledger_id).copied());
+        if minter != Some(from) {
+            let from_balance = self._balance(from);
+            if from_balance < amount {
+                return Err("Insufficient funds".to_string());
+            }
+            MOCK_BALANCES.with_borrow_mut(|balances| {
+                balances.insert((self.ledger_id, from), from_balance - amount)
+            });
+        }
+        if minter != Some(to) {
+            self._mint(to, amount);
+        }
+        let block_index = MOCK_BLOCK_COUNT.with_borrow_mut(|count| {
+            *count += 1;
+            *count - 1
+        });
+        if minter != Some(from) && minter != Some(to) {
+            let transfer = LedgerTransfer {
+                from: _to_account_identifier(from),
+                to: _to_account_identifier(to),
+                spender: spender.map(_to_account_identifier),
+                amount,
+            };
+            MOCK_TRANSFERS.with_borrow_mut(|transfers| {
+                transfers.insert((self.ledger_id, block_index), transfer)
+            });
+        }
+        Ok(Nat::from(block_index))
+    }
+}
+
+#[cfg(test)]
+impl Ledger for MockLedger {
+    async fn transfer(
+        &self,
+        amount: Amount,
+        from_subaccount: Option<Subaccount>,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let from = Account {
+            owner: env::canister_id(),
+            subaccount: from_subaccount,
+        };
+        self._move(amount, from, to_account, None)
+    }
+
+    async fn transfer_from(
+        &self,
+        amount: Amount,
+        from_account: Account,
+        to_account: Account,
+    ) -> Result<Nat, String> {
+        let spender = env::canister_id();
+        let key = (self.ledger_id, from_account, spender);
+        let allowance = MOCK_ALLOWANCES
+            .with_borrow(|allowances| allowances.get(&key).copied().unwrap_or_default());
+        if allowance < amount {
+            return Err("Insufficient allowance".to_string());
+        }
+        let block_index = self._move(amount, from_account, to_account, Some(spender.into()))?;
+        MOCK_ALLOWANCES.with_borrow_mut(|allowances| allowances.insert(key, allowance - amount));
+        Ok(block_index)
+    }
+
+    async fn balance_of(&self, account: Account) -> Result<Amount, String> {
+        Ok(self._balance(account))
+    }
+
+    async fn fee(&self) -> Result<Amount, String> {
+        Ok(0)
+    }
+
+    async fn metadata(&self) -> Result<Vec<(String, MetadataValue)>, String> {
+        Ok(vec![
+            MetadataValue::entry("icrc1:symbol", "MOCK"),
+            MetadataValue::entry("icrc1:name", "Mock Token"),
+            MetadataValue::entry("icrc1:decimals", Nat::from(8u64)),
+            MetadataValue::entry("icrc1:fee", Nat::from(0u64)),
+        ])
+    }
+
+    async fn minting_account(&self) -> Result<Option<Account>, String> {
+        Ok(MOCK_MINTERS.with_borrow(|minters| minters.get(&self.ledger_id).copied()))
+    }
+
+    async fn get_transfer(&self, block_index: u64) -> Result<Option<LedgerTransfer>, String> {
+        if block_index >= MOCK_BLOCK_COUNT.with_borrow(|count| *count) {
+            return Err("Block not found".to_string());
+        }
+        Ok(MOCK_TRANSFERS
+            .with_borrow(|transfers| transfers.get(&(self.ledger_id, block_index)).copied()))
+    }
+}
+
+fn _to_amount(value: Nat) -> Result<Amount, String> {
+    u128::try_from(value.0).map_err(|_| "Amount overflow".to_string())
+}
+
+/// Gets the account identifier of an account
+pub fn _to_account_identifier(account: Account) -> AccountIdentifier {
+    AccountIdentifier::new(&account.owner, &_to_ic_subaccount(account.subaccount))
+}
+
+/// Parses a transfer out of an ICRC-3 block,blocks either name their operation in the `op`
+/// field of their transaction or in their `btype` (e.g "1xfer" or "2xfer")
+fn _parse_icrc3_transfer(block: &ICRC3Value) -> Option<LedgerTransfer> {
+    let ICRC3Value::Map(block) = block else {
+        return None;
+    };
+    let Some(ICRC3Value::Map(tx)) = block.get("tx") else {
+        return None;
+    };
+
+    let operation = match (tx.get("op"), block.get("btype")) {
+        (Some(ICRC3Value::Text(operation)), _) => operation.as_str(),
+        (_, Some(ICRC3Value::Text(block_type))) => block_type.trim_start_matches(char::is_numeric),
+        _ => return None,
+    };
+    if operation != "xfer" {
+        return None;
+    }
+
+    let Some(ICRC3Value::Nat(amount)) = tx.get("amt") else {
+        return None;
+    };
+    let spender = match tx.get("spender") {
+        Some(spender) => Some(_parse_icrc3_account(spender)?),
+        None => None,
+    };
+
+    Some(LedgerTransfer {
+        from: _parse_icrc3_account(tx.get("from")?)?,
+        to: _parse_icrc3_account(tx.get("to")?)?,
+        spender,
+        amount: u128::try_from(amount.0.clone()).ok()?,
+    })
+}
+
+/// Parses an ICRC-3 account,an array of the owner and optionally the subaccount
+fn _parse_icrc3_account(value: &ICRC3Value) -> Option<AccountIdentifier> {
+    let ICRC3Value::Array(parts) = value else {
+        return None;
+    };
+    let Some(ICRC3Value::Blob(owner)) = parts.first() else {
+        return None;
+    };
+    let subaccount = match parts.get(1) {
+        Some(ICRC3Value::Blob(subaccount)) => {
+            Some(Subaccount::try_from(subaccount.as_slice()).ok()?)
+        }
+        Some(_) => return None,
+        None => None,
+    };
+
+    Some(_to_account_identifier(Account {
+        owner: Principal::try_from_slice(owner).ok()?,
+        subaccount,
+    }))
+}
+
+fn _to_ic_subaccount(sub: Option<Subaccount>) -> ICSubaccount {
+    match sub {
+        Some(res) => ICSubaccount(res),
+        None => DEFAULT_SUBACCOUNT,
+    }
+}
@@ -1,23 +1,102 @@
-use candid::{CandidType, Decode, Encode};
+use candid::{CandidType, Decode, Principal};
 use ic_stable_structures::{storable::Bound, Storable};
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 
 use serde::Deserialize;
 
+use super::env;
+use super::icrc7::TokenId;
+use super::versioned::{_from_versioned_bytes, _to_versioned_bytes, Versioned, UNVERSIONED};
+use crate::types::{AssetId, INITIAL_ASSET_ID};
+
 type Amount = u128;
 type Time = u64;
 
+/// Identifier of a lock span in the lock span table
+pub type SpanId = u64;
+
 pub const _ONE_BASIS_POINT: u64 = 1000;
 
 pub const _ONE_PERCENT: u64 = 100_000;
 
 pub const _BASE_UNITS: Amount = 1_000_000_000;
 
-const YEAR: Time = 31_536_000_000_000_000;
+pub const YEAR: Time = 31_536_000_000_000_000;
 
 const MONTH: Time = 2_628_000_000_000_000;
 
+pub const DAY: Time = 86_400_000_000_000;
+
+/// The span of liquidity provided through lending,it is not part of the lock span table
+/// and can not be locked into
+pub const INSTANT_SPAN_ID: SpanId = 0;
+
+/// Default share of fees paid to locked spans,the rest goes to the instant span
+pub const DEFAULT_LOCKED_FEES_SHARE: u64 = 40 * _ONE_PERCENT;
+
+/// Default window over which incoming fees are credited to the spans
+pub const DEFAULT_FEE_STREAM_WINDOW: Time = DAY;
+
+/// Default early unlock penalty of a span,the admin can lower it down to zero
+pub const DEFAULT_EARLY_UNLOCK_PENALTY: u64 = 10 * _ONE_PERCENT;
+
+/// Lock Span Config
+///
+/// An entry of the lock span table
+#[derive(Copy, Clone, Deserialize, Debug, CandidType, PartialEq, Eq)]
+pub struct LockSpanConfig {
+    /// The duration tokens are locked for in this span
+    pub duration: Time,
+    /// The weight of the span when sharing the locked spans' fees
+    pub weight: u64,
+    /// Only enabled spans accept new locks
+    pub enabled: bool,
+    /// The maximum amount that can be locked in the span
+    pub max_capacity: Option<Amount>,
+}
+
+impl Storable for LockSpanConfig {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for LockSpanConfig {
+    const VERSION: u8 = 1;
+}
+
+/// Default Lock Spans
+///
+/// The lock spans available before the lock span table was introduced,their ids
+/// match the number of months tokens are locked for
+pub fn default_lock_spans() -> Vec<(SpanId, LockSpanConfig)> {
+    [(2, 2 * MONTH), (6, 6 * MONTH), (12, YEAR)]
+        .into_iter()
+        .map(|(months, duration)| {
+            (
+                months,
+                LockSpanConfig {
+                    duration,
+                    weight: months,
+                    enabled: true,
+                    max_capacity: None,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Lock Span
+///
+/// The fixed lock spans used before the lock span table was introduced,
+/// only kept to migrate locks stored with them
 #[derive(Copy, Clone, Deserialize, Debug, CandidType, PartialEq, Eq)]
 pub enum LockSpan {
     Instant,
@@ -26,37 +105,292 @@ pub enum LockSpan {
     Year,
 }
 
+impl LockSpan {
+    /// The id of the span in the lock span table
+    pub fn span_id(&self) -> SpanId {
+        match self {
+            LockSpan::Instant => INSTANT_SPAN_ID,
+            LockSpan::Month2 => 2,
+            LockSpan::Month6 => 6,
+            LockSpan::Year => 12,
+        }
+    }
+}
+
+/// Expiry Action
+///
+/// What happens to a lock once its expiry time is reached
+#[derive(Copy, Clone, Deserialize, Debug, CandidType, PartialEq, Eq, Default)]
+pub enum ExpiryAction {
+    /// The lock is kept as is until it is unlocked
+    #[default]
+    Keep,
+    /// The lock is locked again for the same span
+    Renew,
+    /// The lock is locked again in the instant span with its earnings,earning like lent
+    /// liquidity and available to unlock at any time
+    MoveToInstant,
+}
+
+/// Vesting Schedule
+///
+/// The principal of a vesting lock unlocks linearly from its start time to its end time,
+/// nothing can be claimed before the cliff time
+#[derive(Copy, Clone, Deserialize, Debug, CandidType, PartialEq, Eq)]
+pub struct VestingSchedule {
+    pub start_time: Time,
+    pub cliff_time: Time,
+    pub end_time: Time,
+    /// The amount of principal already claimed
+    pub claimed: Amount,
+}
+
+impl VestingSchedule {
+    /// Vested Amount Function
+    ///
+    /// Params
+    /// - total: Amount - The total principal of the lock,including the amount already claimed
+    /// - current_time: Time - The time the vested amount is calculated at
+    ///
+    /// Returns
+    /// - Amount - The amount of principal vested at the current time,including the amount
+    ///   already claimed
+    pub fn _vested_amount(&self, total: Amount, current_time: Time) -> Amount {
+        if current_time < self.cliff_time {
+            return 0;
+        }
+        if current_time >= self.end_time {
+            return total;
+        }
+        (total * (current_time - self.start_time) as u128)
+            / (self.end_time - self.start_time) as u128
+    }
+}
+
+/// Vesting Grant
+///
+/// A vesting lock created by the admin
+#[derive(Copy, Clone, Deserialize, Debug, CandidType)]
+pub struct VestingGrant {
+    /// The owner of the vesting lock
+    pub beneficiary: Principal,
+    pub amount: Amount,
+    /// The span the lock earns fees in
+    pub span_id: SpanId,
+    pub start_time: Time,
+    pub cliff_time: Time,
+    pub end_time: Time,
+}
+
+#[derive(Deserialize, CandidType, Debug, Copy, Clone)]
+pub struct EarlyUnlockPreview {
+    /// The amount of the lock's principal returned
+    pub principal_returned: Amount,
+    /// The amount of the lock's principal forfeited to the remaining lockers
+    pub penalty: Amount,
+    /// The earnings accrued by the lock
+    pub earnings: Amount,
+}
+
 #[derive(Deserialize, CandidType, Debug, Copy, Clone)]
 pub struct LockDetails {
-    pub stake_span: LockSpan,
+    pub span_id: SpanId,
     pub amount: Amount,
     pub expiry_time: Time,
     pub pre_earnings: Amount,
+    /// When set, earnings are periodically rolled into `amount` instead of being claimable
+    #[serde(default)]
+    pub compounding: bool,
+    /// The id of the ICRC-7 token representing the lock,instant locks are not tokenized
+    #[serde(default)]
+    pub token_id: Option<TokenId>,
+    /// What happens to the lock once its expiry time is reached
+    #[serde(default)]
+    pub expiry_action: ExpiryAction,
+    /// The principal that paid for the lock when it was created on behalf of its owner
+    #[serde(default)]
+    pub funded_by: Option<Principal>,
+    /// The vesting schedule of locks created as vesting grants
+    #[serde(default)]
+    pub vesting: Option<VestingSchedule>,
+    /// The collateral asset whose virtual tokens are locked
+    #[serde(default)]
+    pub asset_id: AssetId,
+}
+
+impl LockDetails {
+    /// Split Lock Function
+    ///
+    /// Splits an amount off the lock into a new lock with the same span and expiry time,
+    /// the new lock gets its own token when inserted.Pre earnings are split in the same
+    /// proportion so the earnings of both locks add up to the earnings of the original lock
+    ///
+    /// Pre earnings of each part are capped by what the part has earned in total,so rounding
+    /// can not leave a lock with pre earnings above its earnings
+    ///
+    /// Params
+    /// - amount: Amount - The amount split off,must be less than the lock's amount
+    /// - lifetime_earnings_per_token: Amount - The current lifetime earnings per token of the span
+    ///
+    /// Returns
+    /// - LockDetails - The lock split off
+    pub fn _split(&mut self, amount: Amount, lifetime_earnings_per_token: Amount) -> LockDetails {
+        let remaining_amount = self.amount - amount;
+
+        let pre_earnings = ((self.pre_earnings * amount) / self.amount)
+            .min((amount * lifetime_earnings_per_token) / base_units());
+        let remaining_pre_earnings = (self.pre_earnings - pre_earnings)
+            .min((remaining_amount * lifetime_earnings_per_token) / base_units());
+
+        self.amount = remaining_amount;
+        self.pre_earnings = remaining_pre_earnings;
+
+        LockDetails {
+            amount,
+            pre_earnings,
+            token_id: None,
+            ..*self
+        }
+    }
+
+    /// Renew Lock Function
+    ///
+    /// Locks the lock again for the same span from its expiry time,as many times as needed
+    /// for the new expiry time to be in the future
+    ///
+    /// Params
+    /// - span_duration: Time - The duration of the lock's span,must not be 0
+    /// - current_time: Time - The time of the renewal
+    pub fn _renew(&mut self, span_duration: Time, current_time: Time) {
+        if current_time < self.expiry_time {
+            return;
+        }
+        let periods = (current_time - self.expiry_time) / span_duration + 1;
+        self.expiry_time += periods * span_duration;
+    }
+
+    /// Merge Lock Function
+    ///
+    /// Merges another lock of the same span into this lock,taking the later expiry time
+    ///
+    /// Params
+    /// - other: LockDetails - The lock merged in
+    pub fn _merge(&mut self, other: LockDetails) {
+        self.amount += other.amount;
+        self.pre_earnings += other.pre_earnings;
+        self.expiry_time = self.expiry_time.max(other.expiry_time);
+    }
 }
 
 impl Storable for LockDetails {
+    // maps created with a smaller bound are migrated to the v2 layout on init,which allows
+    // the bound to grow
     const BOUND: Bound = Bound::Bounded {
-        max_size: 74,
+        max_size: 512,
         is_fixed_size: false,
     };
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        _from_versioned_bytes(bytes.as_ref())
     }
 
     fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+        _to_versioned_bytes(self)
     }
 }
 
-#[derive(CandidType, Deserialize, Default, Clone)]
+impl Versioned for LockDetails {
+    const VERSION: u8 = 1;
+
+    fn _decode_version(version: u8, payload: &[u8]) -> Self {
+        match version {
+            // locks stored before the lock span table reference a fixed lock span
+            UNVERSIONED => Decode!(payload, Self)
+                .unwrap_or_else(|_| Decode!(payload, LegacyLockDetails).unwrap().into()),
+            _ => panic!("Unsupported lock details version {}", version),
+        }
+    }
+}
+
+#[derive(Deserialize, CandidType)]
+struct LegacyLockDetails {
+    stake_span: LockSpan,
+    amount: Amount,
+    expiry_time: Time,
+    pre_earnings: Amount,
+    compounding: Option<bool>,
+}
+
+impl From<LegacyLockDetails> for LockDetails {
+    fn from(legacy: LegacyLockDetails) -> Self {
+        LockDetails {
+            span_id: legacy.stake_span.span_id(),
+            amount: legacy.amount,
+            expiry_time: legacy.expiry_time,
+            pre_earnings: legacy.pre_earnings,
+            compounding: legacy.compounding.unwrap_or_default(),
+            token_id: None,
+            expiry_action: ExpiryAction::Keep,
+            funded_by: None,
+            vesting: None,
+            asset_id: INITIAL_ASSET_ID,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+#[serde(default)]
 pub struct Vault {
     pub debt: Amount,
     pub free_liquidity: Amount,
     pub lifetime_fees: Amount,
-    pub span0_details: LockDurationDetails,
-    pub span2_details: LockDurationDetails,
-    pub span6_details: LockDurationDetails,
-    pub span12_details: LockDurationDetails,
+    /// Share of fees paid to the locked spans,the rest is paid to the instant span
+    pub locked_fees_share: u64,
+    /// Details of each span keyed by span id,including the instant span
+    pub spans: BTreeMap<SpanId, LockDurationDetails>,
+    /// Fees received but not yet credited to the spans
+    pub fee_stream: FeeStream,
+}
+
+impl Default for Vault {
+    fn default() -> Self {
+        Vault {
+            debt: 0,
+            free_liquidity: 0,
+            lifetime_fees: 0,
+            locked_fees_share: DEFAULT_LOCKED_FEES_SHARE,
+            spans: BTreeMap::new(),
+            fee_stream: FeeStream::default(),
+        }
+    }
+}
+
+/// Fee Stream
+///
+/// Incoming fees are credited to the spans linearly over a window instead of at once,
+/// so liquidity provided right before a fee is paid only earns the part of the fee
+/// credited after it was provided
+#[derive(Copy, Clone, Deserialize, CandidType, Debug)]
+#[serde(default)]
+pub struct FeeStream {
+    /// Fees received but not yet credited to the spans
+    pub undistributed_fees: Amount,
+    /// Time the stream was last advanced
+    pub last_update: Time,
+    /// Time by which all undistributed fees are credited
+    pub end_time: Time,
+    /// Window over which an incoming fee is credited,0 credits fees at once
+    pub window: Time,
+}
+
+impl Default for FeeStream {
+    fn default() -> Self {
+        FeeStream {
+            undistributed_fees: 0,
+            last_update: 0,
+            end_time: 0,
+            window: DEFAULT_FEE_STREAM_WINDOW,
+        }
+    }
 }
 
 impl Vault {
@@ -66,25 +400,36 @@ impl Vault {
     /// Params
     ///  - Amount :The amount of asset being put staked or deposited
     ///  - Current Lifetime Earnings :The total amount since first epoch of asset  received as fees to leverage provider from traders trading with leverage
-    ///  - Stake Span :The specific staking duration
+    ///  - Span Id :The id of the specific staking span
+    ///  - Span Duration :The duration of the specific staking span
     ///
     /// Returns
-    ///  - StakeDetails :The details of the newly created stake
-    pub fn _create_lock(&mut self, amount: Amount, stake_span: LockSpan) -> LockDetails {
-        let (span_lifetime_earnings_per_token, span_init_total_locked, expiry_time) =
-            self._update_specific_span_details(amount, stake_span, true);
+    ///  - StakeDetails :The details of the newly created stake,its asset is left to the
+    ///    initial asset and must be set by the caller for other assets
+    pub fn _create_lock(
+        &mut self,
+        amount: Amount,
+        span_id: SpanId,
+        span_duration: Time,
+    ) -> LockDetails {
+        let span_lifetime_earnings_per_token =
+            self._update_specific_span_details(amount, span_id, true);
 
-        let pre_earnings = if span_init_total_locked == 0 {
-            0
-        } else {
-            (amount * span_lifetime_earnings_per_token) / base_units()
-        };
+        let pre_earnings = (amount * span_lifetime_earnings_per_token) / base_units();
+
+        let expiry_time = env::time() + span_duration;
 
         let stake_details = LockDetails {
-            stake_span,
+            span_id,
             amount,
             pre_earnings,
             expiry_time,
+            compounding: false,
+            token_id: None,
+            expiry_action: ExpiryAction::Keep,
+            funded_by: None,
+            vesting: None,
+            asset_id: INITIAL_ASSET_ID,
         };
 
         return stake_details;
@@ -92,19 +437,112 @@ impl Vault {
 
     /// Update Fees Across Span Function
     ///
-    /// Updates the fees for all staking duration spans (instant, 2 months, 6 months, and 1 year)
-    /// using the current lifetime fees accumulated in the vault.
+    /// Distributes a fee across the instant span and the spans of the lock span table
     ///
     /// The function updates:
-    /// - Instant staking span (span0) with no duration multiplier
-    /// - 2 month staking span (span2) with 2x duration multiplier  
-    /// - 6 month staking span (span6) with 6x duration multiplier
-    /// - 12 month staking span (span12) with 12x duration multiplier
-    pub fn _update_fees_across_span(&mut self, fee_earned: Amount) {
-        self.span0_details._update_earnings(fee_earned, None);
-        self.span2_details._update_earnings(fee_earned, Some(2));
-        self.span6_details._update_earnings(fee_earned, Some(6));
-        self.span0_details._update_earnings(fee_earned, Some(12));
+    /// - Instant staking span with the share of fees not paid to the locked spans
+    /// - Each locked span with its weight's share of the locked spans' fees
+    ///
+    /// A span with nothing locked can not earn,its share of the fee goes to the instant span and
+    /// fees the instant span can not earn are kept in the fee stream
+    pub fn _update_fees_across_span(
+        &mut self,
+        fee_earned: Amount,
+        lock_spans: &BTreeMap<SpanId, LockSpanConfig>,
+    ) {
+        let locked_fees = _percentage128(self.locked_fees_share, fee_earned);
+        let total_weight: u128 = lock_spans
+            .values()
+            .map(|config| config.weight as u128)
+            .sum();
+
+        let mut instant_fees = fee_earned - locked_fees;
+
+        for (span_id, config) in lock_spans {
+            if total_weight == 0 {
+                instant_fees += locked_fees;
+                break;
+            }
+            let span_fees = (locked_fees * config.weight as u128) / total_weight;
+
+            if !self._span_details_mut(*span_id)._update_earnings(span_fees) {
+                instant_fees += span_fees;
+            }
+        }
+
+        self._credit_instant_span(instant_fees);
+    }
+
+    /// Stream Fees Function
+    ///
+    /// Adds a fee to the fee stream,the new fee is credited over the stream's window and the
+    /// stream's end time moves to the amount weighted average of the fees' end times
+    ///
+    /// Params
+    /// - fee_earned: Amount - The fee received
+    /// - current_time: Time - The time the fee was received
+    /// - lock_spans: &BTreeMap<SpanId, LockSpanConfig> - The lock span table
+    pub fn _stream_fees(
+        &mut self,
+        fee_earned: Amount,
+        current_time: Time,
+        lock_spans: &BTreeMap<SpanId, LockSpanConfig>,
+    ) {
+        self._advance_fee_stream(current_time, lock_spans);
+
+        if self.fee_stream.window == 0 {
+            self._update_fees_across_span(fee_earned, lock_spans);
+            return;
+        }
+
+        let stream = &mut self.fee_stream;
+        let pending_fees = stream.undistributed_fees;
+        let remaining_time = stream.end_time.saturating_sub(current_time) as u128;
+        let total_fees = pending_fees + fee_earned;
+        if total_fees == 0 {
+            return;
+        }
+
+        // the end time is weighted by amount so frequent small fees can not keep pushing back
+        // the payout of the fees already streaming
+        let weighted_duration =
+            (pending_fees * remaining_time + fee_earned * stream.window as u128) / total_fees;
+
+        stream.undistributed_fees = total_fees;
+        stream.end_time = current_time + weighted_duration as Time;
+    }
+
+    /// Advance Fee Stream Function
+    ///
+    /// Credits the part of the undistributed fees vested since the stream was last advanced
+    /// to the spans,all of them once the stream's end time is reached
+    ///
+    /// Params
+    /// - current_time: Time - The time the stream is advanced to
+    /// - lock_spans: &BTreeMap<SpanId, LockSpanConfig> - The lock span table
+    pub fn _advance_fee_stream(
+        &mut self,
+        current_time: Time,
+        lock_spans: &BTreeMap<SpanId, LockSpanConfig>,
+    ) {
+        let stream = &mut self.fee_stream;
+        if current_time <= stream.last_update {
+            return;
+        }
+
+        let released_fees = if current_time >= stream.end_time {
+            stream.undistributed_fees
+        } else {
+            (stream.undistributed_fees * (current_time - stream.last_update) as u128)
+                / (stream.end_time - stream.last_update) as u128
+        };
+
+        stream.undistributed_fees -= released_fees;
+        stream.last_update = current_time;
+
+        if released_fees != 0 {
+            self._update_fees_across_span(released_fees, lock_spans);
+        }
     }
 
     /// Calculate Stake Earnings Function
@@ -118,29 +556,197 @@ impl Vault {
     /// Returns
     /// - Amount - The total earnings for this stake (current earnings minus pre-earnings)
     pub fn _calc_lock_earnings(&self, ref_stake: LockDetails) -> Amount {
-        let lifetime_earnings_per_token;
-        match ref_stake.stake_span {
-            LockSpan::Instant => {
-                lifetime_earnings_per_token = self.span0_details.lifetime_earnings_per_token
-            }
-            LockSpan::Month2 => {
-                lifetime_earnings_per_token = self.span2_details.lifetime_earnings_per_token
-            }
-            LockSpan::Month6 => {
-                lifetime_earnings_per_token = self.span6_details.lifetime_earnings_per_token
-            }
-            LockSpan::Year => {
-                lifetime_earnings_per_token = self.span12_details.lifetime_earnings_per_token
-            }
-        };
+        let lifetime_earnings_per_token = self
+            ._span_details(ref_stake.span_id)
+            .lifetime_earnings_per_token;
 
         let amount_earned = (ref_stake.amount * lifetime_earnings_per_token) / base_units();
 
-        let user_earnings = amount_earned - ref_stake.pre_earnings;
+        // pre earnings above the earnings mean the lock's accounting is broken,trap instead of
+        // hiding it
+        let user_earnings = amount_earned
+            .checked_sub(ref_stake.pre_earnings)
+            .expect("Lock pre earnings exceed its earnings");
 
         return user_earnings;
     }
 
+    /// Compound Lock Function
+    ///
+    /// Rolls the earnings accrued by a lock into its amount,the span's total locked is increased
+    /// by the same amount and the lock's pre earnings checkpoint is moved to the current
+    /// lifetime earnings per token of the span
+    ///
+    /// Params
+    /// - ref_lock: LockDetails - The lock to compound (updated in place)
+    ///
+    /// Returns
+    /// - Amount - The earnings added to the lock's amount
+    pub fn _compound_lock(&mut self, ref_lock: &mut LockDetails) -> Amount {
+        let earnings = self._calc_lock_earnings(*ref_lock);
+
+        if earnings == 0 {
+            return 0;
+        }
+
+        let span_details = self._span_details_mut(ref_lock.span_id);
+        span_details.update_total_locked(earnings, true);
+
+        ref_lock.amount += earnings;
+        ref_lock.pre_earnings =
+            (ref_lock.amount * span_details.lifetime_earnings_per_token) / base_units();
+
+        earnings
+    }
+
+    /// Settle Lock Function
+    ///
+    /// Moves the lock's earnings checkpoint to the current lifetime earnings per token of its span
+    ///
+    /// Params
+    /// - ref_lock: LockDetails - The lock to settle (updated in place)
+    ///
+    /// Returns
+    /// - Amount - The earnings accrued by the lock until now,to be paid out by the caller
+    pub fn _settle_lock(&self, ref_lock: &mut LockDetails) -> Amount {
+        let earnings = self._calc_lock_earnings(*ref_lock);
+        ref_lock.pre_earnings += earnings;
+
+        earnings
+    }
+
+    /// Increase Lock Function
+    ///
+    /// Adds an amount to an existing lock,the lock's pre earnings are increased by the earnings
+    /// already accumulated per token so the lock's accrued earnings are left unchanged
+    ///
+    /// Note :earnings should be settled before increasing the lock so the added amount only
+    /// earns from the current time
+    ///
+    /// Params
+    /// - ref_lock: LockDetails - The lock to increase (updated in place)
+    /// - amount: Amount - The amount added to the lock
+    pub fn _increase_lock(&mut self, ref_lock: &mut LockDetails, amount: Amount) {
+        let span_details = self._span_details_mut(ref_lock.span_id);
+        span_details.update_total_locked(amount, true);
+
+        ref_lock.amount += amount;
+        ref_lock.pre_earnings += (amount * span_details.lifetime_earnings_per_token) / base_units();
+    }
+
+    /// Move Lock Function
+    ///
+    /// Moves a lock's amount from its current span to a new span,the lock's expiry time restarts
+    /// from the current time and its earnings checkpoint is reset to the new span
+    ///
+    /// Note :earnings accrued in the previous span should be settled before moving the lock
+    ///
+    /// Params
+    /// - ref_lock: LockDetails - The lock to move
+    /// - new_span_id: SpanId - The id of the span the lock is moved to
+    /// - new_span_duration: Time - The duration of the span the lock is moved to
+    ///
+    /// Returns
+    /// - LockDetails - The details of the moved lock
+    pub fn _move_lock(
+        &mut self,
+        ref_lock: LockDetails,
+        new_span_id: SpanId,
+        new_span_duration: Time,
+    ) -> LockDetails {
+        self._open_lock(ref_lock);
+
+        let mut new_lock = self._create_lock(ref_lock.amount, new_span_id, new_span_duration);
+        new_lock.compounding = ref_lock.compounding;
+        new_lock.token_id = ref_lock.token_id;
+        new_lock.expiry_action = ref_lock.expiry_action;
+        new_lock.funded_by = ref_lock.funded_by;
+        new_lock.asset_id = ref_lock.asset_id;
+
+        new_lock
+    }
+
+    /// Calculate Early Unlock Penalty Function
+    ///
+    /// The penalty is the span's early unlock penalty applied to the lock's amount,scaled by the
+    /// fraction of the lock's duration that is still remaining
+    ///
+    /// Params
+    /// - ref_lock: LockDetails - The lock being unlocked early
+    /// - span_duration: Time - The duration of the lock's span
+    /// - current_time: Time - The time of the unlock
+    ///
+    /// Returns
+    /// - Amount - The amount of the lock's principal forfeited
+    pub fn _calc_early_unlock_penalty(
+        &self,
+        ref_lock: LockDetails,
+        span_duration: Time,
+        current_time: Time,
+    ) -> Amount {
+        let duration = span_duration;
+        if duration == 0 || current_time >= ref_lock.expiry_time {
+            return 0;
+        }
+
+        let remaining_time = (ref_lock.expiry_time - current_time).min(duration);
+
+        let span_details = self._span_details(ref_lock.span_id);
+        let max_penalty = _percentage128(span_details.early_unlock_penalty, ref_lock.amount);
+
+        (max_penalty * remaining_time as u128) / duration as u128
+    }
+
+    /// Distribute Penalty Function
+    ///
+    /// Distributes a penalty to the remaining lockers of a span through the span's lifetime earnings per token,
+    /// if the span has no remaining lockers the penalty is streamed like any other fee
+    ///
+    /// Penalties paid to remaining lockers skip the fee stream since a locked span can only be joined
+    /// for its full duration,so locking right before an early unlock to take part of its penalty is
+    /// not free the way lending right before a fee is
+    ///
+    /// Params
+    /// - span_id: SpanId - The id of the span the penalty was taken from
+    /// - penalty: Amount - The penalty to distribute
+    /// - current_time: Time - The time the penalty was taken
+    /// - lock_spans: &BTreeMap<SpanId, LockSpanConfig> - The lock span table
+    pub fn _distribute_penalty(
+        &mut self,
+        span_id: SpanId,
+        penalty: Amount,
+        current_time: Time,
+        lock_spans: &BTreeMap<SpanId, LockSpanConfig>,
+    ) {
+        if penalty == 0 {
+            return;
+        }
+        if !self._span_details_mut(span_id)._update_earnings(penalty) {
+            self._stream_fees(penalty, current_time, lock_spans);
+        }
+    }
+
+    /// Credits fees to the instant span,fees are kept in the fee stream while nothing is
+    /// lent so they are not lost
+    fn _credit_instant_span(&mut self, fees: Amount) {
+        if !self
+            ._span_details_mut(INSTANT_SPAN_ID)
+            ._update_earnings(fees)
+        {
+            self.fee_stream.undistributed_fees += fees;
+        }
+    }
+
+    /// Returns the details of a specific span
+    pub fn _span_details(&self, span_id: SpanId) -> LockDurationDetails {
+        self.spans.get(&span_id).cloned().unwrap_or_default()
+    }
+
+    /// Returns a mutable reference to the details of a specific span
+    pub fn _span_details_mut(&mut self, span_id: SpanId) -> &mut LockDurationDetails {
+        self.spans.entry(span_id).or_default()
+    }
+
     /// Close Stake Function
     ///
     /// Params
@@ -149,106 +755,111 @@ impl Vault {
     /// Returns
     ///  - Earnings :The amount earned by the particular stake for the entire staking duration
     pub fn _open_lock(&mut self, reference_stake: LockDetails) {
-        match reference_stake.stake_span {
-            LockSpan::Instant => self
-                .span0_details
-                .update_total_locked(reference_stake.amount, false),
-            LockSpan::Month2 => self
-                .span2_details
-                .update_total_locked(reference_stake.amount, false),
-            LockSpan::Month6 => self
-                .span6_details
-                .update_total_locked(reference_stake.amount, false),
-            LockSpan::Year => self
-                .span12_details
-                .update_total_locked(reference_stake.amount, false),
-        };
+        self._span_details_mut(reference_stake.span_id)
+            .update_total_locked(reference_stake.amount, false);
     }
 
     /// Update Asset Staking Details Function
 
     /// # Params
     /// * `amount` - The amount of tokens being staked or unstaked
-    /// * `specific_span` - The staking duration period (Instant, 2 Months, 6 Months, or 1 Year)
+    /// * `span_id` - The id of the staking span
     /// * `lock` - Boolean indicating if tokens are being locked (true) or unlocked (false)
     ///
     /// # Returns
-    /// A tuple containing:
     /// * The lifetime earnings per token for the specific stake duration
-    /// * The total amount of tokens locked in this stake duration before this update
-    /// * The expiry timestamp when these staked tokens can be withdrawn
-    ///
-    /// # Details
-    /// This function handles updating staking details when tokens are staked or unstaked.
-    /// For each stake duration, it:
-    /// 1. Records the current total locked amount
-    /// 2. Updates the staking details with the new amount
-    /// 3. Calculates the expiry time based on the stake duration
     pub fn _update_specific_span_details(
         &mut self,
         amount: Amount,
-        specific_span: LockSpan,
+        span_id: SpanId,
         lock: bool,
-    ) -> (Amount, Amount, Time) {
-        let span_lifetime_earnings_per_token;
-        let span_init_total_locked;
-        let expiry_time;
-
-        match specific_span {
-            LockSpan::Instant => {
-                span_init_total_locked = self.span0_details.total_locked;
-
-                span_lifetime_earnings_per_token =
-                    self.span0_details._lifetime_earnings_per_token();
-                self.span0_details.update_total_locked(amount, lock);
-                expiry_time = ic_cdk::api::time()
-            }
-            LockSpan::Month2 => {
-                span_init_total_locked = self.span2_details.total_locked;
-
-                span_lifetime_earnings_per_token =
-                    self.span2_details._lifetime_earnings_per_token();
-                self.span2_details.update_total_locked(amount, lock);
-                expiry_time = ic_cdk::api::time() + (2 * MONTH);
-            }
-            LockSpan::Month6 => {
-                span_init_total_locked = self.span6_details.total_locked;
+    ) -> Amount {
+        let span_details = self._span_details_mut(span_id);
 
-                span_lifetime_earnings_per_token =
-                    self.span6_details._lifetime_earnings_per_token();
-                self.span6_details.update_total_locked(amount, lock);
-                expiry_time = ic_cdk::api::time() + (6 * MONTH)
-            }
-            LockSpan::Year => {
-                span_init_total_locked = self.span12_details.total_locked;
-
-                span_lifetime_earnings_per_token =
-                    self.span12_details._lifetime_earnings_per_token();
-                self.span12_details.update_total_locked(amount, lock);
-                expiry_time = ic_cdk::api::time() + YEAR
-            }
-        }
+        span_details.update_total_locked(amount, lock);
 
-        return (
-            span_lifetime_earnings_per_token,
-            span_init_total_locked,
-            expiry_time,
-        );
+        span_details._lifetime_earnings_per_token()
     }
 }
 
 impl Storable for Vault {
     const BOUND: Bound = Bound::Unbounded;
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        _from_versioned_bytes(bytes.as_ref())
     }
 
     fn to_bytes(&self) -> Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap())
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for Vault {
+    const VERSION: u8 = 1;
+
+    fn _decode_version(version: u8, payload: &[u8]) -> Self {
+        match version {
+            // vaults stored before the lock span table hold a field for each fixed lock span
+            UNVERSIONED => Decode!(payload, Self)
+                .unwrap_or_else(|_| Decode!(payload, LegacyVault).unwrap().into()),
+            _ => panic!("Unsupported vault version {}", version),
+        }
+    }
+}
+
+#[derive(Deserialize, CandidType)]
+struct LegacyVault {
+    debt: Amount,
+    free_liquidity: Amount,
+    lifetime_fees: Amount,
+    span0_details: LegacyLockDurationDetails,
+    span2_details: LegacyLockDurationDetails,
+    span6_details: LegacyLockDurationDetails,
+    span12_details: LegacyLockDurationDetails,
+}
+
+#[derive(Deserialize, CandidType)]
+struct LegacyLockDurationDetails {
+    lifetime_earnings_per_token: Amount,
+    total_locked: Amount,
+    early_unlock_penalty: Option<u64>,
+}
+
+impl From<LegacyVault> for Vault {
+    fn from(legacy: LegacyVault) -> Self {
+        let spans = [
+            (LockSpan::Instant, legacy.span0_details),
+            (LockSpan::Month2, legacy.span2_details),
+            (LockSpan::Month6, legacy.span6_details),
+            (LockSpan::Year, legacy.span12_details),
+        ]
+        .into_iter()
+        .map(|(span, details)| {
+            (
+                span.span_id(),
+                LockDurationDetails {
+                    lifetime_earnings_per_token: details.lifetime_earnings_per_token,
+                    total_locked: details.total_locked,
+                    early_unlock_penalty: details
+                        .early_unlock_penalty
+                        .unwrap_or(DEFAULT_EARLY_UNLOCK_PENALTY),
+                },
+            )
+        })
+        .collect();
+
+        Vault {
+            debt: legacy.debt,
+            free_liquidity: legacy.free_liquidity,
+            lifetime_fees: legacy.lifetime_fees,
+            locked_fees_share: DEFAULT_LOCKED_FEES_SHARE,
+            spans,
+            fee_stream: FeeStream::default(),
+        }
     }
 }
 
-#[derive(Clone, Deserialize, CandidType, Default)]
+#[derive(Clone, Deserialize, CandidType)]
+#[serde(default)]
 pub struct LockDurationDetails {
     /// The total Amount earned by a single token since span creation
     pub lifetime_earnings_per_token: Amount,
@@ -256,6 +867,21 @@ pub struct LockDurationDetails {
     ///
     /// The total Amount of liquidity locked in that particular span
     pub total_locked: Amount,
+    /// Early Unlock Penalty
+    ///
+    /// The percentage of a lock's amount forfeited when unlocking right after locking,
+    /// decreases linearly to zero at expiry
+    pub early_unlock_penalty: u64,
+}
+
+impl Default for LockDurationDetails {
+    fn default() -> Self {
+        LockDurationDetails {
+            lifetime_earnings_per_token: 0,
+            total_locked: 0,
+            early_unlock_penalty: DEFAULT_EARLY_UNLOCK_PENALTY,
+        }
+    }
 }
 
 impl LockDurationDetails {
@@ -265,28 +891,18 @@ impl LockDurationDetails {
     /// Updates fees for a staking duration
     ///
     /// # Parameters
-    /// - `current_all_time_earnings`: latest fees received
-    /// - `span_share`: Optional share value for the staking duration
-
-    pub fn _update_earnings(&mut self, fees_earned: Amount, span_share: Option<u128>) {
-        let (percentage, share, total_share) = match span_share {
-            Some(value) => (40 * _ONE_PERCENT, value, 20),
-            None => (60 * _ONE_PERCENT, 1, 1),
-        };
-
-        let init_total_locked = if self.total_locked == 0 {
-            1
-        } else {
-            self.total_locked
-        };
-
-        // new earnings
-        let locked_new_earnings = _percentage128(percentage, fees_earned);
+    /// - `fees_earned`: fees paid to the span
+    ///
+    /// # Returns
+    /// False if the span has nothing locked and can not earn the fees
+    pub fn _update_earnings(&mut self, fees_earned: Amount) -> bool {
+        if self.total_locked == 0 {
+            return false;
+        }
 
-        let span_new_earnings_per_token =
-            (locked_new_earnings * share * base_units()) / (total_share * init_total_locked);
+        self.lifetime_earnings_per_token += (fees_earned * base_units()) / self.total_locked;
 
-        self.lifetime_earnings_per_token += span_new_earnings_per_token;
+        true
     }
     /// Updates stake duration details by modifying the total locked amount
     ///
@@ -305,7 +921,7 @@ impl LockDurationDetails {
     }
 }
 
-fn base_units() -> Amount {
+pub fn base_units() -> Amount {
     10u128.pow(12)
 }
 
@@ -1,2 +1,9 @@
 pub mod asset;
+pub mod env;
+pub mod icrc7;
+pub mod ledger;
 pub mod lock;
+pub mod qtoken;
+pub mod versioned;
+pub mod voting;
+pub mod yields;
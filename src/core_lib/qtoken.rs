@@ -0,0 +1,304 @@
+use candid::{CandidType, Nat, Principal};
+use ic_stable_structures::{storable::Bound, Storable};
+use icrc_ledger_types::icrc::generic_value::{ICRC3Map, ICRC3Value};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::versioned::{_from_versioned_bytes, _to_versioned_bytes, Versioned};
+
+type Amount = u128;
+type Time = u64;
+
+/// The fee charged by the built-in QToken ledger,QToken transfers are free
+pub const QTOKEN_FEE: Amount = 0;
+
+/// Max size of a memo in bytes
+pub const MAX_MEMO_SIZE: usize = 32;
+
+/// Max age of a transaction's created at time (24 hours)
+pub const TRANSACTION_WINDOW: Time = 86_400_000_000_000;
+
+/// Max drift between a transaction's created at time and the ledger's time (2 minutes)
+pub const PERMITTED_DRIFT: Time = 120_000_000_000;
+
+/// Max number of blocks returned by a single `icrc3_get_blocks` call
+pub const MAX_BLOCKS_PER_REQUEST: u64 = 100;
+
+/// The block types recorded by the ledger with the standard defining each
+pub const SUPPORTED_BLOCK_TYPES: [(&str, &str); 5] = [
+    (
+        "1burn",
+        "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-1/README.md",
+    ),
+    (
+        "1mint",
+        "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-1/README.md",
+    ),
+    (
+        "1xfer",
+        "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-1/README.md",
+    ),
+    (
+        "2approve",
+        "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-2/README.md",
+    ),
+    (
+        "2xfer",
+        "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-2/README.md",
+    ),
+];
+
+/// The standards implemented by the ledger with the url of each
+pub const SUPPORTED_STANDARDS: [(&str, &str); 3] = [
+    (
+        "ICRC-1",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-1",
+    ),
+    (
+        "ICRC-2",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-2",
+    ),
+    (
+        "ICRC-3",
+        "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3",
+    ),
+];
+
+/// Key of an account in the ledger's maps,accounts without a subaccount use the default
+/// subaccount so both forms of the default account share a balance
+pub type AccountKey = (Principal, Subaccount);
+
+pub fn _to_account_key(account: Account) -> AccountKey {
+    (account.owner, account.subaccount.unwrap_or_default())
+}
+
+/// QToken State
+///
+/// The metadata and counters of the built-in QToken ledger
+#[derive(Clone, Default, Deserialize, Debug, CandidType)]
+pub struct QTokenState {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: Amount,
+    /// Hash of the last block,None until the first block is recorded
+    pub last_block_hash: Option<[u8; 32]>,
+}
+
+impl Storable for QTokenState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for QTokenState {
+    const VERSION: u8 = 1;
+}
+
+/// QToken Allowance
+///
+/// An allowance given through `icrc2_approve`
+#[derive(Copy, Clone, Default, Deserialize, Debug, CandidType)]
+pub struct QTokenAllowance {
+    pub amount: Amount,
+    pub expires_at: Option<Time>,
+}
+
+impl QTokenAllowance {
+    /// Gets the amount that can be spent at `current_time`,expired allowances can not be spent
+    pub fn _spendable(&self, current_time: Time) -> Amount {
+        match self.expires_at {
+            Some(expires_at) if expires_at <= current_time => 0,
+            _ => self.amount,
+        }
+    }
+}
+
+impl Storable for QTokenAllowance {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for QTokenAllowance {
+    const VERSION: u8 = 1;
+}
+
+/// QToken Block
+///
+/// A block of the built-in QToken ledger in the ICRC-3 format
+#[derive(Clone, Deserialize, Debug, CandidType)]
+pub struct QTokenBlock(pub ICRC3Value);
+
+impl QTokenBlock {
+    /// Creates a block holding the transaction `tx` of type `block_type`
+    ///
+    /// # Arguments
+    /// * `block_type` - The ICRC-3 block type (e.g "1xfer")
+    /// * `tx` - The fields of the transaction
+    /// * `timestamp` - Time the block is created at
+    /// * `parent_hash` - Hash of the previous block,None for the first block
+    pub fn new(
+        block_type: &str,
+        tx: ICRC3Map,
+        timestamp: Time,
+        parent_hash: Option<[u8; 32]>,
+    ) -> Self {
+        let mut block = ICRC3Map::new();
+        if let Some(parent_hash) = parent_hash {
+            block.insert(
+                "phash".to_string(),
+                ICRC3Value::Blob(parent_hash.to_vec().into()),
+            );
+        }
+        block.insert(
+            "btype".to_string(),
+            ICRC3Value::Text(block_type.to_string()),
+        );
+        block.insert("ts".to_string(), ICRC3Value::Nat(Nat::from(timestamp)));
+        block.insert("tx".to_string(), ICRC3Value::Map(tx));
+        QTokenBlock(ICRC3Value::Map(block))
+    }
+
+    pub fn _hash(&self) -> [u8; 32] {
+        self.0.clone().hash()
+    }
+}
+
+impl Storable for QTokenBlock {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for QTokenBlock {
+    const VERSION: u8 = 1;
+}
+
+/// Encodes an account as an ICRC-3 value,the default subaccount is left out
+pub fn _account_value(account: Account) -> ICRC3Value {
+    let mut parts = vec![ICRC3Value::Blob(account.owner.as_slice().to_vec().into())];
+    if let Some(subaccount) = account
+        .subaccount
+        .filter(|subaccount| *subaccount != [0; 32])
+    {
+        parts.push(ICRC3Value::Blob(subaccount.to_vec().into()));
+    }
+    ICRC3Value::Array(parts)
+}
+
+/// Created At Time Error
+///
+/// Why a transaction's created at time was rejected
+pub enum CreatedAtTimeError {
+    TooOld,
+    CreatedInFuture { ledger_time: Time },
+}
+
+/// Checks that a transaction's created at time is within the transaction window
+pub fn _check_created_at_time(
+    created_at_time: Option<Time>,
+    current_time: Time,
+) -> Result<(), CreatedAtTimeError> {
+    let Some(created_at_time) = created_at_time else {
+        return Ok(());
+    };
+    if created_at_time + TRANSACTION_WINDOW + PERMITTED_DRIFT < current_time {
+        return Err(CreatedAtTimeError::TooOld);
+    }
+    if created_at_time > current_time + PERMITTED_DRIFT {
+        return Err(CreatedAtTimeError::CreatedInFuture {
+            ledger_time: current_time,
+        });
+    }
+    Ok(())
+}
+
+/// Hashes a transaction for deduplication,the fee is part of the transaction's arguments
+/// but not of its block so it is added to the hashed fields
+pub fn _transaction_hash(tx: &ICRC3Map, fee: &Option<Nat>) -> [u8; 32] {
+    let mut fields = tx.clone();
+    if let Some(fee) = fee {
+        fields.insert("fee".to_string(), ICRC3Value::Nat(fee.clone()));
+    }
+    ICRC3Value::Map(fields).hash()
+}
+
+/// Encodes a number with unsigned LEB128 as certified by ICRC-3
+fn _leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn _hash_with_domain(domain: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([domain.len() as u8]);
+    hasher.update(domain.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Gets the root hash of the ICRC-3 tip hash tree,set as the canister's certified data
+///
+/// The tree holds the `last_block_hash` and `last_block_index` leaves
+pub fn _tip_root_hash(last_block_index: u64, last_block_hash: [u8; 32]) -> [u8; 32] {
+    let leaf_hash = |contents: &[u8]| _hash_with_domain("ic-hashtree-leaf", &[contents]);
+    let labeled_hash = |label: &str, child: [u8; 32]| {
+        _hash_with_domain("ic-hashtree-labeled", &[label.as_bytes(), &child])
+    };
+
+    let hash_node = labeled_hash("last_block_hash", leaf_hash(&last_block_hash));
+    let index_node = labeled_hash("last_block_index", leaf_hash(&_leb128(last_block_index)));
+    _hash_with_domain("ic-hashtree-fork", &[&hash_node, &index_node])
+}
+
+/// Gets the CBOR encoding of the ICRC-3 tip hash tree whose root is `_tip_root_hash`
+pub fn _tip_hash_tree(last_block_index: u64, last_block_hash: [u8; 32]) -> Vec<u8> {
+    use ciborium::value::Value;
+
+    let labeled = |label: &str, contents: Vec<u8>| {
+        Value::Array(vec![
+            Value::Integer(2.into()),
+            Value::Bytes(label.as_bytes().to_vec()),
+            Value::Array(vec![Value::Integer(3.into()), Value::Bytes(contents)]),
+        ])
+    };
+    let tree = Value::Array(vec![
+        Value::Integer(1.into()),
+        labeled("last_block_hash", last_block_hash.to_vec()),
+        labeled("last_block_index", _leb128(last_block_index)),
+    ]);
+
+    let mut bytes = vec![];
+    ciborium::ser::into_writer(&tree, &mut bytes).unwrap();
+    bytes
+}
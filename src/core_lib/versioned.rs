@@ -0,0 +1,61 @@
+use candid::{decode_one, encode_one, CandidType};
+
+use serde::de::DeserializeOwned;
+
+use std::borrow::Cow;
+
+/// Prefix of values stored in a versioned envelope,values stored before envelopes were
+/// introduced start with their Candid ("DIDL") or bincode encoding instead
+const ENVELOPE_MAGIC: [u8; 3] = *b"RVE";
+
+/// Schema version of values stored before envelopes were introduced
+pub const UNVERSIONED: u8 = 0;
+
+/// Versioned
+///
+/// A type stored in stable memory inside a versioned envelope,the envelope holds the schema
+/// version the value was encoded with so values written by older versions of the canister can
+/// still be decoded after its fields change
+///
+/// Keys of stable maps are not wrapped since their encoding defines the order of the map
+pub trait Versioned: CandidType + DeserializeOwned {
+    /// Schema version values are currently encoded with
+    const VERSION: u8;
+
+    /// Decodes a value encoded with an older schema version
+    ///
+    /// Unversioned values are plain Candid by default,types whose unversioned values were
+    /// encoded differently or that changed since override this
+    fn _decode_version(version: u8, payload: &[u8]) -> Self {
+        match version {
+            UNVERSIONED => decode_one(payload).unwrap(),
+            _ => panic!("Unsupported schema version {}", version),
+        }
+    }
+}
+
+/// Encodes a value with its current schema version
+pub fn _to_versioned_bytes<T: Versioned>(value: &T) -> Cow<'static, [u8]> {
+    let mut bytes = ENVELOPE_MAGIC.to_vec();
+    bytes.push(T::VERSION);
+    bytes.extend(encode_one(value).unwrap());
+    Cow::Owned(bytes)
+}
+
+/// Decodes a value stored with any schema version
+pub fn _from_versioned_bytes<T: Versioned>(bytes: &[u8]) -> T {
+    let (version, payload) = _open_envelope(bytes);
+    if version == T::VERSION {
+        decode_one(payload).unwrap()
+    } else {
+        T::_decode_version(version, payload)
+    }
+}
+
+/// Splits stored bytes into the schema version and the encoded value
+pub fn _open_envelope(bytes: &[u8]) -> (u8, &[u8]) {
+    match bytes.strip_prefix(&ENVELOPE_MAGIC) {
+        Some([version, payload @ ..]) => (*version, payload),
+        _ => (UNVERSIONED, bytes),
+    }
+}
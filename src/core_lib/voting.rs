@@ -0,0 +1,108 @@
+use candid::CandidType;
+use ic_stable_structures::{storable::Bound, Storable};
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+use super::versioned::{_from_versioned_bytes, _to_versioned_bytes, Versioned};
+
+use super::lock::{LockDetails, INSTANT_SPAN_ID, YEAR};
+
+type Amount = u128;
+type Time = u64;
+
+const NANOS_PER_SECOND: Time = 1_000_000_000;
+
+/// The remaining lock time giving a voting power equal to the amount locked,in seconds
+///
+/// It is the duration of the longest default span and kept constant so past voting power
+/// does not change with the lock span table
+pub const MAX_VOTING_SPAN: Time = YEAR / NANOS_PER_SECOND;
+
+/// Converts a time in nanoseconds to the seconds voting power is tracked in
+pub fn _to_seconds(time: Time) -> Time {
+    time / NANOS_PER_SECOND
+}
+
+/// Voting Point
+///
+/// The aggregated voting power of a set of locks at a point in time,scaled by the max voting span.
+/// The bias decreases by the slope every second until a lock expires and its amount is removed
+/// from the slope
+#[derive(Copy, Clone, Deserialize, Debug, CandidType, Default, PartialEq, Eq)]
+pub struct VotingPoint {
+    /// Sum of amount × remaining seconds of the locks
+    pub bias: u128,
+    /// Sum of the amounts of the locks that have not expired
+    pub slope: u128,
+}
+
+impl VotingPoint {
+    /// Advances the point by a number of seconds
+    pub fn _advance(&mut self, seconds: Time) {
+        self.bias = self.bias.saturating_sub(self.slope * seconds as u128);
+    }
+
+    /// Adds (or removes) the contribution of a lock
+    pub fn _apply(&mut self, contribution: LockContribution, current_time: Time, add: bool) {
+        let bias = contribution._bias_at(current_time);
+        if add {
+            self.bias += bias;
+            self.slope += contribution.slope;
+        } else {
+            self.bias = self.bias.saturating_sub(bias);
+            self.slope = self.slope.saturating_sub(contribution.slope);
+        }
+    }
+
+    /// The voting power of the point
+    pub fn _voting_power(&self) -> Amount {
+        self.bias / MAX_VOTING_SPAN as u128
+    }
+}
+
+impl Storable for VotingPoint {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for VotingPoint {
+    const VERSION: u8 = 1;
+}
+
+/// Lock Contribution
+///
+/// The part of a lock's voting power that decays,its amount until its expiry time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LockContribution {
+    pub slope: u128,
+    /// The expiry time of the lock in seconds
+    pub end_time: Time,
+}
+
+impl LockContribution {
+    /// Gets the contribution of a lock at a time in seconds,None for instant locks
+    /// and expired locks which have no voting power
+    pub fn _from_lock(lock: &LockDetails, current_time: Time) -> Option<Self> {
+        let end_time = _to_seconds(lock.expiry_time);
+        if lock.span_id == INSTANT_SPAN_ID || end_time <= current_time || lock.amount == 0 {
+            return None;
+        }
+        Some(LockContribution {
+            slope: lock.amount,
+            end_time,
+        })
+    }
+
+    /// The scaled voting power of the lock at a time in seconds
+    pub fn _bias_at(&self, current_time: Time) -> u128 {
+        self.slope * self.end_time.saturating_sub(current_time) as u128
+    }
+}
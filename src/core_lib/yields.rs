@@ -0,0 +1,115 @@
+use candid::CandidType;
+use ic_stable_structures::{storable::Bound, Storable};
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use super::versioned::{_from_versioned_bytes, _to_versioned_bytes, Versioned};
+
+use super::lock::{base_units, SpanId, Vault, _ONE_PERCENT, DAY, YEAR};
+
+type Amount = u128;
+type Time = u64;
+
+/// Trailing windows yields are computed over,in days
+pub const YIELD_WINDOWS: [u64; 3] = [7, 30, 90];
+
+/// Snapshots older than the longest yield window (with a day of margin) are pruned
+pub const SNAPSHOT_RETENTION: Time = 91 * DAY;
+
+/// Span Snapshot
+///
+/// The state of a span's earnings accumulator at a point in time
+#[derive(Copy, Clone, Deserialize, Debug, CandidType, Default)]
+pub struct SpanSnapshot {
+    pub lifetime_earnings_per_token: Amount,
+    pub total_locked: Amount,
+}
+
+/// Yield Snapshot
+///
+/// The state of every span at a point in time,keyed by span id
+#[derive(Clone, Deserialize, Debug, CandidType, Default)]
+pub struct YieldSnapshot {
+    pub spans: BTreeMap<SpanId, SpanSnapshot>,
+}
+
+impl YieldSnapshot {
+    /// Takes a snapshot of the vault's spans
+    pub fn _from_vault(vault: &Vault) -> Self {
+        let spans = vault
+            .spans
+            .iter()
+            .map(|(span_id, details)| {
+                (
+                    *span_id,
+                    SpanSnapshot {
+                        lifetime_earnings_per_token: details.lifetime_earnings_per_token,
+                        total_locked: details.total_locked,
+                    },
+                )
+            })
+            .collect();
+
+        YieldSnapshot { spans }
+    }
+}
+
+impl Storable for YieldSnapshot {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for YieldSnapshot {
+    const VERSION: u8 = 1;
+}
+
+/// Span Yield
+///
+/// Trailing yields of a span,each APY is None when there is no snapshot old enough
+/// to cover its window
+#[derive(Copy, Clone, Deserialize, Debug, CandidType)]
+pub struct SpanYield {
+    pub span_id: SpanId,
+    pub total_locked: Amount,
+    /// APY over the last 7 days (100_000 = 1%)
+    pub apy_7d: Option<u64>,
+    /// APY over the last 30 days (100_000 = 1%)
+    pub apy_30d: Option<u64>,
+    /// APY over the last 90 days (100_000 = 1%)
+    pub apy_90d: Option<u64>,
+}
+
+/// Trailing APY Function
+///
+/// Annualizes the growth of a span's lifetime earnings per token over a period,without
+/// compounding
+///
+/// Params
+/// - current: Amount - The lifetime earnings per token at the end of the period
+/// - past: Amount - The lifetime earnings per token at the start of the period
+/// - elapsed: Time - The length of the period
+///
+/// Returns
+/// - u64 - The APY (100_000 = 1%)
+pub fn _trailing_apy(current: Amount, past: Amount, elapsed: Time) -> u64 {
+    if elapsed == 0 {
+        return 0;
+    }
+    let earned_per_token = current.saturating_sub(past);
+
+    let apy = earned_per_token
+        .saturating_mul(100 * _ONE_PERCENT as u128)
+        .saturating_mul(YEAR as u128)
+        / (elapsed as u128 * base_units());
+
+    apy.min(u64::MAX as u128) as u64
+}
@@ -1,14 +1,55 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
 
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Deserialize, Nat, Principal};
 
+use ic_ledger_types::AccountIdentifier;
+use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
+use icrc_ledger_types::icrc::generic_value::{ICRC3Map, ICRC3Value};
 use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::{
+    Memo, TransferArg as Icrc1TransferArg, TransferError as Icrc1TransferError,
+};
+use icrc_ledger_types::icrc2::allowance::{Allowance, AllowanceArgs};
+use icrc_ledger_types::icrc2::approve::{ApproveArgs, ApproveError};
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
+use icrc_ledger_types::icrc3::archive::{GetArchivesArgs, ICRC3ArchiveInfo};
+use icrc_ledger_types::icrc3::blocks::{
+    BlockWithId, GetBlocksRequest, GetBlocksResult, ICRC3DataCertificate, SupportedBlockType,
+};
 
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell};
 
-use core_lib::lock::{LockDetails, LockSpan, Vault};
-use types::LiquidityManagerDetails;
+use core_lib::asset::{Asset, AssetType};
+use core_lib::env;
+use core_lib::icrc7::{
+    _to_token_id, LockTokensState, StandardRecord, TokenId, TransferArg, TransferError,
+    TransferResult, COLLECTION_NAME, COLLECTION_SYMBOL, DEFAULT_TAKE_VALUE, MAX_QUERY_BATCH_SIZE,
+    MAX_TAKE_VALUE, MAX_TOKEN_MEMO_SIZE, MAX_UPDATE_BATCH_SIZE, SUPPORTED_TOKEN_STANDARDS,
+};
+use core_lib::ledger::{_to_account_identifier, IcpLedger, Ledger};
+use core_lib::lock::{
+    default_lock_spans, EarlyUnlockPreview, ExpiryAction, LockDetails, LockSpanConfig, SpanId,
+    Vault, VestingGrant, VestingSchedule, _ONE_PERCENT, DAY, INSTANT_SPAN_ID,
+};
+use core_lib::qtoken::{
+    _account_value, _check_created_at_time, _tip_hash_tree, _tip_root_hash, _to_account_key,
+    _transaction_hash, AccountKey, CreatedAtTimeError, QTokenAllowance, QTokenBlock, QTokenState,
+    MAX_BLOCKS_PER_REQUEST, MAX_MEMO_SIZE, PERMITTED_DRIFT, QTOKEN_FEE, SUPPORTED_BLOCK_TYPES,
+    SUPPORTED_STANDARDS, TRANSACTION_WINDOW,
+};
+use core_lib::voting::{_to_seconds, LockContribution, VotingPoint};
+use core_lib::yields::{
+    _trailing_apy, SpanYield, YieldSnapshot, SNAPSHOT_RETENTION, YIELD_WINDOWS,
+};
+use types::{
+    AdminAction, AdminActionRecord, AdminActionStatus, AssetConfig, AssetId, AssetMetadata,
+    ConfigChange, ConfigUpdate, GovernanceMode, GovernanceSettings, LiquidityManagerDetails,
+    MarketDetails, MarketStatus, MetadataStatus, OperationFamily, PauseDetails, PendingAdminAction,
+    Role, RoleAssignments, TimelockState, INITIAL_ASSET_ID,
+};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type Amount = u128;
@@ -20,11 +61,64 @@ const _USERS_MARGIN_BALANCE_MEMORY_ID: MemoryId = MemoryId::new(3);
 const _APPROVED_MARKETS_MEMORY_ID: MemoryId = MemoryId::new(4);
 const _VAULT_MEMORY_ID: MemoryId = MemoryId::new(5);
 const _ADMIN_MEMORY_ID: MemoryId = MemoryId::new(6);
+const _COMPOUNDING_LOCKS_MEMORY_ID: MemoryId = MemoryId::new(7);
+const _LOCK_SPANS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const _LOCK_TOKENS_MEMORY_ID: MemoryId = MemoryId::new(9);
+const _LOCK_TOKENS_STATE_MEMORY_ID: MemoryId = MemoryId::new(10);
+const _ACCOUNT_LOCK_TOKENS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const _EXPIRING_LOCKS_MEMORY_ID: MemoryId = MemoryId::new(12);
+const _YIELD_SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const _ACCOUNT_VOTING_POINTS_MEMORY_ID: MemoryId = MemoryId::new(14);
+const _ACCOUNT_SLOPE_CHANGES_MEMORY_ID: MemoryId = MemoryId::new(15);
+const _VOTING_POINTS_MEMORY_ID: MemoryId = MemoryId::new(16);
+const _SLOPE_CHANGES_MEMORY_ID: MemoryId = MemoryId::new(17);
+const _ASSETS_MEMORY_ID: MemoryId = MemoryId::new(18);
+const _VAULTS_MEMORY_ID: MemoryId = MemoryId::new(19);
+const _USERS_MARGIN_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(20);
+const _CLAIMED_DEPOSITS_MEMORY_ID: MemoryId = MemoryId::new(21);
+const _QTOKEN_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(22);
+const _QTOKEN_ALLOWANCES_MEMORY_ID: MemoryId = MemoryId::new(23);
+const _QTOKEN_BLOCKS_MEMORY_ID: MemoryId = MemoryId::new(24);
+const _QTOKEN_STATE_MEMORY_ID: MemoryId = MemoryId::new(25);
+const _QTOKEN_TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(26);
+const _ROLES_MEMORY_ID: MemoryId = MemoryId::new(27);
+const _PENDING_ADMIN_MEMORY_ID: MemoryId = MemoryId::new(28);
+const _MARKETS_MEMORY_ID: MemoryId = MemoryId::new(29);
+const _PAUSED_OPERATIONS_MEMORY_ID: MemoryId = MemoryId::new(30);
+const _MIN_AMOUNTS_MEMORY_ID: MemoryId = MemoryId::new(31);
+const _CONFIG_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(32);
+const _SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(33);
+const _GOVERNANCE_MEMORY_ID: MemoryId = MemoryId::new(34);
+const _PENDING_ADMIN_ACTIONS_MEMORY_ID: MemoryId = MemoryId::new(35);
+const _TIMELOCK_MEMORY_ID: MemoryId = MemoryId::new(36);
+const _ADMIN_ACTION_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(37);
+
+/// Longest delay admin actions can be timelocked for,so a mistaken delay can not lock the
+/// admin out for good
+const MAX_TIMELOCK_DELAY: Time = 30 * DAY;
+
+/// Interval at which earnings of compounding locks are rolled into their amount
+const COMPOUNDING_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Interval at which expired locks are renewed or moved to the instant span
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Interval at which snapshots of the spans are taken to compute trailing yields
+const YIELD_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Delay before a failed fetch of an asset's ledger metadata is retried
+const METADATA_RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
 
 thread_local! {
 
+    // last failed metadata fetch of each asset,kept on the heap since the fetch of every asset
+    // without metadata is retried after an upgrade
+    static METADATA_FETCH_ERRORS :RefCell<BTreeMap<AssetId,(String,Time)>> = const { RefCell::new(BTreeMap::new()) };
+
     static MEMORY_MANAGER:RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default())) ;
 
+    // the details,vault and margin balances below hold the single asset of canisters installed
+    // before multiple assets,they are only read to migrate it to the initial asset
     static LIQUIDTY_MANAGER_DETAILS :RefCell<StableCell<LiquidityManagerDetails,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
         reference.get(LIQUIDITY_MANAGER_DETAILS_MEMORY_ID)
     }),LiquidityManagerDetails::default()).unwrap());
@@ -43,28 +137,260 @@ thread_local! {
         reference.get(_USERS_MARGIN_BALANCE_MEMORY_ID)
     })));
 
+    static ASSETS :RefCell<StableBTreeMap<AssetId,LiquidityManagerDetails,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ASSETS_MEMORY_ID)
+    })));
+
+    static VAULTS :RefCell<StableBTreeMap<AssetId,Vault,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_VAULTS_MEMORY_ID)
+    })));
+
+    static USERS_MARGIN_BALANCES :RefCell<StableBTreeMap<(AssetId,Principal),Amount,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_USERS_MARGIN_BALANCES_MEMORY_ID)
+    })));
+
+    // principal credited with each claimed deposit keyed by the asset and the ledger block index
+    static CLAIMED_DEPOSITS :RefCell<StableBTreeMap<(AssetId,u64),Principal,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_CLAIMED_DEPOSITS_MEMORY_ID)
+    })));
+
+    // balances,allowances,blocks and state of the built-in QToken ledger
+    static QTOKEN_BALANCES :RefCell<StableBTreeMap<AccountKey,Amount,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_QTOKEN_BALANCES_MEMORY_ID)
+    })));
+
+    static QTOKEN_ALLOWANCES :RefCell<StableBTreeMap<(AccountKey,AccountKey),QTokenAllowance,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_QTOKEN_ALLOWANCES_MEMORY_ID)
+    })));
+
+    static QTOKEN_BLOCKS :RefCell<StableBTreeMap<u64,QTokenBlock,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_QTOKEN_BLOCKS_MEMORY_ID)
+    })));
+
+    static QTOKEN_STATE :RefCell<StableCell<QTokenState,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_QTOKEN_STATE_MEMORY_ID)
+    }),QTokenState::default()).unwrap());
+
     static APPROVED_MARKETS :RefCell<StableBTreeMap<Principal,bool,Memory>> = RefCell::new(StableBTreeMap::init(
         MEMORY_MANAGER.with_borrow(|reference|{
         reference.get(_APPROVED_MARKETS_MEMORY_ID)
     })));
+
+    static MARKETS :RefCell<StableBTreeMap<Principal,MarketDetails,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_MARKETS_MEMORY_ID)
+    })));
+
+    static PAUSED_OPERATIONS :RefCell<StableBTreeMap<OperationFamily,PauseDetails,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_PAUSED_OPERATIONS_MEMORY_ID)
+    })));
+
+    // min amounts of operation families overriding the min amount of their asset
+    static MIN_AMOUNTS :RefCell<StableBTreeMap<(AssetId,OperationFamily),Amount,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_MIN_AMOUNTS_MEMORY_ID)
+    })));
+
+    static CONFIG_HISTORY :RefCell<StableBTreeMap<u64,ConfigChange,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_CONFIG_HISTORY_MEMORY_ID)
+    })));
+
+    // number of the last migration run on the canister's stable memory
+    static SCHEMA_VERSION :RefCell<StableCell<u32,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_SCHEMA_VERSION_MEMORY_ID)
+    }),0).unwrap());
+
+    static GOVERNANCE :RefCell<StableCell<GovernanceSettings,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_GOVERNANCE_MEMORY_ID)
+    }),GovernanceSettings::default()).unwrap());
+
+    // admin actions queued with queueAdminAction until they are executed or cancelled
+    static PENDING_ADMIN_ACTIONS :RefCell<StableBTreeMap<u64,PendingAdminAction,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_PENDING_ADMIN_ACTIONS_MEMORY_ID)
+    })));
+
+    // every admin action queued with queueAdminAction and whether it was cancelled or executed
+    static ADMIN_ACTION_HISTORY :RefCell<StableBTreeMap<u64,AdminActionRecord,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ADMIN_ACTION_HISTORY_MEMORY_ID)
+    })));
+
+    static TIMELOCK :RefCell<StableCell<TimelockState,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_TIMELOCK_MEMORY_ID)
+    }),TimelockState::default()).unwrap());
     static ADMIN: RefCell<StableCell<Principal, Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference| {
         reference.get(_ADMIN_MEMORY_ID)
     }), Principal::anonymous()).unwrap());
 
+    // index of the locks with the compounding flag set,so compounding does not scan every lock
+    static COMPOUNDING_LOCKS :RefCell<StableBTreeMap<(Principal,Time),bool,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_COMPOUNDING_LOCKS_MEMORY_ID)
+    })));
+
+    // admin proposed through proposeAdmin,anonymous when there is no pending handover
+    static PENDING_ADMIN: RefCell<StableCell<Principal, Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference| {
+        reference.get(_PENDING_ADMIN_MEMORY_ID)
+    }), Principal::anonymous()).unwrap());
+
+    static ROLES :RefCell<StableBTreeMap<(Role,Principal),bool,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ROLES_MEMORY_ID)
+    })));
+
+    static LOCK_SPANS :RefCell<StableBTreeMap<SpanId,LockSpanConfig,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_LOCK_SPANS_MEMORY_ID)
+    })));
+
+    // span capacity held by deposits waiting on their transfer,kept on the heap since it only
+    // lives for the duration of an inter canister call
+    static RESERVED_SPAN_CAPACITY :RefCell<BTreeMap<(AssetId,SpanId),Amount>> = const { RefCell::new(BTreeMap::new()) };
+
+    static LOCK_TOKENS :RefCell<StableBTreeMap<TokenId,(Principal,Time),Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_LOCK_TOKENS_MEMORY_ID)
+    })));
+
+    // lock tokens held by each principal,so tokens can be listed without scanning every lock
+    static ACCOUNT_LOCK_TOKENS :RefCell<StableBTreeMap<(Principal,TokenId),bool,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ACCOUNT_LOCK_TOKENS_MEMORY_ID)
+    })));
+
+    // blocks of built-in QToken transactions with a created at time keyed by that time and the
+    // transaction's hash,so duplicates within the transaction window are rejected
+    static QTOKEN_TRANSACTIONS :RefCell<StableBTreeMap<(Time,[u8;32]),u64,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_QTOKEN_TRANSACTIONS_MEMORY_ID)
+    })));
+
+    static LOCK_TOKENS_STATE :RefCell<StableCell<LockTokensState,Memory>> = RefCell::new(StableCell::init(MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_LOCK_TOKENS_STATE_MEMORY_ID)
+    }),LockTokensState::default()).unwrap());
+
+    // index of the locks with an expiry action by expiry time,so the sweep only reads expired locks
+    static EXPIRING_LOCKS :RefCell<StableBTreeMap<(Time,Principal,Time),bool,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_EXPIRING_LOCKS_MEMORY_ID)
+    })));
+
+    static YIELD_SNAPSHOTS :RefCell<StableBTreeMap<(AssetId,Time),YieldSnapshot,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_YIELD_SNAPSHOTS_MEMORY_ID)
+    })));
+
+    // voting power checkpoints of each asset,times are in seconds
+    static ACCOUNT_VOTING_POINTS :RefCell<StableBTreeMap<(AssetId,Principal,Time),VotingPoint,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ACCOUNT_VOTING_POINTS_MEMORY_ID)
+    })));
+
+    static ACCOUNT_SLOPE_CHANGES :RefCell<StableBTreeMap<(AssetId,Principal,Time),Amount,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_ACCOUNT_SLOPE_CHANGES_MEMORY_ID)
+    })));
+
+    static VOTING_POINTS :RefCell<StableBTreeMap<(AssetId,Time),VotingPoint,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_VOTING_POINTS_MEMORY_ID)
+    })));
+
+    static SLOPE_CHANGES :RefCell<StableBTreeMap<(AssetId,Time),Amount,Memory>> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with_borrow(|reference|{
+        reference.get(_SLOPE_CHANGES_MEMORY_ID)
+    })));
+
 }
 
 #[ic_cdk::init]
-fn init(details: LiquidityManagerDetails) {
-    let caller = ic_cdk::caller();
+fn init(mut details: LiquidityManagerDetails) {
+    let caller = env::caller();
     ADMIN.with_borrow_mut(|admin| {
         admin.set(caller).unwrap();
     });
-    LIQUIDTY_MANAGER_DETAILS.with_borrow_mut(|reference| reference.set(details).unwrap());
+    details.metadata = None;
+    if let Err(error) = _check_asset_types(&details) {
+        ic_cdk::trap(&error);
+    }
+    _insert_asset(INITIAL_ASSET_ID, details, Vault::default());
+    _init_lock_spans();
+    _take_yield_snapshot();
+    // a new canister's stable memory needs none of the migrations
+    SCHEMA_VERSION.with_borrow_mut(|reference| reference.set(_latest_schema_version()).unwrap());
+    _start_timers();
+    // ledgers can not be called during init so metadata is fetched once init completes
+    _schedule_metadata_fetch(INITIAL_ASSET_ID, Duration::ZERO);
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    _run_migrations();
+    // timers do not persist across upgrades
+    _start_timers();
+    _qtoken_certify_tip();
+    // assets added before ledger metadata was tracked or whose fetch failed are retried
+    _get_assets()
+        .into_iter()
+        .filter(|(_, details)| details.metadata.is_none())
+        .for_each(|(asset_id, _)| _schedule_metadata_fetch(asset_id, Duration::ZERO));
+}
+
+/// Migrations of the canister's stable memory in the order they are run,each migration is
+/// numbered and runs once when a canister whose schema version is lower is upgraded
+///
+/// Values stored before versioned envelopes need no migration,they are decoded from their
+/// previous encoding when read and written in an envelope the next time they are saved,since
+/// re-encoding every stored value at once could exceed the upgrade's instruction limit
+const MIGRATIONS: [(u32, fn()); 5] = [
+    // canisters installed before multiple assets have their asset moved to the initial asset
+    (1, _init_assets),
+    // canisters installed before the lock span table start with the previously fixed spans
+    (2, _init_lock_spans),
+    // locks created before lock tokens are issued their tokens
+    (3, _tokenize_locks),
+    // locks created before voting power checkpoints are checkpointed
+    (4, _init_voting_power),
+    // markets approved before market details were tracked are moved to the markets table
+    (5, _init_markets),
+];
+
+fn _latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |(version, _)| *version)
 }
 
-/// Gets the current margin balance for a user
+/// Runs the migrations newer than the canister's schema version in order
+fn _run_migrations() {
+    let schema_version = SCHEMA_VERSION.with_borrow(|reference| *reference.get());
+    for (version, migration) in MIGRATIONS {
+        if version <= schema_version {
+            continue;
+        }
+        migration();
+        SCHEMA_VERSION.with_borrow_mut(|reference| reference.set(version).unwrap());
+    }
+}
+
+/// Gets the number of the last migration run on the canister's stable memory
+#[ic_cdk::query(name = "getSchemaVersion")]
+fn get_schema_version() -> u32 {
+    SCHEMA_VERSION.with_borrow(|reference| *reference.get())
+}
+
+/// Gets the current margin balance for a user in an asset
 ///
 /// # Arguments
+/// * `asset_id` - Id of the collateral asset
 /// * `user` - Principal ID of the user to get balance for
 ///
 /// # Returns
@@ -74,8 +400,8 @@ fn init(details: LiquidityManagerDetails) {
 /// - Returns 0 if user has no margin balance
 /// - Margin balance represents funds available for creating positions and providing leverage
 #[ic_cdk::query(name = "getUserMarginBalance")]
-fn get_user_margin_balance(user: Principal) -> Amount {
-    return _get_user_margin_balance(user);
+fn get_user_margin_balance(asset_id: AssetId, user: Principal) -> Amount {
+    _get_user_margin_balance(asset_id, user)
 }
 
 /// Gets all active locks owned by  a user
@@ -96,10 +422,13 @@ fn get_user_locks(user: Principal) -> Vec<(Time, LockDetails, Amount)> {
     return _get_user_locks(user);
 }
 
-/// Gets the current staking details for the vault
+/// Gets the current staking details for the vault of an asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
 ///
 /// # Returns
-/// * `VaultStakingDetails` - Current vault staking state including:
+/// * `Option<Vault>` - Current vault staking state,None if the asset does not exist,including:
 ///   - Total staked amount
 ///   - Free liquidity available for lending
 ///   - Outstanding debt
@@ -111,18 +440,42 @@ fn get_user_locks(user: Principal) -> Vec<(Time, LockDetails, Amount)> {
 /// - Helps determine if new positions can be opened
 /// - Provides data for fee distribution calculations
 #[ic_cdk::query(name = "getVault")]
-fn get_vault() -> Vault {
-    _get_vault()
+fn get_vault(asset_id: AssetId) -> Option<Vault> {
+    _get_asset(asset_id)?;
+    Some(_get_vault(asset_id))
 }
 
+/// Gets the details of an asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Option<LiquidityManagerDetails>` - The asset,its virtual asset,its min amount and the
+///   ledger metadata of both once fetched,None if the asset does not exist
 #[ic_cdk::query(name = "getLiquidityManagerDetails")]
-fn get_liquidity_manager_details() -> LiquidityManagerDetails {
-    _get_liquidity_manager_details()
+fn get_liquidity_manager_details(asset_id: AssetId) -> Option<LiquidityManagerDetails> {
+    _get_asset(asset_id)
+}
+
+/// Gets whether the ledger metadata of an asset has been fetched and validated
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Option<MetadataStatus>` - Pending until the first fetch,Validated once the metadata is
+///   stored or Failed with the error of the last fetch,None if the asset does not exist
+#[ic_cdk::query(name = "getAssetMetadataStatus")]
+fn get_asset_metadata_status(asset_id: AssetId) -> Option<MetadataStatus> {
+    _get_asset(asset_id)?;
+    Some(_get_metadata_status(asset_id))
 }
 
 /// Funds a user's account with assets
 ///
 /// # Arguments
+/// * `asset_id` - Id of the collateral asset to deposit
 /// * `amount` - Amount of tokens to deposit
 /// * `from_subaccount` - Optional subaccount to transfer from
 /// * `receiver` - Principal ID of account to fund
@@ -134,18 +487,21 @@ fn get_liquidity_manager_details() -> LiquidityManagerDetails {
 /// - Transfers tokens from caller's account to canister
 /// - Updates receiver's balance if transfer succeeds
 /// - Amount must be >= vault's minimum amount
-#[ic_cdk::update(name = "fundAccount")]
+#[ic_cdk::update(name = "fundAccount", guard = "deposits_guard")]
 async fn fund_account(
+    asset_id: AssetId,
     amount: Amount,
     from_subaccount: Option<Subaccount>,
     receiver: Principal,
 ) -> Result<Amount, String> {
-    let vault_details = _get_liquidity_manager_details();
-    if amount < vault_details.min_amount {
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    if amount < _get_min_amount(asset_id, OperationFamily::Deposits) {
         return Err("Amount is less than min amount".to_string());
     }
 
-    let depositor = ic_cdk::caller();
+    let depositor = env::caller();
 
     let asset = vault_details.asset;
 
@@ -157,36 +513,127 @@ async fn fund_account(
                 subaccount: from_subaccount,
             },
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             false,
         )
         .await;
     if tx_valid {
-        _update_user_balance(receiver, amount, true);
+        _update_user_balance(asset_id, receiver, amount, true);
         return Ok(amount);
     }
 
     return Err("transaction failed".to_string());
 }
 
-#[ic_cdk::update(name = "withdrawFromAccount")]
-async fn withdraw_from_account(amount: Amount, to_account: Account) -> Result<Amount, String> {
-    let vault_details = _get_liquidity_manager_details();
-    if amount < vault_details.min_amount {
+/// Claims a deposit made by transferring tokens to the vault
+///
+/// The transfer is read from the asset ledger's block (ICRC-3 `icrc3_get_blocks` or the ICP
+/// ledger's `query_blocks`,following archives) and credited to the caller's margin balance
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `block_index` - Index of the ledger block of the transfer
+/// * `from_subaccount` - Subaccount of the caller the transfer was sent from
+///
+/// # Returns
+/// * `Ok(Amount)` - The amount credited
+/// * `Err(String)` - If the block is not a transfer from the caller to the vault,the deposit was
+///   already claimed or credited by `fundAccount`,or the amount is less than the min amount
+///
+/// # Notes
+/// - Transfers must be sent to the default account of the canister
+/// - Each block can only be claimed once,see `getDepositClaimant`
+#[ic_cdk::update(name = "claimDeposit", guard = "deposits_guard")]
+async fn claim_deposit(
+    asset_id: AssetId,
+    block_index: u64,
+    from_subaccount: Option<Subaccount>,
+) -> Result<Amount, String> {
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    if _get_deposit_claimant(asset_id, block_index).is_some() {
+        return Err("Deposit already claimed".to_string());
+    }
+
+    let depositor = env::caller();
+
+    let Some(transfer) = vault_details
+        .asset
+        .ledger()
+        .get_transfer(block_index)
+        .await?
+    else {
+        return Err("Block is not a transfer".to_string());
+    };
+
+    let vault_account = _to_account_identifier(Account::from(env::canister_id()));
+    if transfer.to != vault_account {
+        return Err("Deposit was not sent to the vault".to_string());
+    }
+    // transfers made by the vault as spender were credited by fundAccount
+    if transfer.spender == Some(vault_account) {
+        return Err("Deposit already claimed".to_string());
+    }
+    let depositor_account = _to_account_identifier(Account {
+        owner: depositor,
+        subaccount: from_subaccount,
+    });
+    if transfer.from != depositor_account {
+        return Err("Deposit was not sent by caller".to_string());
+    }
+    if transfer.amount < _get_min_amount(asset_id, OperationFamily::Deposits) {
+        return Err("Amount is less than min amount".to_string());
+    }
+
+    // the block could have been claimed while it was fetched
+    if _get_deposit_claimant(asset_id, block_index).is_some() {
+        return Err("Deposit already claimed".to_string());
+    }
+    CLAIMED_DEPOSITS
+        .with_borrow_mut(|reference| reference.insert((asset_id, block_index), depositor));
+    _update_user_balance(asset_id, depositor, transfer.amount, true);
+
+    Ok(transfer.amount)
+}
+
+/// Gets the principal credited with a deposit claimed through `claimDeposit`
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `block_index` - Index of the ledger block of the deposit
+///
+/// # Returns
+/// * `Option<Principal>` - The principal credited,None if the block has not been claimed
+#[ic_cdk::query(name = "getDepositClaimant")]
+fn get_deposit_claimant(asset_id: AssetId, block_index: u64) -> Option<Principal> {
+    _get_deposit_claimant(asset_id, block_index)
+}
+
+#[ic_cdk::update(name = "withdrawFromAccount", guard = "withdrawals_guard")]
+async fn withdraw_from_account(
+    asset_id: AssetId,
+    amount: Amount,
+    to_account: Account,
+) -> Result<Amount, String> {
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    if amount < _get_min_amount(asset_id, OperationFamily::Withdrawals) {
         return Err("Amount is less than min amount".to_string());
     }
-    let user = ic_cdk::caller();
+    let user = env::caller();
 
-    _update_user_balance(user, amount, false);
+    _update_user_balance(asset_id, user, amount, false);
 
     let asset = vault_details.asset;
     let tx_valid = asset
         .move_asset(
             amount,
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             to_account,
@@ -194,13 +641,63 @@ async fn withdraw_from_account(amount: Amount, to_account: Account) -> Result<Am
         )
         .await;
     if !tx_valid {
-        _update_user_balance(user, amount, true);
+        _update_user_balance(asset_id, user, amount, true);
         return Err("transaction failed".to_string());
     }
 
     return Ok(amount);
 }
 
+/// Withdraws ICP from a user's account to an account identifier
+///
+/// Uses the ICP ledger's legacy transfer for destinations that only have an account
+/// identifier,such as exchange deposit addresses
+///
+/// # Arguments
+/// * `asset_id` - Id of the ICP asset
+/// * `amount` - Amount of ICP to withdraw (in e8s)
+/// * `to` - The account identifier to send the ICP to
+///
+/// # Returns
+/// * `Ok(Amount)` - The amount withdrawn
+/// * `Err(String)` - If the asset is not ICP,the balance is insufficient or the transfer fails
+#[ic_cdk::update(name = "withdrawToAccountIdentifier", guard = "withdrawals_guard")]
+async fn withdraw_to_account_identifier(
+    asset_id: AssetId,
+    amount: Amount,
+    to: AccountIdentifier,
+) -> Result<Amount, String> {
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    let AssetType::ICP = vault_details.asset.asset_type else {
+        return Err("Asset is not ICP".to_string());
+    };
+    if amount < _get_min_amount(asset_id, OperationFamily::Withdrawals) {
+        return Err("Amount is less than min amount".to_string());
+    }
+    let user = env::caller();
+
+    if _get_user_margin_balance(asset_id, user) < amount {
+        return Err("Insufficient margin balance".to_string());
+    }
+
+    _update_user_balance(asset_id, user, amount, false);
+
+    let ledger = IcpLedger {
+        ledger_id: vault_details.asset.ledger_id,
+    };
+    let tx_result = ledger
+        .transfer_to_account_identifier(amount, None, to)
+        .await;
+    if tx_result.is_err() {
+        _update_user_balance(asset_id, user, amount, true);
+        return Err("transaction failed".to_string());
+    }
+
+    Ok(amount)
+}
+
 ///////////////////////////
 ///  Vault Functions
 //////////////////////////
@@ -210,7 +707,9 @@ async fn withdraw_from_account(amount: Amount, to_account: Account) -> Result<Am
 /// lend liquidity to vault
 ///
 /// # Arguments
+/// * `asset_id` - Id of the collateral asset to lend
 /// * `amount` - Amount of tokens to convert to virtual tokens
+/// * `beneficiary` - Optional account the virtual tokens and lock are created for,defaults to the caller
 ///
 /// # Returns
 /// * `Result<bool, String>` - Ok(true) if successful, Err with message if failed
@@ -222,16 +721,27 @@ async fn withdraw_from_account(amount: Amount, to_account: Account) -> Result<Am
 /// - Updates vault's free liquidity
 /// - Amount must be >= vault's minimum amount
 /// - Reverts funding balance change if virtual token transfer fails
-#[ic_cdk::update(name = "lendToVault")]
-async fn lend_to_vault(amount: Amount) -> Result<bool, String> {
-    let user = ic_cdk::caller();
+/// - The lock records the caller as its funder when lending on behalf of a beneficiary
+#[ic_cdk::update(name = "lendToVault", guard = "lending_guard")]
+async fn lend_to_vault(
+    asset_id: AssetId,
+    amount: Amount,
+    beneficiary: Option<Account>,
+) -> Result<bool, String> {
+    let user = env::caller();
 
-    let vault_details = _get_liquidity_manager_details();
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    _check_metadata_validated(asset_id)?;
 
-    if amount < vault_details.min_amount {
+    if amount < _get_min_amount(asset_id, OperationFamily::Lending) {
         return Err("Amount is less than min amount".to_string());
     }
-    _update_user_balance(user, amount, false);
+
+    let (owner, funded_by) = _resolve_beneficiary(user, beneficiary)?;
+
+    _update_user_balance(asset_id, user, amount, false);
 
     let virtual_asset = vault_details.virtual_asset;
 
@@ -239,27 +749,29 @@ async fn lend_to_vault(amount: Amount) -> Result<bool, String> {
         .move_asset(
             amount,
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             Account {
-                owner: user,
+                owner,
                 subaccount: None,
             },
             true,
         )
         .await;
     if !mint_tx_valid {
-        _update_user_balance(user, amount, true);
+        _update_user_balance(asset_id, user, amount, true);
         return Err("Error occured during minting transaction".to_string());
     }
 
-    let mut vault = _get_vault();
+    let mut vault = _get_vault(asset_id);
     vault.free_liquidity += amount;
 
-    let stake: LockDetails = vault._create_lock(amount, LockSpan::Instant);
-    _insert_user_lock(user, stake);
-    _update_vault(vault);
+    let mut stake: LockDetails = vault._create_lock(amount, INSTANT_SPAN_ID, 0);
+    stake.funded_by = funded_by;
+    stake.asset_id = asset_id;
+    _insert_user_lock(owner, stake);
+    _update_vault(asset_id, vault);
 
     return Ok(true);
 }
@@ -267,20 +779,26 @@ async fn lend_to_vault(amount: Amount) -> Result<bool, String> {
 /// collects debt back from vault by burning virtual tokens and returning the equivalent amount to user's balance
 ///
 /// # Arguments
+/// * `asset_id` - Id of the collateral asset to collect
 /// * `amount` - Amount of virtual tokens to burn
 /// * `from_subaccount` - Optional subaccount to transfer tokens from
-#[ic_cdk::update(name = "collectFromVault")]
+#[ic_cdk::update(name = "collectFromVault", guard = "collecting_guard")]
 async fn collect_from_vault(
+    asset_id: AssetId,
     amount: Amount,
     from_sub_account: Option<Subaccount>,
 ) -> Result<bool, String> {
-    let user = ic_cdk::caller();
+    let user = env::caller();
 
-    let liquidity_manager_details = _get_liquidity_manager_details();
+    let Some(liquidity_manager_details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
 
-    let mut vault = _get_vault();
+    let mut vault = _get_vault(asset_id);
 
-    if amount < liquidity_manager_details.min_amount || vault.free_liquidity < amount {
+    if amount < _get_min_amount(asset_id, OperationFamily::Collecting)
+        || vault.free_liquidity < amount
+    {
         return Err(
             "Amount is less than min amount or vault has insufficient free liquidity".to_string(),
         );
@@ -288,6 +806,7 @@ async fn collect_from_vault(
 
     // reduce vault staking details first before inter cansiter call to avoid in-consistent state
     vault.free_liquidity -= amount;
+    _update_vault(asset_id, vault);
 
     let LiquidityManagerDetails { virtual_asset, .. } = liquidity_manager_details;
 
@@ -299,19 +818,21 @@ async fn collect_from_vault(
                 subaccount: from_sub_account,
             },
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             false,
         )
         .await;
     if !burn_tx_valid {
+        // the vault is read again as it may have changed during the call
+        let mut vault = _get_vault(asset_id);
         vault.free_liquidity += amount;
+        _update_vault(asset_id, vault);
         return Err("Error occured during burning transaction".to_string());
     }
 
-    _update_user_balance(user, amount, true);
-    _update_vault(vault);
+    _update_user_balance(asset_id, user, amount, true);
     return Ok(true);
 }
 
@@ -320,29 +841,41 @@ async fn collect_from_vault(
 /// lock virtual tokens in a vault lock for a specified duration
 ///
 /// # Arguments
+/// * `asset_id` - Id of the collateral asset whose virtual tokens are locked
 /// * `amount` - Amount of virtual tokens to stake
-/// * `stake_span` - Duration to lock tokens (2 months, 6 months, or 1 year)
+/// * `span_id` - Id of the lock span in the lock span table to lock tokens for
 /// * `from_subaccount` - Optional subaccount to transfer tokens from
+/// * `beneficiary` - Optional account the lock is created for,defaults to the caller
 ///
 /// # Returns
 /// * `bool` - True if staking succeeded, false if failed
 ///
-#[ic_cdk::update(name = "lockQTokens")]
+/// # Notes
+/// - The lock and its earnings belong to the beneficiary,the caller is recorded as its funder
+#[ic_cdk::update(name = "lockQTokens", guard = "locking_guard")]
 async fn lock_qtokens(
+    asset_id: AssetId,
     amount: Amount,
-    stake_span: LockSpan,
+    span_id: SpanId,
     from_subaccount: Option<Subaccount>,
+    beneficiary: Option<Account>,
 ) -> Result<Amount, &'static str> {
-    if let LockSpan::Instant = stake_span {
+    if span_id == INSTANT_SPAN_ID {
         return Err("Can not stake with instant stakespan");
     };
-    let user = ic_cdk::caller();
-    let vault_details = _get_liquidity_manager_details();
+    let user = env::caller();
+    let Some(vault_details) = _get_asset(asset_id) else {
+        return Err("Asset not found");
+    };
 
-    if amount < vault_details.min_amount {
+    if amount < _get_min_amount(asset_id, OperationFamily::Locking) {
         return Err("Amount less than min amount");
     }
 
+    let (owner, funded_by) = _resolve_beneficiary(user, beneficiary)?;
+
+    _reserve_span_capacity(asset_id, span_id, amount)?;
+
     let virtual_asset = vault_details.virtual_asset;
 
     let tx_valid = virtual_asset
@@ -353,21 +886,25 @@ async fn lock_qtokens(
                 subaccount: from_subaccount,
             },
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             false,
         )
         .await;
+    _release_span_capacity(asset_id, span_id, amount);
     if !tx_valid {
         return Err("Deposit transaction failed");
     }
-    let mut vault = _get_vault();
+    let mut vault = _get_vault(asset_id);
 
-    let lock = vault._create_lock(amount, stake_span);
+    let span_duration = _get_lock_span(span_id).unwrap().duration;
+    let mut lock = vault._create_lock(amount, span_id, span_duration);
+    lock.funded_by = funded_by;
+    lock.asset_id = asset_id;
 
-    _insert_user_lock(user, lock);
-    _update_vault(vault);
+    _insert_user_lock(owner, lock);
+    _update_vault(asset_id, vault);
 
     return Ok(amount);
 }
@@ -382,32 +919,48 @@ async fn lock_qtokens(
 /// * `Ok(Amount)` - Amount of tokens returned including rewards
 /// * `Err(String)` - Error message if unstaking fails
 
-#[ic_cdk::update(name = "unlockQTokens")]
+#[ic_cdk::update(name = "unlockQTokens", guard = "unlocking_guard")]
 async fn unlock_qtokens(lock_timestamp: Time) -> Result<Amount, String> {
-    let user = ic_cdk::caller();
-    let ref_lock = _get_user_lock(user, lock_timestamp);
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
 
-    if ic_cdk::api::time() < ref_lock.expiry_time {
+    if env::time() < ref_lock.expiry_time {
         return Err("Expiry time in the future".to_string());
     };
 
-    let liquidity_manager_details = _get_liquidity_manager_details();
+    let liquidity_manager_details = _get_liquidity_manager_details(ref_lock.asset_id);
 
-    let mut vault = _get_vault();
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
 
     let lock_earnings = vault._calc_lock_earnings(ref_lock);
 
-    let amount_to_send = match ref_lock.stake_span {
-        LockSpan::Instant => lock_earnings,
-        _ => ref_lock.amount + lock_earnings,
+    // the amount of a lent instant lock is held by the user,locks moved to the instant span
+    // at expiry still have their amount held by the vault
+    let amount_to_send = if ref_lock.span_id == INSTANT_SPAN_ID
+        && ref_lock.expiry_action != ExpiryAction::MoveToInstant
+    {
+        lock_earnings
+    } else {
+        ref_lock.amount + lock_earnings
     };
 
+    // remove lock before inter canister call so it can not be unlocked or transferred meanwhile
+    vault._open_lock(ref_lock);
+    _remove_user_lock(user, lock_timestamp);
+    _update_vault(ref_lock.asset_id, vault);
+
     let tx_valid = liquidity_manager_details
         .virtual_asset
         .move_asset(
             amount_to_send,
             Account {
-                owner: ic_cdk::id(),
+                owner: env::canister_id(),
                 subaccount: None,
             },
             Account {
@@ -418,214 +971,4498 @@ async fn unlock_qtokens(lock_timestamp: Time) -> Result<Amount, String> {
         )
         .await;
     if !tx_valid {
+        let mut vault = _get_vault(ref_lock.asset_id);
+        vault
+            ._span_details_mut(ref_lock.span_id)
+            .update_total_locked(ref_lock.amount, true);
+        _update_user_lock(user, lock_timestamp, ref_lock);
+        _update_vault(ref_lock.asset_id, vault);
         return Err("transaction failed".to_string());
     }
 
-    vault._open_lock(ref_lock);
-    _remove_user_lock(user, lock_timestamp);
-    _update_vault(vault);
-
     return Ok(amount_to_send);
 }
 
-/// Validates and processes a position creation request
+/// Unlocks virtual tokens before the lock's expiry time
+///
+/// The principal is returned minus a penalty proportional to the time left until expiry,
+/// the penalty is distributed to the remaining lockers of the same span
 ///
 /// # Arguments
-/// * `user` - Principal ID of the user creating position
-/// * `collateral` - Amount of collateral to lock
-/// * `debt` - Amount of leverage to borrow
+/// * `lock_timestamp` - Timestamp of the lock to unlock
 ///
 /// # Returns
-/// * `(bool, u32)` - (validity status, interest rate)
-///   - First value indicates if user has sufficient margin balance and vault has enough liquidity
-///   - Second value is the interest rate for the borrowed amount
-///
-/// If valid, updates user's margin balance and vault's free liquidity by reducing both
-#[ic_cdk::update(name = "liquidityChangeValidityCheck", guard = "approved_market_guard")]
-async fn liquidity_change_validity_check(
-    user: Principal,
-    collateral: Amount,
-    debt: Amount,
-) -> (bool, u32) {
-    let account_balance = _get_user_margin_balance(user);
+/// * `Ok(Amount)` - Amount of tokens returned including earnings
+/// * `Err(String)` - Error message if unlocking fails
+#[ic_cdk::update(name = "earlyUnlock", guard = "unlocking_guard")]
+async fn early_unlock(lock_timestamp: Time) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
 
-    let mut vault = _get_vault();
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not early unlock instant locks".to_string());
+    }
 
-    let valid = account_balance >= collateral && vault.free_liquidity >= debt;
+    if ref_lock.vesting.is_some() {
+        return Err("Can not early unlock vesting locks".to_string());
+    }
 
-    if valid {
-        vault.free_liquidity -= debt;
-        vault.debt += debt;
-        _update_user_balance(user, collateral, false);
+    let liquidity_manager_details = _get_liquidity_manager_details(ref_lock.asset_id);
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    let EarlyUnlockPreview {
+        principal_returned,
+        penalty,
+        earnings,
+    } = _preview_early_unlock(&mut vault, &mut ref_lock, env::time());
+
+    // remove lock before inter canister call so it can not be unlocked twice
+    _remove_user_lock(user, lock_timestamp);
+    _update_vault(ref_lock.asset_id, vault);
+
+    let amount_to_send = principal_returned + earnings;
+
+    let tx_valid = liquidity_manager_details
+        .virtual_asset
+        .move_asset(
+            amount_to_send,
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            Account {
+                owner: user,
+                subaccount: None,
+            },
+            true,
+        )
+        .await;
+    if !tx_valid {
+        _update_user_lock(user, lock_timestamp, ref_lock);
+        return Err("transaction failed".to_string());
     }
 
-    _update_vault(vault);
+    let mut vault = _get_vault(ref_lock.asset_id);
+    vault._open_lock(ref_lock);
+    // a span without a penalty lets locks unlock early for free
+    if penalty != 0 {
+        vault._distribute_penalty(
+            ref_lock.span_id,
+            penalty,
+            ic_cdk::api::time(),
+            &_get_lock_spans(),
+        );
+    }
+    _update_vault(ref_lock.asset_id, vault);
 
-    return (valid, 0);
+    Ok(amount_to_send)
 }
 
-/// Updates position state and distributes fees when a position is modified or closed
+/// Previews the outcome of unlocking a lock early at the current time
 ///
 /// # Arguments
-/// * `user` - Principal ID of position owner
-/// * `margin_delta` - Amount to return to user's margin balance
-/// * `manage_debt_params` - Parameters for debt repayment and fee calculation
+/// * `user` - Principal ID of the lock owner
+/// * `lock_timestamp` - Timestamp of the lock
 ///
-/// # Effects
-/// - Updates user margin balance
-/// - Adjusts vault debt and liquidity
-/// - Distributes earned fees across stake spans
-#[ic_cdk::update(name = "managePositionUpdate", guard = "approved_market_guard")]
-async fn manage_position_update(
+/// # Returns
+/// * `Ok(EarlyUnlockPreview)` - Principal returned,penalty forfeited and earnings paid out
+/// * `Err(String)` - Error message if the lock does not exist
+#[ic_cdk::query(name = "previewEarlyUnlock")]
+fn preview_early_unlock(
     user: Principal,
-    margin_delta: Amount,
-    manage_debt_params: ManageDebtParams,
-) {
-    if margin_delta != 0 {
-        _update_user_balance(user, margin_delta, true);
-    }
+    lock_timestamp: Time,
+) -> Result<EarlyUnlockPreview, String> {
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    Ok(_preview_early_unlock(
+        &mut vault,
+        &mut ref_lock,
+        env::time(),
+    ))
+}
+
+/// Gets the trailing yields of the instant span (earned by lenders) and of every lock span of
+/// an asset
+///
+/// Yields are computed from the growth of each span's lifetime earnings per token since the
+/// snapshot taken at the start of each window
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Vec<SpanYield>` - Total locked and 7, 30 and 90 day APY of each span,the instant span first
+///
+/// # Notes
+/// - An APY is None until a snapshot old enough to cover its window has been taken
+#[ic_cdk::query(name = "getSpanYields")]
+fn get_span_yields(asset_id: AssetId) -> Vec<SpanYield> {
+    if _get_asset(asset_id).is_none() {
+        return Vec::new();
+    }
+    let current_time = env::time();
+    let vault = _get_vault(asset_id);
+
+    let window_snapshots: Vec<Option<(Time, YieldSnapshot)>> = YIELD_WINDOWS
+        .iter()
+        .map(|days| _get_yield_snapshot_before(asset_id, current_time.saturating_sub(days * DAY)))
+        .collect();
+
+    let span_ids = std::iter::once(INSTANT_SPAN_ID).chain(_get_lock_spans().into_keys());
+
+    span_ids
+        .map(|span_id| {
+            let details = vault._span_details(span_id);
+            let apys: Vec<Option<u64>> = window_snapshots
+                .iter()
+                .map(|window_snapshot| {
+                    let (time, snapshot) = window_snapshot.as_ref()?;
+                    let past_earnings_per_token = snapshot
+                        .spans
+                        .get(&span_id)
+                        .map(|span| span.lifetime_earnings_per_token)
+                        .unwrap_or_default();
+                    Some(_trailing_apy(
+                        details.lifetime_earnings_per_token,
+                        past_earnings_per_token,
+                        current_time - time,
+                    ))
+                })
+                .collect();
+
+            SpanYield {
+                span_id,
+                total_locked: details.total_locked,
+                apy_7d: apys[0],
+                apy_30d: apys[1],
+                apy_90d: apys[2],
+            }
+        })
+        .collect()
+}
+
+/// Gets the voting power of an account in an asset at a time
+///
+/// The voting power of a lock is its amount × its remaining lock time / the max voting span
+/// (one year),decaying linearly to 0 at its expiry time.Instant locks have no voting power
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset,voting power is tracked separately for each asset
+/// * `account` - The account,locks are held by default accounts only
+/// * `at_time` - The time in nanoseconds,past voting power is served from checkpoints
+///
+/// # Returns
+/// * `Amount` - The sum of the voting power of the account's locks
+#[ic_cdk::query(name = "getVotingPower")]
+fn get_voting_power(asset_id: AssetId, account: Account, at_time: Time) -> Amount {
+    if !_is_default_subaccount(account.subaccount) {
+        return 0;
+    }
+    _account_voting_point_at(asset_id, account.owner, _to_seconds(at_time))._voting_power()
+}
+
+/// Gets the total voting power of all locks of an asset at a time
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `at_time` - The time in nanoseconds,past voting power is served from checkpoints
+///
+/// # Returns
+/// * `Amount` - The sum of the voting power of all locks
+#[ic_cdk::query(name = "getTotalVotingPower")]
+fn get_total_voting_power(asset_id: AssetId, at_time: Time) -> Amount {
+    _total_voting_point_at(asset_id, _to_seconds(at_time))._voting_power()
+}
+
+/// Sets the compounding flag of a lock
+///
+/// When set, the lock's earnings are rolled into its amount periodically and whenever the lock
+/// is interacted with, instead of being paid out on unlock as separate earnings
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to update
+/// * `compounding` - True to compound the lock's earnings, false to stop compounding
+///
+/// # Returns
+/// * `Ok(Amount)` - Earnings rolled into the lock's amount by this call
+/// * `Err(String)` - Error message if the lock does not exist or is an instant lock
+#[ic_cdk::update(name = "setLockCompounding", guard = "locking_guard")]
+fn set_lock_compounding(lock_timestamp: Time, compounding: bool) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not compound instant locks".to_string());
+    }
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    // settle earnings accrued under the previous setting first
+    let compounded = if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock)
+    } else {
+        0
+    };
+    ref_lock.compounding = compounding;
+
+    _update_user_lock(user, lock_timestamp, ref_lock);
+    _update_vault(ref_lock.asset_id, vault);
+
+    Ok(compounded)
+}
+
+/// Sets what happens to a lock once its expiry time is reached
+///
+/// Renewing locks are locked again for the same span at expiry,locks moved to the instant span
+/// are locked again in the instant span with their earnings and can be unlocked at any time.
+/// Expired locks are processed periodically so the action is applied shortly after expiry
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to update
+/// * `expiry_action` - The action applied at expiry
+///
+/// # Returns
+/// * `Ok(())` - If the action was set
+/// * `Err(String)` - Error message if the lock does not exist or is an instant lock
+#[ic_cdk::update(name = "setLockExpiryAction", guard = "locking_guard")]
+fn set_lock_expiry_action(lock_timestamp: Time, expiry_action: ExpiryAction) -> Result<(), String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not set expiry action of instant locks".to_string());
+    }
+
+    if ref_lock.vesting.is_some() {
+        return Err("Can not set expiry action of vesting locks".to_string());
+    }
+
+    ref_lock.expiry_action = expiry_action;
+    _update_user_lock(user, lock_timestamp, ref_lock);
+
+    Ok(())
+}
+
+/// Extends a lock by moving it to an equal or longer span
+///
+/// Earnings accrued in the current span are settled first,they are rolled into the lock's amount
+/// for compounding locks and sent to the user otherwise.The lock's expiry time restarts from
+/// the current time
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to extend
+/// * `new_span_id` - Id of the span to move the lock to,must not be shorter than the current span
+///
+/// # Returns
+/// * `Ok(Amount)` - Earnings sent to the user when settling
+/// * `Err(String)` - Error message if extending fails,if only sending the earnings fails the lock
+///   is extended and keeps its earnings
+#[ic_cdk::update(name = "extendLock", guard = "locking_guard")]
+async fn extend_lock(lock_timestamp: Time, new_span_id: SpanId) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not extend instant locks".to_string());
+    }
+
+    if ref_lock.vesting.is_some() {
+        return Err("Can not extend vesting locks".to_string());
+    }
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    // a lock extended within its own span is already counted in the span's total
+    let moved_amount = if new_span_id == ref_lock.span_id {
+        0
+    } else {
+        ref_lock.amount
+    };
+    let new_span = _validate_lock_span(ref_lock.asset_id, new_span_id, moved_amount, &vault)?;
+    let current_span_duration = _get_lock_span(ref_lock.span_id)
+        .map(|config| config.duration)
+        .unwrap_or_default();
+
+    if new_span.duration < current_span_duration {
+        return Err("Can not move lock to a shorter span".to_string());
+    }
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
+    let lock_earnings = vault._calc_lock_earnings(ref_lock);
+
+    let new_lock = vault._move_lock(ref_lock, new_span_id, new_span.duration);
+
+    _update_user_lock(user, lock_timestamp, new_lock);
+    _update_vault(ref_lock.asset_id, vault);
+
+    if lock_earnings == 0 {
+        return Ok(0);
+    }
+
+    let liquidity_manager_details = _get_liquidity_manager_details(ref_lock.asset_id);
+
+    let tx_valid = liquidity_manager_details
+        .virtual_asset
+        .move_asset(
+            lock_earnings,
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            Account {
+                owner: user,
+                subaccount: None,
+            },
+            true,
+        )
+        .await;
+    if !tx_valid {
+        _unsettle_lock_earnings(user, lock_timestamp, new_lock.token_id, lock_earnings);
+        return Err("Earnings transaction failed,lock was extended with its earnings".to_string());
+    }
+
+    Ok(lock_earnings)
+}
+
+/// Adds virtual tokens to an existing lock
+///
+/// The lock's span and expiry time are left unchanged,earnings accrued by the lock are settled
+/// first (compounded for compounding locks) so the added amount only earns from now on
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to increase
+/// * `amount` - Amount of virtual tokens to add
+/// * `from_subaccount` - Optional subaccount to transfer tokens from
+///
+/// # Returns
+/// * `Ok(Amount)` - The new amount of the lock
+/// * `Err(String)` - Error message if increasing fails
+#[ic_cdk::update(name = "increaseLock", guard = "locking_guard")]
+async fn increase_lock(
+    lock_timestamp: Time,
+    amount: Amount,
+    from_subaccount: Option<Subaccount>,
+) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not increase instant locks".to_string());
+    }
+
+    if ref_lock.vesting.is_some() {
+        return Err("Can not increase vesting locks".to_string());
+    }
+
+    if env::time() >= ref_lock.expiry_time {
+        return Err("Can not increase expired locks".to_string());
+    }
+
+    let vault_details = _get_liquidity_manager_details(ref_lock.asset_id);
+
+    if amount < _get_min_amount(ref_lock.asset_id, OperationFamily::Locking) {
+        return Err("Amount less than min amount".to_string());
+    }
+
+    _reserve_span_capacity(ref_lock.asset_id, ref_lock.span_id, amount)?;
+
+    let virtual_asset = vault_details.virtual_asset;
+
+    let tx_valid = virtual_asset
+        .move_asset(
+            amount,
+            Account {
+                owner: user,
+                subaccount: from_subaccount,
+            },
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            false,
+        )
+        .await;
+    _release_span_capacity(ref_lock.asset_id, ref_lock.span_id, amount);
+    if !tx_valid {
+        return Err("Deposit transaction failed".to_string());
+    }
+
+    // the lock could have been closed or have expired during the inter canister call
+    let Some(mut ref_lock) =
+        _try_get_user_lock(user, lock_timestamp).filter(|lock| env::time() < lock.expiry_time)
+    else {
+        let mut vault = _get_vault(ref_lock.asset_id);
+        let span_duration = _get_lock_span(ref_lock.span_id)
+            .map(|config| config.duration)
+            .unwrap_or_default();
+        let mut lock = vault._create_lock(amount, ref_lock.span_id, span_duration);
+        lock.asset_id = ref_lock.asset_id;
+        _insert_user_lock(user, lock);
+        _update_vault(ref_lock.asset_id, vault);
+        return Err("Lock was closed or expired,amount was locked in a new lock".to_string());
+    };
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
+    let lock_earnings = vault._settle_lock(&mut ref_lock);
+    vault._increase_lock(&mut ref_lock, amount);
+
+    _update_user_lock(user, lock_timestamp, ref_lock);
+    _update_vault(ref_lock.asset_id, vault);
+
+    if lock_earnings == 0 {
+        return Ok(ref_lock.amount);
+    }
+
+    let tx_valid = virtual_asset
+        .move_asset(
+            lock_earnings,
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            Account {
+                owner: user,
+                subaccount: None,
+            },
+            true,
+        )
+        .await;
+    if !tx_valid {
+        _unsettle_lock_earnings(user, lock_timestamp, ref_lock.token_id, lock_earnings);
+        return Err("Earnings transaction failed,amount was added to the lock".to_string());
+    }
+
+    Ok(ref_lock.amount)
+}
+
+/// Unlocks part of an expired lock
+///
+/// The unlocked part receives the same share of the lock's earnings,the rest of the lock
+/// keeps earning
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to unlock from
+/// * `amount` - Amount of the lock to unlock,must be less than the lock's amount
+///
+/// # Returns
+/// * `Ok(Amount)` - Amount of tokens returned including earnings
+/// * `Err(String)` - Error message if unlocking fails
+#[ic_cdk::update(name = "partialUnlock", guard = "unlocking_guard")]
+async fn partial_unlock(lock_timestamp: Time, amount: Amount) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not partially unlock instant locks".to_string());
+    }
+
+    if ref_lock.vesting.is_some() {
+        return Err("Can not partially unlock vesting locks".to_string());
+    }
+
+    if env::time() < ref_lock.expiry_time {
+        return Err("Expiry time in the future".to_string());
+    };
+
+    let liquidity_manager_details = _get_liquidity_manager_details(ref_lock.asset_id);
+
+    if amount < _get_min_amount(ref_lock.asset_id, OperationFamily::Unlocking) || amount == 0 {
+        return Err("Amount less than min amount".to_string());
+    }
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
+
+    if amount >= ref_lock.amount {
+        return Err("Amount must be less than the lock amount".to_string());
+    }
+
+    let lifetime_earnings_per_token = vault
+        ._span_details(ref_lock.span_id)
+        .lifetime_earnings_per_token;
+    let unlocked_part = ref_lock._split(amount, lifetime_earnings_per_token);
+
+    let amount_to_send = unlocked_part.amount + vault._calc_lock_earnings(unlocked_part);
+
+    vault._open_lock(unlocked_part);
+
+    // update lock before inter canister call so the same amount can not be unlocked twice
+    _update_user_lock(user, lock_timestamp, ref_lock);
+    _update_vault(ref_lock.asset_id, vault);
+
+    let tx_valid = liquidity_manager_details
+        .virtual_asset
+        .move_asset(
+            amount_to_send,
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            Account {
+                owner: user,
+                subaccount: None,
+            },
+            true,
+        )
+        .await;
+    if !tx_valid {
+        let mut vault = _get_vault(ref_lock.asset_id);
+        vault
+            ._span_details_mut(unlocked_part.span_id)
+            .update_total_locked(unlocked_part.amount, true);
+        // re-read the lock and only add back the unlocked part,so changes made to the lock during
+        // the inter canister call are kept
+        match _get_held_lock(user, lock_timestamp, ref_lock.token_id) {
+            Some(mut lock) if lock.span_id == unlocked_part.span_id => {
+                lock._merge(unlocked_part);
+                _update_user_lock(user, lock_timestamp, lock);
+            }
+            // the lock was transferred or left its span during the call,so the unlocked part
+            // is given back to the caller as a new lock
+            _ => {
+                _insert_user_lock(user, unlocked_part);
+            }
+        }
+        _update_vault(ref_lock.asset_id, vault);
+        return Err("transaction failed".to_string());
+    }
+
+    Ok(amount_to_send)
+}
+
+/// Splits a lock into several locks with the same span and expiry time
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the lock to split
+/// * `amounts` - Amounts of the new locks,the rest stays in the original lock
+///
+/// # Returns
+/// * `Ok(Vec<Time>)` - Timestamps of the new locks in the order of `amounts`
+/// * `Err(String)` - Error message if splitting fails
+#[ic_cdk::update(name = "splitLock", guard = "locking_guard")]
+fn split_lock(lock_timestamp: Time, amounts: Vec<Amount>) -> Result<Vec<Time>, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    if ref_lock.span_id == INSTANT_SPAN_ID {
+        return Err("Can not split instant locks".to_string());
+    }
+
+    if ref_lock.vesting.is_some() {
+        return Err("Can not split vesting locks".to_string());
+    }
+
+    let min_amount = _get_min_amount(ref_lock.asset_id, OperationFamily::Locking);
+    if amounts.is_empty()
+        || amounts
+            .iter()
+            .any(|amount| *amount == 0 || *amount < min_amount)
+    {
+        return Err("Amount less than min amount".to_string());
+    }
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
+
+    let Some(total_amount) = amounts
+        .iter()
+        .try_fold(0 as Amount, |total, amount| total.checked_add(*amount))
+    else {
+        return Err("Amounts overflow".to_string());
+    };
+
+    if total_amount >= ref_lock.amount {
+        return Err("Amounts must add up to less than the lock amount".to_string());
+    }
+
+    let lifetime_earnings_per_token = vault
+        ._span_details(ref_lock.span_id)
+        .lifetime_earnings_per_token;
+    let new_locks_timestamps = amounts
+        .into_iter()
+        .map(|amount| _insert_user_lock(user, ref_lock._split(amount, lifetime_earnings_per_token)))
+        .collect();
+
+    _update_user_lock(user, lock_timestamp, ref_lock);
+    _update_vault(ref_lock.asset_id, vault);
+
+    Ok(new_locks_timestamps)
+}
+
+/// Merges several locks of the same span and asset into one lock
+///
+/// The locks are merged into the first lock,which takes the latest expiry time of all the locks
+///
+/// # Arguments
+/// * `lock_timestamps` - Timestamps of the locks to merge
+///
+/// # Returns
+/// * `Ok(Amount)` - The amount of the merged lock
+/// * `Err(String)` - Error message if merging fails
+#[ic_cdk::update(name = "mergeLocks", guard = "locking_guard")]
+fn merge_locks(lock_timestamps: Vec<Time>) -> Result<Amount, String> {
+    let user = env::caller();
+
+    let mut unique_timestamps = lock_timestamps.clone();
+    unique_timestamps.sort();
+    unique_timestamps.dedup();
+    if lock_timestamps.len() < 2 || unique_timestamps.len() != lock_timestamps.len() {
+        return Err("At least two distinct locks are required".to_string());
+    }
+
+    let mut locks = Vec::new();
+    for timestamp in &lock_timestamps {
+        let Some(lock) = _try_get_user_lock(user, *timestamp) else {
+            return Err("Lock not found".to_string());
+        };
+        locks.push(lock);
+    }
+
+    let span_id = locks[0].span_id;
+    if span_id == INSTANT_SPAN_ID {
+        return Err("Can not merge instant locks".to_string());
+    }
+    if locks.iter().any(|lock| lock.span_id != span_id) {
+        return Err("Locks must have the same span".to_string());
+    }
+    let asset_id = locks[0].asset_id;
+    if locks.iter().any(|lock| lock.asset_id != asset_id) {
+        return Err("Locks must have the same asset".to_string());
+    }
+    if locks.iter().any(|lock| lock.vesting.is_some()) {
+        return Err("Can not merge vesting locks".to_string());
+    }
+
+    let mut vault = _get_vault(asset_id);
+
+    for lock in locks.iter_mut().filter(|lock| lock.compounding) {
+        vault._compound_lock(lock);
+    }
+
+    let mut merged_lock = locks[0];
+    for (timestamp, lock) in lock_timestamps.iter().zip(locks).skip(1) {
+        merged_lock._merge(lock);
+        _remove_user_lock(user, *timestamp);
+    }
+
+    _update_user_lock(user, lock_timestamps[0], merged_lock);
+    _update_vault(asset_id, vault);
+
+    Ok(merged_lock.amount)
+}
+
+/// Claims the vested principal of a vesting lock
+///
+/// The vested part of the lock is unlocked and sent to the user with the earnings accrued on it,
+/// the rest of the lock keeps earning in its span
+///
+/// # Arguments
+/// * `lock_timestamp` - Timestamp of the vesting lock
+///
+/// # Returns
+/// * `Ok(Amount)` - Amount of tokens returned including earnings
+/// * `Err(String)` - Error message if claiming fails
+#[ic_cdk::update(name = "claimVested", guard = "unlocking_guard")]
+async fn claim_vested(lock_timestamp: Time) -> Result<Amount, String> {
+    let user = env::caller();
+    let Some(mut ref_lock) = _try_get_user_lock(user, lock_timestamp) else {
+        return Err("Lock not found".to_string());
+    };
+
+    let Some(mut vesting) = ref_lock.vesting else {
+        return Err("Lock is not a vesting lock".to_string());
+    };
+
+    let mut vault = _get_vault(ref_lock.asset_id);
+
+    if ref_lock.compounding {
+        vault._compound_lock(&mut ref_lock);
+    }
+
+    let vested_amount = vesting._vested_amount(ref_lock.amount + vesting.claimed, env::time());
+    let claimable = vested_amount.saturating_sub(vesting.claimed);
+    if claimable == 0 {
+        return Err("Nothing vested to claim".to_string());
+    }
+
+    let initial_lock = ref_lock;
+    let fully_vested = claimable >= ref_lock.amount;
+    let claimed_part = if fully_vested {
+        ref_lock
+    } else {
+        let lifetime_earnings_per_token = vault
+            ._span_details(ref_lock.span_id)
+            .lifetime_earnings_per_token;
+        ref_lock._split(claimable, lifetime_earnings_per_token)
+    };
+
+    let amount_to_send = claimed_part.amount + vault._calc_lock_earnings(claimed_part);
+
+    vault._open_lock(claimed_part);
+
+    // update lock before inter canister call so the same amount can not be claimed twice
+    if fully_vested {
+        _remove_user_lock(user, lock_timestamp);
+    } else {
+        vesting.claimed += claimable;
+        ref_lock.vesting = Some(vesting);
+        _update_user_lock(user, lock_timestamp, ref_lock);
+    }
+    _update_vault(ref_lock.asset_id, vault);
+
+    let liquidity_manager_details = _get_liquidity_manager_details(ref_lock.asset_id);
+
+    let tx_valid = liquidity_manager_details
+        .virtual_asset
+        .move_asset(
+            amount_to_send,
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            Account {
+                owner: user,
+                subaccount: None,
+            },
+            true,
+        )
+        .await;
+    if !tx_valid {
+        let mut vault = _get_vault(ref_lock.asset_id);
+        vault
+            ._span_details_mut(claimed_part.span_id)
+            .update_total_locked(claimed_part.amount, true);
+        if fully_vested {
+            _update_user_lock(
+                user,
+                _free_lock_timestamp(user, lock_timestamp),
+                initial_lock,
+            );
+        } else if let Some(mut lock) = _get_held_lock(user, lock_timestamp, initial_lock.token_id) {
+            // re-read the lock and only add back the claimed part,so changes made to the lock
+            // during the inter canister call are kept
+            lock._merge(claimed_part);
+            if let Some(vesting) = lock.vesting.as_mut() {
+                vesting.claimed -= claimable.min(vesting.claimed);
+            }
+            _update_user_lock(user, lock_timestamp, lock);
+        } else {
+            // the lock was transferred during the inter canister call,the claimed part is kept
+            // in a new lock
+            _insert_user_lock(
+                user,
+                LockDetails {
+                    vesting: None,
+                    ..claimed_part
+                },
+            );
+        }
+        _update_vault(ref_lock.asset_id, vault);
+        return Err("transaction failed".to_string());
+    }
+
+    Ok(amount_to_send)
+}
+
+// --------------------------------------------------------------------------------------
+// ICRC-7 Lock Tokens
+// --------------------------------------------------------------------------------------
+
+/// Gets the name of the lock token collection
+#[ic_cdk::query]
+fn icrc7_name() -> String {
+    COLLECTION_NAME.to_string()
+}
+
+/// Gets the symbol of the lock token collection
+#[ic_cdk::query]
+fn icrc7_symbol() -> String {
+    COLLECTION_SYMBOL.to_string()
+}
+
+/// Gets the number of lock tokens in existence
+#[ic_cdk::query]
+fn icrc7_total_supply() -> Nat {
+    Nat::from(LOCK_TOKENS.with_borrow(|reference| reference.len()))
+}
+
+/// Gets the max number of lock tokens,None since locks are not capped
+#[ic_cdk::query]
+fn icrc7_supply_cap() -> Option<Nat> {
+    None
+}
+
+/// Gets the metadata of the lock token collection
+#[ic_cdk::query]
+fn icrc7_collection_metadata() -> Vec<(String, ICRC3Value)> {
+    vec![
+        (
+            "icrc7:name".to_string(),
+            ICRC3Value::Text(COLLECTION_NAME.to_string()),
+        ),
+        (
+            "icrc7:symbol".to_string(),
+            ICRC3Value::Text(COLLECTION_SYMBOL.to_string()),
+        ),
+        (
+            "icrc7:total_supply".to_string(),
+            ICRC3Value::Nat(icrc7_total_supply()),
+        ),
+        (
+            "icrc7:max_query_batch_size".to_string(),
+            ICRC3Value::Nat(Nat::from(MAX_QUERY_BATCH_SIZE)),
+        ),
+        (
+            "icrc7:max_update_batch_size".to_string(),
+            ICRC3Value::Nat(Nat::from(MAX_UPDATE_BATCH_SIZE)),
+        ),
+        (
+            "icrc7:default_take_value".to_string(),
+            ICRC3Value::Nat(Nat::from(DEFAULT_TAKE_VALUE)),
+        ),
+        (
+            "icrc7:max_take_value".to_string(),
+            ICRC3Value::Nat(Nat::from(MAX_TAKE_VALUE)),
+        ),
+        (
+            "icrc7:max_memo_size".to_string(),
+            ICRC3Value::Nat(Nat::from(MAX_TOKEN_MEMO_SIZE)),
+        ),
+        (
+            "icrc7:atomic_batch_transfers".to_string(),
+            ICRC3Value::Text("false".to_string()),
+        ),
+    ]
+}
+
+/// Gets the max number of tokens or accounts in a single query call
+#[ic_cdk::query]
+fn icrc7_max_query_batch_size() -> Option<Nat> {
+    Some(Nat::from(MAX_QUERY_BATCH_SIZE))
+}
+
+/// Gets the max number of transfers in a single `icrc7_transfer` call
+#[ic_cdk::query]
+fn icrc7_max_update_batch_size() -> Option<Nat> {
+    Some(Nat::from(MAX_UPDATE_BATCH_SIZE))
+}
+
+/// Gets the number of token ids returned by `icrc7_tokens_of` when no take is given
+#[ic_cdk::query]
+fn icrc7_default_take_value() -> Option<Nat> {
+    Some(Nat::from(DEFAULT_TAKE_VALUE))
+}
+
+/// Gets the max number of token ids returned by a single `icrc7_tokens_of` call
+#[ic_cdk::query]
+fn icrc7_max_take_value() -> Option<Nat> {
+    Some(Nat::from(MAX_TAKE_VALUE))
+}
+
+/// Gets the max size of a transfer's memo in bytes
+#[ic_cdk::query]
+fn icrc7_max_memo_size() -> Option<Nat> {
+    Some(Nat::from(MAX_TOKEN_MEMO_SIZE))
+}
+
+/// Gets whether the transfers of a batch are applied atomically,they are applied one by one
+#[ic_cdk::query]
+fn icrc7_atomic_batch_transfers() -> Option<bool> {
+    Some(false)
+}
+
+/// Gets the standards implemented by the lock token collection
+#[ic_cdk::query]
+fn icrc10_supported_standards() -> Vec<StandardRecord> {
+    SUPPORTED_TOKEN_STANDARDS
+        .iter()
+        .map(|(name, url)| StandardRecord {
+            name: name.to_string(),
+            url: url.to_string(),
+        })
+        .collect()
+}
+
+/// Gets the owners of lock tokens
+///
+/// # Arguments
+/// * `token_ids` - Ids of the tokens
+///
+/// # Returns
+/// * `Vec<Option<Account>>` - The owner of each token,None if the token does not exist
+#[ic_cdk::query]
+fn icrc7_owner_of(token_ids: Vec<Nat>) -> Vec<Option<Account>> {
+    _check_query_batch_size(token_ids.len());
+    token_ids
+        .iter()
+        .map(|token_id| {
+            _get_lock_token(token_id).map(|(owner, _)| Account {
+                owner,
+                subaccount: None,
+            })
+        })
+        .collect()
+}
+
+/// Gets the number of lock tokens held by accounts
+#[ic_cdk::query]
+fn icrc7_balance_of(accounts: Vec<Account>) -> Vec<Nat> {
+    _check_query_batch_size(accounts.len());
+    accounts
+        .iter()
+        .map(|account| Nat::from(_count_account_lock_tokens(*account)))
+        .collect()
+}
+
+/// Gets the lock tokens held by an account
+///
+/// # Arguments
+/// * `account` - The account holding the tokens
+/// * `prev` - Only tokens with an id greater than prev are returned
+/// * `take` - Max number of tokens returned
+///
+/// # Returns
+/// * `Vec<Nat>` - Ids of the tokens in ascending order
+#[ic_cdk::query]
+fn icrc7_tokens_of(account: Account, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let take = take
+        .map(|take| u64::try_from(take.0).unwrap_or(MAX_TAKE_VALUE))
+        .unwrap_or(DEFAULT_TAKE_VALUE)
+        .min(MAX_TAKE_VALUE);
+    // ids past the largest token id can not be followed by any token
+    let prev = match prev.map(|prev| _to_token_id(&prev)) {
+        Some(None) => return Vec::new(),
+        Some(prev) => prev,
+        None => None,
+    };
+
+    _get_account_lock_tokens(account, prev, take as usize)
+        .into_iter()
+        .map(Nat::from)
+        .collect()
+}
+
+/// Gets the metadata of lock tokens
+///
+/// The metadata of a token is the asset, span, amount, expiry time and accrued earnings of its lock,
+/// and the funder of locks created on behalf of their owner
+///
+/// # Arguments
+/// * `token_ids` - Ids of the tokens
+///
+/// # Returns
+/// * `Vec<Option<Vec<(String, ICRC3Value)>>>` - The metadata of each token,None if the token does not exist
+#[ic_cdk::query]
+fn icrc7_token_metadata(token_ids: Vec<Nat>) -> Vec<Option<Vec<(String, ICRC3Value)>>> {
+    _check_query_batch_size(token_ids.len());
+    token_ids
+        .iter()
+        .map(|token_id| {
+            let (owner, timestamp) = _get_lock_token(token_id)?;
+            let lock = _get_user_lock(owner, timestamp);
+            let vault = _get_vault(lock.asset_id);
+            let mut metadata = vec![
+                (
+                    "asset_id".to_string(),
+                    ICRC3Value::Nat(Nat::from(lock.asset_id)),
+                ),
+                (
+                    "span_id".to_string(),
+                    ICRC3Value::Nat(Nat::from(lock.span_id)),
+                ),
+                (
+                    "amount".to_string(),
+                    ICRC3Value::Nat(Nat::from(lock.amount)),
+                ),
+                (
+                    "expiry_time".to_string(),
+                    ICRC3Value::Nat(Nat::from(lock.expiry_time)),
+                ),
+                (
+                    "earnings".to_string(),
+                    ICRC3Value::Nat(Nat::from(vault._calc_lock_earnings(lock))),
+                ),
+            ];
+            if let Some(funded_by) = lock.funded_by {
+                metadata.push((
+                    "funded_by".to_string(),
+                    ICRC3Value::Text(funded_by.to_text()),
+                ));
+            }
+            Some(metadata)
+        })
+        .collect()
+}
+
+/// Transfers lock tokens
+///
+/// The lock a token represents is moved to the recipient,who can then unlock it and claim its
+/// earnings.Locks are owned by principals so tokens can only be sent from and to default accounts
+///
+/// # Arguments
+/// * `args` - The transfers to make
+///
+/// # Returns
+/// * `Vec<Option<TransferResult>>` - The transaction index or error of each transfer
+#[ic_cdk::update(guard = "transfers_guard")]
+fn icrc7_transfer(args: Vec<TransferArg>) -> Vec<Option<TransferResult>> {
+    let caller = env::caller();
+
+    if args.len() as u64 > MAX_UPDATE_BATCH_SIZE {
+        return vec![Some(Err(TransferError::GenericBatchError {
+            error_code: Nat::from(0u64),
+            message: "Exceeds max update batch size".to_string(),
+        }))];
+    }
+
+    args.into_iter()
+        .map(|arg| Some(_transfer_lock_token(caller, arg)))
+        .collect()
+}
+
+// --------------------------------------------------------------------------------------
+// ICRC-1/2/3 Built-in QToken Ledger
+// --------------------------------------------------------------------------------------
+
+/// Gets the name of the built-in QToken
+#[ic_cdk::query]
+fn icrc1_name() -> String {
+    QTOKEN_STATE.with_borrow(|reference| reference.get().name.clone())
+}
+
+/// Gets the symbol of the built-in QToken
+#[ic_cdk::query]
+fn icrc1_symbol() -> String {
+    QTOKEN_STATE.with_borrow(|reference| reference.get().symbol.clone())
+}
+
+/// Gets the decimals of the built-in QToken,the decimals of the asset it represents
+#[ic_cdk::query]
+fn icrc1_decimals() -> u8 {
+    QTOKEN_STATE.with_borrow(|reference| reference.get().decimals)
+}
+
+/// Gets the fee of a built-in QToken transfer
+#[ic_cdk::query]
+fn icrc1_fee() -> Nat {
+    Nat::from(QTOKEN_FEE)
+}
+
+/// Gets the metadata of the built-in QToken
+#[ic_cdk::query]
+fn icrc1_metadata() -> Vec<(String, MetadataValue)> {
+    _qtoken_metadata()
+}
+
+/// Gets the number of built-in QTokens in existence
+#[ic_cdk::query]
+fn icrc1_total_supply() -> Nat {
+    Nat::from(QTOKEN_STATE.with_borrow(|reference| reference.get().total_supply))
+}
+
+/// Gets the minting account of the built-in QToken,the default account of the canister
+#[ic_cdk::query]
+fn icrc1_minting_account() -> Option<Account> {
+    Some(Account::from(env::canister_id()))
+}
+
+/// Gets the built-in QToken balance of an account
+#[ic_cdk::query]
+fn icrc1_balance_of(account: Account) -> Nat {
+    Nat::from(_qtoken_balance_of(account))
+}
+
+/// Gets the token standards implemented by the canister's built-in QToken ledger
+#[ic_cdk::query]
+fn icrc1_supported_standards() -> Vec<StandardRecord> {
+    SUPPORTED_STANDARDS
+        .iter()
+        .map(|(name, url)| StandardRecord {
+            name: name.to_string(),
+            url: url.to_string(),
+        })
+        .collect()
+}
+
+/// Transfers built-in QTokens from an account of the caller
+///
+/// # Arguments
+/// * `arg` - The ICRC-1 transfer,transfers to the minting account burn and transfers from it mint
+///
+/// # Returns
+/// * `Ok(Nat)` - The index of the transfer's block
+/// * `Err(TransferError)` - If the fee,memo or created at time is invalid,the balance is
+///   insufficient or the same transfer was made within the transaction window
+#[ic_cdk::update(guard = "transfers_guard")]
+fn icrc1_transfer(arg: Icrc1TransferArg) -> Result<Nat, Icrc1TransferError> {
+    let from = Account {
+        owner: env::caller(),
+        subaccount: arg.from_subaccount,
+    };
+    let Ok(amount) = Amount::try_from(arg.amount.0) else {
+        return Err(Icrc1TransferError::InsufficientFunds {
+            balance: Nat::from(_qtoken_balance_of(from)),
+        });
+    };
+
+    _qtoken_transfer(
+        None,
+        from,
+        arg.to,
+        amount,
+        arg.fee,
+        arg.memo,
+        arg.created_at_time,
+    )
+    .map(Nat::from)
+    .map_err(_to_icrc1_transfer_error)
+}
+
+/// Approves a spender to transfer built-in QTokens from an account of the caller
+///
+/// # Arguments
+/// * `args` - The ICRC-2 approval,replacing any previous allowance of the spender
+///
+/// # Returns
+/// * `Ok(Nat)` - The index of the approval's block
+/// * `Err(ApproveError)` - If the expected allowance does not match,the approval expired or
+///   the fee,memo or created at time is invalid
+#[ic_cdk::update]
+fn icrc2_approve(args: ApproveArgs) -> Result<Nat, ApproveError> {
+    let from = Account {
+        owner: env::caller(),
+        subaccount: args.from_subaccount,
+    };
+
+    _qtoken_approve(from, args).map(Nat::from)
+}
+
+/// Gets the built-in QToken allowance given by an account to a spender
+#[ic_cdk::query]
+fn icrc2_allowance(args: AllowanceArgs) -> Allowance {
+    let allowance = _qtoken_allowance(args.account, args.spender);
+
+    Allowance {
+        allowance: Nat::from(allowance._spendable(env::time())),
+        expires_at: allowance.expires_at,
+    }
+}
+
+/// Transfers built-in QTokens from an account that approved the caller
+///
+/// # Arguments
+/// * `args` - The ICRC-2 transfer from
+///
+/// # Returns
+/// * `Ok(Nat)` - The index of the transfer's block
+/// * `Err(TransferFromError)` - If the allowance or balance is insufficient,the fee,memo or
+///   created at time is invalid or the same transfer was made within the transaction window
+#[ic_cdk::update(guard = "transfers_guard")]
+fn icrc2_transfer_from(args: TransferFromArgs) -> Result<Nat, TransferFromError> {
+    let spender = Account {
+        owner: env::caller(),
+        subaccount: args.spender_subaccount,
+    };
+    let Ok(amount) = Amount::try_from(args.amount.0) else {
+        return Err(TransferFromError::InsufficientFunds {
+            balance: Nat::from(_qtoken_balance_of(args.from)),
+        });
+    };
+
+    _qtoken_transfer(
+        Some(spender),
+        args.from,
+        args.to,
+        amount,
+        args.fee,
+        args.memo,
+        args.created_at_time,
+    )
+    .map(Nat::from)
+}
+
+/// Gets blocks of the built-in QToken ledger
+///
+/// # Arguments
+/// * `requests` - Ranges of blocks,at most `MAX_BLOCKS_PER_REQUEST` blocks are returned in total
+///
+/// # Returns
+/// * `GetBlocksResult` - The number of blocks and the requested blocks,blocks are never archived
+#[ic_cdk::query]
+fn icrc3_get_blocks(requests: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    let log_length = QTOKEN_BLOCKS.with_borrow(|reference| reference.len());
+
+    let mut blocks: Vec<BlockWithId> = vec![];
+    for request in requests {
+        let remaining = MAX_BLOCKS_PER_REQUEST - blocks.len() as u64;
+        let (Ok(start), Ok(length)) = (
+            u64::try_from(request.start.0),
+            u64::try_from(request.length.0),
+        ) else {
+            continue;
+        };
+        let end = start.saturating_add(length.min(remaining)).min(log_length);
+
+        QTOKEN_BLOCKS.with_borrow(|reference| {
+            blocks.extend(
+                reference
+                    .range(start..end)
+                    .map(|(index, block)| BlockWithId {
+                        id: Nat::from(index),
+                        block: block.0,
+                    }),
+            )
+        });
+    }
+
+    GetBlocksResult {
+        log_length: Nat::from(log_length),
+        blocks,
+        archived_blocks: vec![],
+    }
+}
+
+/// Gets the archives of the built-in QToken ledger,blocks are never archived
+#[ic_cdk::query]
+fn icrc3_get_archives(_args: GetArchivesArgs) -> Vec<ICRC3ArchiveInfo> {
+    vec![]
+}
+
+/// Gets the certificate of the last block of the built-in QToken ledger
+///
+/// The certified data is the root of a hash tree holding the index and hash of the last block,
+/// None before the first block is recorded
+#[ic_cdk::query]
+fn icrc3_get_tip_certificate() -> Option<ICRC3DataCertificate> {
+    let (last_block_index, last_block_hash) = _qtoken_tip()?;
+    let certificate = env::data_certificate()?;
+
+    Some(ICRC3DataCertificate {
+        certificate: certificate.into(),
+        hash_tree: _tip_hash_tree(last_block_index, last_block_hash).into(),
+    })
+}
+
+/// Gets the block types recorded by the built-in QToken ledger
+#[ic_cdk::query]
+fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    SUPPORTED_BLOCK_TYPES
+        .iter()
+        .map(|(block_type, url)| SupportedBlockType {
+            block_type: block_type.to_string(),
+            url: url.to_string(),
+        })
+        .collect()
+}
+
+/// Validates and processes a position creation request
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset of the position
+/// * `user` - Principal ID of the user creating position
+/// * `collateral` - Amount of collateral to lock
+/// * `debt` - Amount of leverage to borrow
+///
+/// # Returns
+/// * `(bool, u32)` - (validity status, interest rate)
+///   - First value indicates if user has sufficient margin balance and vault has enough liquidity
+///   - Second value is the interest rate for the borrowed amount
+///
+/// If valid, updates user's margin balance and vault's free liquidity by reducing both,
+/// suspended markets can not take new debt and calls are rejected while new debt is paused
+#[ic_cdk::update(name = "liquidityChangeValidityCheck", guard = "new_debt_guard")]
+async fn liquidity_change_validity_check(
+    asset_id: AssetId,
+    user: Principal,
+    collateral: Amount,
+    debt: Amount,
+) -> (bool, u32) {
+    if _get_asset(asset_id).is_none() {
+        return (false, 0);
+    }
+    if debt != 0 && _get_market_status(env::caller()) != Some(MarketStatus::Active) {
+        return (false, 0);
+    }
+
+    let account_balance = _get_user_margin_balance(asset_id, user);
+
+    let mut vault = _get_vault(asset_id);
+
+    let valid = account_balance >= collateral && vault.free_liquidity >= debt;
+
+    if valid {
+        vault.free_liquidity -= debt;
+        vault.debt += debt;
+        _update_market_debt(env::caller(), asset_id, debt, 0);
+        _update_user_balance(asset_id, user, collateral, false);
+    }
+
+    _update_vault(asset_id, vault);
+
+    return (valid, 0);
+}
+
+/// Updates position state and distributes fees when a position is modified or closed
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset of the position
+/// * `user` - Principal ID of position owner
+/// * `margin_delta` - Amount to return to user's margin balance
+/// * `manage_debt_params` - Parameters for debt repayment and fee calculation
+///
+/// # Effects
+/// - Updates user margin balance
+/// - Adjusts vault debt and liquidity
+/// - Distributes earned fees across stake spans
+#[ic_cdk::update(name = "managePositionUpdate", guard = "approved_market_guard")]
+async fn manage_position_update(
+    asset_id: AssetId,
+    user: Principal,
+    margin_delta: Amount,
+    manage_debt_params: ManageDebtParams,
+) {
+    if _get_asset(asset_id).is_none() {
+        return;
+    }
+
+    if margin_delta != 0 {
+        _update_user_balance(asset_id, user, margin_delta, true);
+    }
+
+    let mut vault = _get_vault(asset_id);
+
+    let ManageDebtParams {
+        initial_debt,
+        net_debt,
+        amount_repaid,
+    } = &manage_debt_params;
+
+    vault.debt = vault.debt + net_debt - (initial_debt + amount_repaid);
+    vault.free_liquidity += amount_repaid;
+    _update_market_debt(
+        env::caller(),
+        asset_id,
+        *net_debt,
+        initial_debt + amount_repaid,
+    );
+
+    // the vault debt and liquidity changed even when no fees were earned
+    let fees_gotten = amount_repaid.saturating_sub(*initial_debt);
+    if fees_gotten != 0 {
+        vault.lifetime_fees += fees_gotten;
+        vault._stream_fees(fees_gotten, env::time(), &_get_lock_spans());
+    }
+    _update_vault(asset_id, vault);
+}
+
+/// Update user balance
+
+fn _get_deposit_claimant(asset_id: AssetId, block_index: u64) -> Option<Principal> {
+    CLAIMED_DEPOSITS.with_borrow(|reference| reference.get(&(asset_id, block_index)))
+}
+
+fn _update_user_balance(asset_id: AssetId, user: Principal, delta: Amount, deposit: bool) {
+    USERS_MARGIN_BALANCES.with_borrow_mut(|reference| {
+        let initial_balance = { reference.get(&(asset_id, user)).unwrap_or_default() };
+        let new_balance = if deposit {
+            initial_balance + delta
+        } else {
+            initial_balance - delta
+        };
+        if new_balance == 0 {
+            reference.remove(&(asset_id, user))
+        } else {
+            reference.insert((asset_id, user), new_balance)
+        }
+    });
+}
+
+/// Gets the vault of an asset with its fee stream advanced to the current time,so every
+/// interaction with the vault sees the fees vested up to now
+///
+/// The advance only happens on the returned copy,nothing is saved until `_update_vault`
+/// is called,so a vault must be read again after any await before it is written back
+fn _get_vault(asset_id: AssetId) -> Vault {
+    let mut vault = VAULTS.with_borrow(|reference| reference.get(&asset_id).unwrap_or_default());
+    vault._advance_fee_stream(env::time(), &_get_lock_spans());
+    vault
+}
+
+fn _update_vault(asset_id: AssetId, new_details: Vault) {
+    VAULTS.with_borrow_mut(|reference| reference.insert(asset_id, new_details));
+}
+
+/// Gets the details of an asset,None if the asset does not exist
+fn _get_asset(asset_id: AssetId) -> Option<LiquidityManagerDetails> {
+    ASSETS.with_borrow(|reference| reference.get(&asset_id))
+}
+
+/// Gets the details of an existing asset,assets are never removed so the asset of a lock
+/// always exists
+fn _get_liquidity_manager_details(asset_id: AssetId) -> LiquidityManagerDetails {
+    _get_asset(asset_id).unwrap()
+}
+
+fn _get_assets() -> Vec<(AssetId, LiquidityManagerDetails)> {
+    ASSETS.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Adds an asset with its vault
+fn _insert_asset(asset_id: AssetId, details: LiquidityManagerDetails, vault: Vault) {
+    ASSETS.with_borrow_mut(|reference| reference.insert(asset_id, details));
+    VAULTS.with_borrow_mut(|reference| reference.insert(asset_id, vault));
+}
+
+/// Checks that only the virtual asset is held in the built-in QToken ledger and that its ledger
+/// id is the canister's id
+fn _check_asset_types(details: &LiquidityManagerDetails) -> Result<(), String> {
+    if matches!(details.asset.asset_type, AssetType::QToken) {
+        return Err("Asset can not be held in the built-in QToken ledger".to_string());
+    }
+    if matches!(details.virtual_asset.asset_type, AssetType::QToken)
+        && details.virtual_asset.ledger_id != env::canister_id()
+    {
+        return Err("Built-in QToken ledger id must be the vault's id".to_string());
+    }
+    Ok(())
+}
+
+fn _check_asset_ledgers_unused(
+    details: &LiquidityManagerDetails,
+    except_asset_id: Option<AssetId>,
+) -> Result<(), String> {
+    let ledger_ids = [details.asset.ledger_id, details.virtual_asset.ledger_id];
+    if ledger_ids[0] == ledger_ids[1]
+        || _get_assets().iter().any(|(asset_id, asset)| {
+            Some(*asset_id) != except_asset_id
+                && (ledger_ids.contains(&asset.asset.ledger_id)
+                    || ledger_ids.contains(&asset.virtual_asset.ledger_id))
+        })
+    {
+        return Err("Asset ledger already used".to_string());
+    }
+    Ok(())
+}
+
+/// Fetches the ledger metadata of an asset and its virtual asset
+///
+/// Fails if the decimals of the virtual asset differ from the asset's or the canister is not the
+/// minting account of the virtual asset
+async fn _fetch_asset_metadata(details: &LiquidityManagerDetails) -> Result<AssetMetadata, String> {
+    let asset = details.asset.fetch_metadata().await?;
+    if matches!(details.virtual_asset.asset_type, AssetType::QToken) {
+        // the built-in ledger takes its metadata from the asset its QTokens represent
+        _qtoken_set_metadata(
+            format!("{} QToken", asset.name),
+            format!("q{}", asset.symbol),
+            asset.decimals,
+        );
+    }
+    let virtual_asset = details.virtual_asset.fetch_metadata().await?;
+
+    if asset.decimals != virtual_asset.decimals {
+        return Err("Virtual asset decimals do not match the asset's".to_string());
+    }
+
+    let minting_account = details.virtual_asset.ledger().minting_account().await?;
+    if minting_account != Some(Account::from(env::canister_id())) {
+        return Err("Vault is not the minting account of the virtual asset".to_string());
+    }
+
+    Ok(AssetMetadata {
+        asset,
+        virtual_asset,
+        updated_at: env::time(),
+    })
+}
+
+async fn _refresh_asset_metadata(asset_id: AssetId) -> Result<AssetMetadata, String> {
+    let Some(details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+
+    let metadata = _fetch_asset_metadata(&details).await?;
+
+    let mut details = _get_liquidity_manager_details(asset_id);
+    details.metadata = Some(metadata.clone());
+    ASSETS.with_borrow_mut(|reference| reference.insert(asset_id, details));
+    METADATA_FETCH_ERRORS.with_borrow_mut(|reference| reference.remove(&asset_id));
+
+    Ok(metadata)
+}
+
+/// Schedules a fetch of an asset's ledger metadata after `delay`,a failed fetch is recorded
+/// for `getAssetMetadataStatus` and retried until it succeeds
+fn _schedule_metadata_fetch(asset_id: AssetId, delay: Duration) {
+    ic_cdk_timers::set_timer(delay, move || {
+        ic_cdk::spawn(async move {
+            if let Err(error) = _refresh_asset_metadata(asset_id).await {
+                METADATA_FETCH_ERRORS
+                    .with_borrow_mut(|reference| reference.insert(asset_id, (error, env::time())));
+                _schedule_metadata_fetch(asset_id, METADATA_RETRY_INTERVAL);
+            }
+        })
+    });
+}
+
+/// Gets whether the ledger metadata of an asset has been fetched and validated
+fn _get_metadata_status(asset_id: AssetId) -> MetadataStatus {
+    if _get_liquidity_manager_details(asset_id).metadata.is_some() {
+        return MetadataStatus::Validated;
+    }
+    match METADATA_FETCH_ERRORS.with_borrow(|reference| reference.get(&asset_id).cloned()) {
+        Some((error, failed_at)) => MetadataStatus::Failed { error, failed_at },
+        None => MetadataStatus::Pending,
+    }
+}
+
+/// Checks that the ledger metadata of an asset was validated,so its virtual asset is known to
+/// match the asset and to be minted by the vault
+fn _check_metadata_validated(asset_id: AssetId) -> Result<(), String> {
+    if _get_metadata_status(asset_id) != MetadataStatus::Validated {
+        return Err("Asset metadata not validated".to_string());
+    }
+    Ok(())
+}
+
+/// Gets the min amount of an operation family in an asset,families without their own min amount
+/// use the asset's min amount
+fn _get_min_amount(asset_id: AssetId, family: OperationFamily) -> Amount {
+    MIN_AMOUNTS
+        .with_borrow(|reference| reference.get(&(asset_id, family)))
+        .unwrap_or_else(|| _get_liquidity_manager_details(asset_id).min_amount)
+}
+
+/// Gets the config of an existing asset
+fn _get_asset_config(asset_id: AssetId) -> AssetConfig {
+    let details = _get_liquidity_manager_details(asset_id);
+    let vault = _get_vault(asset_id);
+    let min_amounts = MIN_AMOUNTS.with_borrow(|reference| {
+        reference
+            .range((asset_id, OperationFamily::Deposits)..=(asset_id, OperationFamily::NewDebt))
+            .map(|((_, family), amount)| (family, amount))
+            .collect()
+    });
+
+    AssetConfig {
+        asset: details.asset,
+        virtual_asset: details.virtual_asset,
+        min_amount: details.min_amount,
+        min_amounts,
+        locked_fees_share: vault.locked_fees_share,
+        fee_stream_window: vault.fee_stream.window,
+        early_unlock_penalties: vault
+            .spans
+            .iter()
+            .map(|(span_id, span)| (*span_id, span.early_unlock_penalty))
+            .collect(),
+    }
+}
+
+/// Checks that no margin balance,liquidity,debt or lock is held in an asset,so its ledgers can
+/// be changed without stranding tokens on the previous ledgers
+///
+/// Locks are checked with the totals locked in the asset's spans rather than by scanning every
+/// user's locks
+fn _check_asset_balances_zero(asset_id: AssetId) -> Result<(), String> {
+    let vault = _get_vault(asset_id);
+    let has_margin_balances = USERS_MARGIN_BALANCES.with_borrow(|reference| {
+        reference
+            .range((asset_id, Principal::management_canister())..)
+            .take_while(|((id, _), _)| *id == asset_id)
+            .any(|(_, balance)| balance != 0)
+    });
+    let has_locks = vault.spans.values().any(|span| span.total_locked != 0);
+
+    if has_margin_balances || has_locks || vault.debt != 0 || vault.free_liquidity != 0 {
+        return Err("Asset balances must be zero to change ledgers".to_string());
+    }
+    Ok(())
+}
+
+/// Moves the details,vault and margin balances of canisters installed before multiple
+/// assets to the initial asset
+fn _init_assets() {
+    if !ASSETS.with_borrow(|reference| reference.is_empty()) {
+        return;
+    }
+
+    let details = LIQUIDTY_MANAGER_DETAILS.with_borrow(|reference| reference.get().clone());
+    let vault = VAULT.with_borrow(|reference| reference.get().clone());
+    _insert_asset(INITIAL_ASSET_ID, details, vault);
+
+    let balances: Vec<(Principal, Amount)> =
+        USERS_MARGIN_BALANCE.with_borrow(|reference| reference.iter().collect());
+    USERS_MARGIN_BALANCES.with_borrow_mut(|reference| {
+        for (user, balance) in &balances {
+            reference.insert((INITIAL_ASSET_ID, *user), *balance);
+        }
+    });
+    USERS_MARGIN_BALANCE.with_borrow_mut(|reference| {
+        for (user, _) in balances {
+            reference.remove(&user);
+        }
+    });
+}
+
+fn _get_user_margin_balance(asset_id: AssetId, user: Principal) -> Amount {
+    USERS_MARGIN_BALANCES
+        .with_borrow(|reference| reference.get(&(asset_id, user)).unwrap_or_default())
+}
+
+fn _get_user_lock(user: Principal, timestamp: Time) -> LockDetails {
+    USERS_LOCKS.with_borrow(|reference| reference.get(&(user, timestamp)).unwrap())
+}
+
+fn _try_get_user_lock(user: Principal, timestamp: Time) -> Option<LockDetails> {
+    USERS_LOCKS.with_borrow(|reference| reference.get(&(user, timestamp)))
+}
+
+fn _get_user_locks(user: Principal) -> Vec<(Time, LockDetails, Amount)> {
+    USERS_LOCKS.with_borrow(|reference| {
+        let iter_map = reference.iter().filter_map(|entries| {
+            if entries.0 .0 == user {
+                let ref_lock = entries.1;
+                let vault = _get_vault(ref_lock.asset_id);
+                let fees_earned = vault._calc_lock_earnings(ref_lock);
+                return Some((entries.0 .1, ref_lock, fees_earned));
+            }
+            None
+        });
+
+        let mut array = Vec::new();
+        for stake in iter_map {
+            array.push(stake)
+        }
+        return array;
+    })
+}
+
+/// Inserts a new lock for a user
+///
+/// Locks are keyed by their creation time,if the user already has a lock at the current time
+/// (i.e several locks created in one call) the next free timestamp is used
+///
+/// Returns the timestamp the lock was inserted at
+fn _insert_user_lock(user: Principal, stake: LockDetails) -> Time {
+    let timestamp = _free_lock_timestamp(user, env::time());
+    _update_user_lock(user, timestamp, stake);
+    timestamp
+}
+
+/// Writes a lock,non instant locks without a token are issued one and the lock's token
+/// is pointed to the lock.The lock is kept in the compounding index while its compounding flag
+/// is set and in the expiry index while it has an expiry action
+fn _update_user_lock(user: Principal, timestamp: Time, mut lock: LockDetails) {
+    if lock.token_id.is_none() && lock.span_id != INSTANT_SPAN_ID {
+        lock.token_id = Some(LOCK_TOKENS_STATE.with_borrow_mut(|reference| {
+            let mut state = *reference.get();
+            let token_id = state._next_token_id();
+            reference.set(state).unwrap();
+            token_id
+        }));
+    }
+    if let Some(token_id) = lock.token_id {
+        let previous_holder =
+            LOCK_TOKENS.with_borrow_mut(|reference| reference.insert(token_id, (user, timestamp)));
+        ACCOUNT_LOCK_TOKENS.with_borrow_mut(|reference| {
+            if let Some((previous_owner, _)) = previous_holder {
+                reference.remove(&(previous_owner, token_id));
+            }
+            reference.insert((user, token_id), true);
+        });
+    }
+    COMPOUNDING_LOCKS.with_borrow_mut(|reference| {
+        if lock.compounding {
+            reference.insert((user, timestamp), true);
+        } else {
+            reference.remove(&(user, timestamp));
+        }
+    });
+    let previous_lock =
+        USERS_LOCKS.with_borrow_mut(|reference| reference.insert((user, timestamp), lock));
+    if let Some(previous_lock) = previous_lock {
+        _unindex_lock_expiry(user, timestamp, previous_lock);
+    }
+    if _has_expiry_action(lock) {
+        EXPIRING_LOCKS.with_borrow_mut(|reference| {
+            reference.insert((lock.expiry_time, user, timestamp), true)
+        });
+    }
+    _checkpoint_voting_power(lock.asset_id, user, previous_lock, Some(lock));
+}
+
+/// Removes a lock,burning its token
+fn _remove_user_lock(user: Principal, timestamp: Time) {
+    COMPOUNDING_LOCKS.with_borrow_mut(|reference| reference.remove(&(user, timestamp)));
+    let removed = USERS_LOCKS.with_borrow_mut(|reference| reference.remove(&(user, timestamp)));
+    let Some(removed) = removed else {
+        return;
+    };
+    _unindex_lock_expiry(user, timestamp, removed);
+    if let Some(token_id) = removed.token_id {
+        LOCK_TOKENS.with_borrow_mut(|reference| reference.remove(&token_id));
+        ACCOUNT_LOCK_TOKENS.with_borrow_mut(|reference| reference.remove(&(user, token_id)));
+    }
+    _checkpoint_voting_power(removed.asset_id, user, Some(removed), None);
+}
+
+/// Checkpoint Voting Power
+///
+/// Records the voting power of the user and of all users in the lock's asset after a lock of
+/// the user changed,the contribution of the previous state of the lock is replaced by the new one and the
+/// slope changes at their expiry times are updated
+fn _checkpoint_voting_power(
+    asset_id: AssetId,
+    user: Principal,
+    previous_lock: Option<LockDetails>,
+    new_lock: Option<LockDetails>,
+) {
+    let current_time = _to_seconds(env::time());
+    let previous = previous_lock.and_then(|lock| LockContribution::_from_lock(&lock, current_time));
+    let new = new_lock.and_then(|lock| LockContribution::_from_lock(&lock, current_time));
+    if previous == new {
+        return;
+    }
+
+    let mut account_point = _account_voting_point_at(asset_id, user, current_time);
+    let mut total_point = _total_voting_point_at(asset_id, current_time);
+
+    for (contribution, add) in [(previous, false), (new, true)] {
+        let Some(contribution) = contribution else {
+            continue;
+        };
+        account_point._apply(contribution, current_time, add);
+        total_point._apply(contribution, current_time, add);
+        _update_slope_change(asset_id, user, contribution, add);
+    }
+
+    ACCOUNT_VOTING_POINTS.with_borrow_mut(|reference| {
+        reference.insert((asset_id, user, current_time), account_point)
+    });
+    VOTING_POINTS
+        .with_borrow_mut(|reference| reference.insert((asset_id, current_time), total_point));
+}
+
+/// Adds (or removes) the slope of a lock from the slope changes at its expiry time
+fn _update_slope_change(
+    asset_id: AssetId,
+    user: Principal,
+    contribution: LockContribution,
+    add: bool,
+) {
+    let update = |slope_change: Option<Amount>| {
+        let slope_change = slope_change.unwrap_or_default();
+        if add {
+            slope_change + contribution.slope
+        } else {
+            slope_change.saturating_sub(contribution.slope)
+        }
+    };
+
+    ACCOUNT_SLOPE_CHANGES.with_borrow_mut(|reference| {
+        let key = (asset_id, user, contribution.end_time);
+        match update(reference.get(&key)) {
+            0 => reference.remove(&key),
+            slope_change => reference.insert(key, slope_change),
+        }
+    });
+    SLOPE_CHANGES.with_borrow_mut(|reference| {
+        let key = (asset_id, contribution.end_time);
+        match update(reference.get(&key)) {
+            0 => reference.remove(&key),
+            slope_change => reference.insert(key, slope_change),
+        }
+    });
+}
+
+/// Gets the voting point of a user in an asset at a time in seconds
+fn _account_voting_point_at(asset_id: AssetId, user: Principal, time: Time) -> VotingPoint {
+    let last_point = ACCOUNT_VOTING_POINTS.with_borrow(|reference| {
+        reference
+            .range((asset_id, user, 0)..=(asset_id, user, time))
+            .next_back()
+            .map(|((_, _, point_time), point)| (point_time, point))
+    });
+    let Some((point_time, point)) = last_point else {
+        return VotingPoint::default();
+    };
+    if point_time == time {
+        return point;
+    }
+
+    let slope_changes: Vec<(Time, Amount)> = ACCOUNT_SLOPE_CHANGES.with_borrow(|reference| {
+        reference
+            .range((asset_id, user, point_time + 1)..=(asset_id, user, time))
+            .map(|((_, _, change_time), slope_change)| (change_time, slope_change))
+            .collect()
+    });
+
+    _advance_voting_point(point, point_time, slope_changes, time)
+}
+
+/// Gets the voting point of all users in an asset at a time in seconds
+fn _total_voting_point_at(asset_id: AssetId, time: Time) -> VotingPoint {
+    let last_point = VOTING_POINTS.with_borrow(|reference| {
+        reference
+            .range((asset_id, 0)..=(asset_id, time))
+            .next_back()
+            .map(|((_, point_time), point)| (point_time, point))
+    });
+    let Some((point_time, point)) = last_point else {
+        return VotingPoint::default();
+    };
+    if point_time == time {
+        return point;
+    }
+
+    let slope_changes: Vec<(Time, Amount)> = SLOPE_CHANGES.with_borrow(|reference| {
+        reference
+            .range((asset_id, point_time + 1)..=(asset_id, time))
+            .map(|((_, change_time), slope_change)| (change_time, slope_change))
+            .collect()
+    });
+
+    _advance_voting_point(point, point_time, slope_changes, time)
+}
+
+/// Advances a voting point to a later time,applying the slope changes in between
+fn _advance_voting_point(
+    mut point: VotingPoint,
+    point_time: Time,
+    slope_changes: Vec<(Time, Amount)>,
+    time: Time,
+) -> VotingPoint {
+    let mut last_time = point_time;
+    for (change_time, slope_change) in slope_changes {
+        point._advance(change_time - last_time);
+        point.slope = point.slope.saturating_sub(slope_change);
+        last_time = change_time;
+    }
+    point._advance(time - last_time);
+    point
+}
+
+/// Checkpoints the voting power of locks created before voting power was tracked
+fn _init_voting_power() {
+    if !VOTING_POINTS.with_borrow(|reference| reference.is_empty()) {
+        return;
+    }
+
+    let locks: Vec<((Principal, Time), LockDetails)> =
+        USERS_LOCKS.with_borrow(|reference| reference.iter().collect());
+
+    for ((user, _), lock) in locks {
+        _checkpoint_voting_power(lock.asset_id, user, None, Some(lock));
+    }
+}
+
+fn _has_expiry_action(lock: LockDetails) -> bool {
+    lock.span_id != INSTANT_SPAN_ID && lock.expiry_action != ExpiryAction::Keep
+}
+
+fn _unindex_lock_expiry(user: Principal, timestamp: Time, lock: LockDetails) {
+    if _has_expiry_action(lock) {
+        EXPIRING_LOCKS
+            .with_borrow_mut(|reference| reference.remove(&(lock.expiry_time, user, timestamp)));
+    }
+}
+
+/// Returns the first timestamp from `timestamp` the user has no lock at
+fn _free_lock_timestamp(user: Principal, timestamp: Time) -> Time {
+    USERS_LOCKS.with_borrow(|reference| {
+        let mut timestamp = timestamp;
+        while reference.contains_key(&(user, timestamp)) {
+            timestamp += 1;
+        }
+        timestamp
+    })
+}
+
+/// Issues tokens to the non instant locks without one
+fn _tokenize_locks() {
+    let untokenized_locks: Vec<((Principal, Time), LockDetails)> =
+        USERS_LOCKS.with_borrow(|reference| {
+            reference
+                .iter()
+                .filter(|(_, lock)| lock.token_id.is_none() && lock.span_id != INSTANT_SPAN_ID)
+                .collect()
+        });
+
+    for ((user, timestamp), lock) in untokenized_locks {
+        _update_user_lock(user, timestamp, lock);
+    }
+}
+
+/// Gets the owner and timestamp of the lock a token represents
+fn _get_lock_token(token_id: &Nat) -> Option<(Principal, Time)> {
+    let token_id = _to_token_id(token_id)?;
+    LOCK_TOKENS.with_borrow(|reference| reference.get(&token_id))
+}
+
+/// Transfers a lock token,moving the lock to the recipient
+fn _transfer_lock_token(caller: Principal, arg: TransferArg) -> TransferResult {
+    let Some((owner, timestamp)) = _get_lock_token(&arg.token_id) else {
+        return Err(TransferError::NonExistingTokenId);
+    };
+
+    if owner != caller || !_is_default_subaccount(arg.from_subaccount) {
+        return Err(TransferError::Unauthorized);
+    }
+
+    if arg
+        .memo
+        .as_ref()
+        .is_some_and(|memo| memo.len() as u64 > MAX_TOKEN_MEMO_SIZE)
+    {
+        return Err(TransferError::GenericError {
+            error_code: Nat::from(0u64),
+            message: "Memo too large".to_string(),
+        });
+    }
+
+    // locks are owned by principals so tokens can only be held by default accounts
+    if arg.to.owner == caller
+        || arg.to.owner == Principal::anonymous()
+        || !_is_default_subaccount(arg.to.subaccount)
+    {
+        return Err(TransferError::InvalidRecipient);
+    }
+
+    let lock = _get_user_lock(owner, timestamp);
+    _remove_user_lock(owner, timestamp);
+    _update_user_lock(
+        arg.to.owner,
+        _free_lock_timestamp(arg.to.owner, timestamp),
+        lock,
+    );
+
+    let transaction_index = LOCK_TOKENS_STATE.with_borrow_mut(|reference| {
+        let mut state = *reference.get();
+        let index = state._record_transfer();
+        reference.set(state).unwrap();
+        index
+    });
+
+    Ok(transaction_index)
+}
+
+fn _qtoken_balance_of(account: Account) -> Amount {
+    QTOKEN_BALANCES
+        .with_borrow(|reference| reference.get(&_to_account_key(account)).unwrap_or_default())
+}
+
+fn _qtoken_allowance(account: Account, spender: Account) -> QTokenAllowance {
+    let key = (_to_account_key(account), _to_account_key(spender));
+    QTOKEN_ALLOWANCES.with_borrow(|reference| reference.get(&key).unwrap_or_default())
+}
+
+fn _qtoken_get_block(block_index: u64) -> Option<QTokenBlock> {
+    QTOKEN_BLOCKS.with_borrow(|reference| reference.get(&block_index))
+}
+
+fn _qtoken_metadata() -> Vec<(String, MetadataValue)> {
+    let state = QTOKEN_STATE.with_borrow(|reference| reference.get().clone());
+    vec![
+        MetadataValue::entry("icrc1:name", state.name),
+        MetadataValue::entry("icrc1:symbol", state.symbol),
+        MetadataValue::entry("icrc1:decimals", Nat::from(state.decimals)),
+        MetadataValue::entry("icrc1:fee", Nat::from(QTOKEN_FEE)),
+    ]
+}
+
+fn _qtoken_set_metadata(name: String, symbol: String, decimals: u8) {
+    QTOKEN_STATE.with_borrow_mut(|reference| {
+        let mut state = reference.get().clone();
+        state.name = name;
+        state.symbol = symbol;
+        state.decimals = decimals;
+        reference.set(state).unwrap();
+    });
+}
+
+fn _qtoken_update_balance(account: Account, delta: Amount, deposit: bool) {
+    let balance = _qtoken_balance_of(account);
+    let new_balance = if deposit {
+        balance + delta
+    } else {
+        balance - delta
+    };
+    QTOKEN_BALANCES
+        .with_borrow_mut(|reference| reference.insert(_to_account_key(account), new_balance));
+}
+
+/// Records a block of the built-in QToken ledger
+///
+/// Returns the index of the block
+fn _qtoken_record_block(block_type: &str, tx: ICRC3Map, total_supply_delta: i128) -> u64 {
+    let mut state = QTOKEN_STATE.with_borrow(|reference| reference.get().clone());
+
+    let block = QTokenBlock::new(block_type, tx, env::time(), state.last_block_hash);
+    state.last_block_hash = Some(block._hash());
+    state.total_supply = state.total_supply.saturating_add_signed(total_supply_delta);
+    QTOKEN_STATE.with_borrow_mut(|reference| reference.set(state).unwrap());
+
+    let block_index = QTOKEN_BLOCKS.with_borrow_mut(|reference| {
+        let block_index = reference.len();
+        reference.insert(block_index, block);
+        block_index
+    });
+    _qtoken_certify_tip();
+
+    block_index
+}
+
+/// Gets the index and hash of the last block of the built-in QToken ledger,None before the
+/// first block is recorded
+fn _qtoken_tip() -> Option<(u64, [u8; 32])> {
+    let last_block_hash = QTOKEN_STATE.with_borrow(|reference| reference.get().last_block_hash)?;
+    let log_length = QTOKEN_BLOCKS.with_borrow(|reference| reference.len());
+
+    Some((log_length.checked_sub(1)?, last_block_hash))
+}
+
+/// Sets the canister's certified data to the root of the built-in QToken ledger's tip tree
+fn _qtoken_certify_tip() {
+    if let Some((last_block_index, last_block_hash)) = _qtoken_tip() {
+        env::set_certified_data(&_tip_root_hash(last_block_index, last_block_hash));
+    }
+}
+
+/// Gets the block of a transaction made with the same created at time and hash within the
+/// transaction window,transactions without a created at time are never deduplicated
+fn _qtoken_find_duplicate(created_at_time: Option<Time>, tx_hash: [u8; 32]) -> Option<u64> {
+    let created_at_time = created_at_time?;
+    QTOKEN_TRANSACTIONS.with_borrow(|reference| reference.get(&(created_at_time, tx_hash)))
+}
+
+/// Records the block of a transaction so it can be deduplicated,transactions too old to be
+/// accepted again are pruned
+fn _qtoken_record_transaction(created_at_time: Option<Time>, tx_hash: [u8; 32], block_index: u64) {
+    let Some(created_at_time) = created_at_time else {
+        return;
+    };
+    let oldest_time = env::time().saturating_sub(TRANSACTION_WINDOW + PERMITTED_DRIFT);
+    QTOKEN_TRANSACTIONS.with_borrow_mut(|reference| {
+        let expired: Vec<(Time, [u8; 32])> = reference
+            .range(..(oldest_time, [0; 32]))
+            .map(|(key, _)| key)
+            .collect();
+        for key in expired {
+            reference.remove(&key);
+        }
+        reference.insert((created_at_time, tx_hash), block_index);
+    });
+}
+
+/// Checks the fee,memo and created at time shared by every built-in QToken transaction
+fn _qtoken_check_transaction(
+    fee: &Option<Nat>,
+    memo: &Option<Memo>,
+    created_at_time: Option<u64>,
+) -> Result<(), TransferFromError> {
+    if fee
+        .as_ref()
+        .is_some_and(|fee| Amount::try_from(&fee.0) != Ok(QTOKEN_FEE))
+    {
+        return Err(TransferFromError::BadFee {
+            expected_fee: Nat::from(QTOKEN_FEE),
+        });
+    }
+    if memo
+        .as_ref()
+        .is_some_and(|memo| memo.0.len() > MAX_MEMO_SIZE)
+    {
+        return Err(TransferFromError::GenericError {
+            error_code: Nat::from(0u64),
+            message: "Memo is too long".to_string(),
+        });
+    }
+    match _check_created_at_time(created_at_time, env::time()) {
+        Ok(()) => Ok(()),
+        Err(CreatedAtTimeError::TooOld) => Err(TransferFromError::TooOld),
+        Err(CreatedAtTimeError::CreatedInFuture { ledger_time }) => {
+            Err(TransferFromError::CreatedInFuture { ledger_time })
+        }
+    }
+}
+
+/// Transfers built-in QTokens,minting them when sent from the minting account and burning them
+/// when sent to it
+///
+/// # Arguments
+/// * `spender` - The account spending its allowance,None when the owner of `from` transfers
+/// * `from` - Account the tokens are transferred from
+/// * `to` - Account the tokens are transferred to
+/// * `amount` - Amount of tokens
+/// * `fee` - Fee expected by the sender
+/// * `memo` - Memo of the transfer
+/// * `created_at_time` - Time the transfer was created at by the sender
+///
+/// # Returns
+/// * `Ok(u64)` - The index of the transfer's block
+/// * `Err(TransferFromError)` - If the transfer is invalid
+fn _qtoken_transfer(
+    spender: Option<Account>,
+    from: Account,
+    to: Account,
+    amount: Amount,
+    fee: Option<Nat>,
+    memo: Option<Memo>,
+    created_at_time: Option<u64>,
+) -> Result<u64, TransferFromError> {
+    _qtoken_check_transaction(&fee, &memo, created_at_time)?;
+
+    let minting_key = _to_account_key(Account::from(env::canister_id()));
+    let is_mint = _to_account_key(from) == minting_key;
+    let is_burn = _to_account_key(to) == minting_key;
+    if is_mint && is_burn {
+        return Err(TransferFromError::GenericError {
+            error_code: Nat::from(0u64),
+            message: "Minting account can not transfer to itself".to_string(),
+        });
+    }
+
+    let spender = spender.filter(|spender| _to_account_key(*spender) != _to_account_key(from));
+
+    let mut tx = ICRC3Map::new();
+    tx.insert("amt".to_string(), ICRC3Value::Nat(Nat::from(amount)));
+    if !is_mint {
+        tx.insert("from".to_string(), _account_value(from));
+    }
+    if !is_burn {
+        tx.insert("to".to_string(), _account_value(to));
+    }
+    if let Some(spender) = spender {
+        tx.insert("spender".to_string(), _account_value(spender));
+    }
+    if let Some(memo) = memo {
+        tx.insert("memo".to_string(), ICRC3Value::Blob(memo.0));
+    }
+    if let Some(created_at_time) = created_at_time {
+        tx.insert(
+            "ts".to_string(),
+            ICRC3Value::Nat(Nat::from(created_at_time)),
+        );
+    }
+
+    let tx_hash = _transaction_hash(&tx, &fee);
+    if let Some(duplicate_of) = _qtoken_find_duplicate(created_at_time, tx_hash) {
+        return Err(TransferFromError::Duplicate {
+            duplicate_of: Nat::from(duplicate_of),
+        });
+    }
+
+    if let Some(spender) = spender {
+        let allowance = _qtoken_allowance(from, spender)._spendable(env::time());
+        if allowance < amount {
+            return Err(TransferFromError::InsufficientAllowance {
+                allowance: Nat::from(allowance),
+            });
+        }
+    }
+
+    if !is_mint {
+        let balance = _qtoken_balance_of(from);
+        if balance < amount {
+            return Err(TransferFromError::InsufficientFunds {
+                balance: Nat::from(balance),
+            });
+        }
+        _qtoken_update_balance(from, amount, false);
+    }
+    if !is_burn {
+        _qtoken_update_balance(to, amount, true);
+    }
+    if let Some(spender) = spender {
+        let key = (_to_account_key(from), _to_account_key(spender));
+        QTOKEN_ALLOWANCES.with_borrow_mut(|reference| {
+            let mut allowance = reference.get(&key).unwrap_or_default();
+            allowance.amount -= amount;
+            reference.insert(key, allowance);
+        });
+    }
+
+    let (block_type, total_supply_delta) = match (is_mint, is_burn, spender.is_some()) {
+        (true, _, _) => ("1mint", amount as i128),
+        (_, true, _) => ("1burn", -(amount as i128)),
+        (_, _, true) => ("2xfer", 0),
+        _ => ("1xfer", 0),
+    };
+
+    let block_index = _qtoken_record_block(block_type, tx, total_supply_delta);
+    _qtoken_record_transaction(created_at_time, tx_hash, block_index);
+
+    Ok(block_index)
+}
+
+/// Approves a spender to transfer built-in QTokens from an account
+///
+/// Returns the index of the approval's block
+fn _qtoken_approve(from: Account, args: ApproveArgs) -> Result<u64, ApproveError> {
+    _qtoken_check_transaction(&args.fee, &args.memo, args.created_at_time).map_err(|error| {
+        match error {
+            TransferFromError::BadFee { expected_fee } => ApproveError::BadFee { expected_fee },
+            TransferFromError::TooOld => ApproveError::TooOld,
+            TransferFromError::CreatedInFuture { ledger_time } => {
+                ApproveError::CreatedInFuture { ledger_time }
+            }
+            TransferFromError::GenericError {
+                error_code,
+                message,
+            } => ApproveError::GenericError {
+                error_code,
+                message,
+            },
+            _ => ApproveError::TemporarilyUnavailable,
+        }
+    })?;
+
+    if _to_account_key(from) == _to_account_key(args.spender) {
+        return Err(ApproveError::GenericError {
+            error_code: Nat::from(0u64),
+            message: "Account can not approve itself".to_string(),
+        });
+    }
+
+    let mut tx = ICRC3Map::new();
+    tx.insert("amt".to_string(), ICRC3Value::Nat(args.amount.clone()));
+    tx.insert("from".to_string(), _account_value(from));
+    tx.insert("spender".to_string(), _account_value(args.spender));
+    if let Some(expected_allowance) = &args.expected_allowance {
+        tx.insert(
+            "expected_allowance".to_string(),
+            ICRC3Value::Nat(expected_allowance.clone()),
+        );
+    }
+    if let Some(expires_at) = args.expires_at {
+        tx.insert(
+            "expires_at".to_string(),
+            ICRC3Value::Nat(Nat::from(expires_at)),
+        );
+    }
+    if let Some(memo) = &args.memo {
+        tx.insert("memo".to_string(), ICRC3Value::Blob(memo.0.clone()));
+    }
+    if let Some(created_at_time) = args.created_at_time {
+        tx.insert(
+            "ts".to_string(),
+            ICRC3Value::Nat(Nat::from(created_at_time)),
+        );
+    }
+
+    let tx_hash = _transaction_hash(&tx, &args.fee);
+    if let Some(duplicate_of) = _qtoken_find_duplicate(args.created_at_time, tx_hash) {
+        return Err(ApproveError::Duplicate {
+            duplicate_of: Nat::from(duplicate_of),
+        });
+    }
+
+    let current_time = env::time();
+    if args
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= current_time)
+    {
+        return Err(ApproveError::Expired {
+            ledger_time: current_time,
+        });
+    }
+
+    let current_allowance = _qtoken_allowance(from, args.spender)._spendable(current_time);
+    if let Some(expected_allowance) = &args.expected_allowance {
+        if Amount::try_from(&expected_allowance.0) != Ok(current_allowance) {
+            return Err(ApproveError::AllowanceChanged {
+                current_allowance: Nat::from(current_allowance),
+            });
+        }
+    }
+
+    let amount = Amount::try_from(args.amount.0).unwrap_or(Amount::MAX);
+    let key = (_to_account_key(from), _to_account_key(args.spender));
+    QTOKEN_ALLOWANCES.with_borrow_mut(|reference| {
+        reference.insert(
+            key,
+            QTokenAllowance {
+                amount,
+                expires_at: args.expires_at,
+            },
+        )
+    });
+
+    let block_index = _qtoken_record_block("2approve", tx, 0);
+    _qtoken_record_transaction(args.created_at_time, tx_hash, block_index);
+
+    Ok(block_index)
+}
+
+fn _to_icrc1_transfer_error(error: TransferFromError) -> Icrc1TransferError {
+    match error {
+        TransferFromError::BadFee { expected_fee } => Icrc1TransferError::BadFee { expected_fee },
+        TransferFromError::BadBurn { min_burn_amount } => {
+            Icrc1TransferError::BadBurn { min_burn_amount }
+        }
+        TransferFromError::InsufficientFunds { balance } => {
+            Icrc1TransferError::InsufficientFunds { balance }
+        }
+        TransferFromError::TooOld => Icrc1TransferError::TooOld,
+        TransferFromError::CreatedInFuture { ledger_time } => {
+            Icrc1TransferError::CreatedInFuture { ledger_time }
+        }
+        TransferFromError::Duplicate { duplicate_of } => {
+            Icrc1TransferError::Duplicate { duplicate_of }
+        }
+        TransferFromError::GenericError {
+            error_code,
+            message,
+        } => Icrc1TransferError::GenericError {
+            error_code,
+            message,
+        },
+        TransferFromError::InsufficientAllowance { .. }
+        | TransferFromError::TemporarilyUnavailable => Icrc1TransferError::TemporarilyUnavailable,
+    }
+}
+
+/// Resolves the owner of a lock created by the caller for an optional beneficiary
+///
+/// Returns the owner and the funder recorded on the lock,which is only set when the caller
+/// creates the lock for someone else
+fn _resolve_beneficiary(
+    caller: Principal,
+    beneficiary: Option<Account>,
+) -> Result<(Principal, Option<Principal>), &'static str> {
+    let Some(beneficiary) = beneficiary else {
+        return Ok((caller, None));
+    };
+
+    // locks are owned by principals so they can only be created for default accounts
+    if beneficiary.owner == Principal::anonymous()
+        || !_is_default_subaccount(beneficiary.subaccount)
+    {
+        return Err("Invalid beneficiary");
+    }
+
+    if beneficiary.owner == caller {
+        return Ok((caller, None));
+    }
+
+    Ok((beneficiary.owner, Some(caller)))
+}
+
+/// Restores earnings settled from a lock whose payout failed
+///
+/// The lock is re-read so changes made to it during the inter canister call are kept,and
+/// followed through its token in case it was transferred so the earnings stay with the lock
+fn _unsettle_lock_earnings(
+    user: Principal,
+    timestamp: Time,
+    token_id: Option<TokenId>,
+    earnings: Amount,
+) {
+    let location = match token_id {
+        Some(token_id) => LOCK_TOKENS.with_borrow(|reference| reference.get(&token_id)),
+        None => Some((user, timestamp)),
+    };
+    let Some((owner, timestamp)) = location else {
+        return;
+    };
+    let Some(mut lock) = _try_get_user_lock(owner, timestamp) else {
+        return;
+    };
+    lock.pre_earnings -= earnings.min(lock.pre_earnings);
+    _update_user_lock(owner, timestamp, lock);
+}
+
+/// Gets the lock at the timestamp if the user still holds the lock with the given token
+fn _get_held_lock(
+    user: Principal,
+    timestamp: Time,
+    token_id: Option<TokenId>,
+) -> Option<LockDetails> {
+    _try_get_user_lock(user, timestamp).filter(|lock| lock.token_id == token_id)
+}
+
+/// Gets the ids of the lock tokens held by an account in ascending order
+///
+/// Only tokens with an id greater than `prev` are returned,at most `take` of them
+fn _get_account_lock_tokens(account: Account, prev: Option<TokenId>, take: usize) -> Vec<TokenId> {
+    if !_is_default_subaccount(account.subaccount) {
+        return Vec::new();
+    }
+    let start = match prev {
+        Some(prev) if prev == TokenId::MAX => return Vec::new(),
+        Some(prev) => prev + 1,
+        None => 0,
+    };
+    ACCOUNT_LOCK_TOKENS.with_borrow(|reference| {
+        reference
+            .range((account.owner, start)..=(account.owner, TokenId::MAX))
+            .take(take)
+            .map(|((_, token_id), _)| token_id)
+            .collect()
+    })
+}
+
+/// Gets the number of lock tokens held by an account
+fn _count_account_lock_tokens(account: Account) -> usize {
+    if !_is_default_subaccount(account.subaccount) {
+        return 0;
+    }
+    ACCOUNT_LOCK_TOKENS.with_borrow(|reference| {
+        reference
+            .range((account.owner, 0)..=(account.owner, TokenId::MAX))
+            .count()
+    })
+}
+
+/// Traps if a query batch exceeds the max query batch size
+fn _check_query_batch_size(batch_size: usize) {
+    if batch_size as u64 > MAX_QUERY_BATCH_SIZE {
+        ic_cdk::trap("Exceeds max query batch size");
+    }
+}
+
+fn _is_default_subaccount(subaccount: Option<Subaccount>) -> bool {
+    subaccount.is_none_or(|subaccount| subaccount == [0; 32])
+}
+
+/// Compound Locks
+///
+/// Rolls the earnings of every lock with the compounding flag set into the lock's amount,the
+/// locks are read through the compounding index
+fn _compound_locks() {
+    let compounding_locks: Vec<((Principal, Time), LockDetails)> =
+        COMPOUNDING_LOCKS.with_borrow(|reference| {
+            reference
+                .iter()
+                .filter_map(|(key, _)| _try_get_user_lock(key.0, key.1).map(|lock| (key, lock)))
+                .collect()
+        });
+
+    if compounding_locks.is_empty() {
+        return;
+    }
+
+    let mut vaults: BTreeMap<AssetId, Vault> = BTreeMap::new();
+    for ((user, timestamp), mut lock) in compounding_locks {
+        let vault = vaults
+            .entry(lock.asset_id)
+            .or_insert_with(|| _get_vault(lock.asset_id));
+        if vault._compound_lock(&mut lock) != 0 {
+            _update_user_lock(user, timestamp, lock);
+        }
+    }
+    for (asset_id, vault) in vaults {
+        _update_vault(asset_id, vault);
+    }
+}
+
+/// Sweep Expired Locks
+///
+/// Applies the expiry action of every expired lock,renewing locks are locked again for the
+/// same span if it is still enabled and within its capacity and locks moved to the instant span
+/// are locked again in the instant span with their earnings rolled into their amount,the tokens
+/// stay in the vault until the lock is unlocked.The locks are read through the expiry index
+fn _sweep_expired_locks() {
+    // expiry actions are applied on the next run once unlocking is resumed
+    if _check_not_paused(OperationFamily::Unlocking).is_err() {
+        return;
+    }
+
+    let current_time = env::time();
+    let expired_locks: Vec<((Principal, Time), LockDetails)> =
+        EXPIRING_LOCKS.with_borrow(|reference| {
+            reference
+                .iter()
+                .take_while(|((expiry_time, _, _), _)| *expiry_time <= current_time)
+                .filter_map(|((_, user, timestamp), _)| {
+                    _try_get_user_lock(user, timestamp).map(|lock| ((user, timestamp), lock))
+                })
+                .collect()
+        });
+
+    if expired_locks.is_empty() {
+        return;
+    }
+
+    let mut vaults: BTreeMap<AssetId, Vault> = BTreeMap::new();
+
+    for ((user, timestamp), mut lock) in expired_locks {
+        match lock.expiry_action {
+            ExpiryAction::Renew => {
+                // the lock is already counted in its span,so it only renews while the span is
+                // not over its capacity
+                let vault = vaults
+                    .entry(lock.asset_id)
+                    .or_insert_with(|| _get_vault(lock.asset_id));
+                let Ok(config) = _validate_lock_span(lock.asset_id, lock.span_id, 0, vault) else {
+                    continue;
+                };
+                lock._renew(config.duration, current_time);
+                _update_user_lock(user, timestamp, lock);
+            }
+            ExpiryAction::MoveToInstant => {
+                let vault = vaults
+                    .entry(lock.asset_id)
+                    .or_insert_with(|| _get_vault(lock.asset_id));
+                // earnings are held by the vault like the amount so they are locked with it
+                vault._compound_lock(&mut lock);
+                let instant_lock = vault._move_lock(lock, INSTANT_SPAN_ID, 0);
+                _update_user_lock(user, timestamp, instant_lock);
+            }
+            ExpiryAction::Keep => {}
+        }
+    }
+    for (asset_id, vault) in vaults {
+        _update_vault(asset_id, vault);
+    }
+}
+
+/// Take Yield Snapshot
+///
+/// Stores a snapshot of the spans of every asset at the current time and prunes snapshots
+/// too old to be used by any yield window
+fn _take_yield_snapshot() {
+    let current_time = env::time();
+
+    for (asset_id, _) in _get_assets() {
+        let snapshot = YieldSnapshot::_from_vault(&_get_vault(asset_id));
+
+        YIELD_SNAPSHOTS.with_borrow_mut(|reference| {
+            reference.insert((asset_id, current_time), snapshot);
+
+            let expired: Vec<(AssetId, Time)> = reference
+                .range((asset_id, 0)..(asset_id, current_time.saturating_sub(SNAPSHOT_RETENTION)))
+                .map(|(key, _)| key)
+                .collect();
+            for key in expired {
+                reference.remove(&key);
+            }
+        });
+    }
+}
+
+/// Gets the latest snapshot of an asset taken at or before the given time
+fn _get_yield_snapshot_before(asset_id: AssetId, time: Time) -> Option<(Time, YieldSnapshot)> {
+    YIELD_SNAPSHOTS.with_borrow(|reference| {
+        reference
+            .range((asset_id, 0)..=(asset_id, time))
+            .next_back()
+            .map(|((_, snapshot_time), snapshot)| (snapshot_time, snapshot))
+    })
+}
+
+fn _get_lock_span(span_id: SpanId) -> Option<LockSpanConfig> {
+    LOCK_SPANS.with_borrow(|reference| reference.get(&span_id))
+}
+
+fn _get_lock_spans() -> BTreeMap<SpanId, LockSpanConfig> {
+    LOCK_SPANS.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Seeds the lock span table with the default lock spans if it is empty
+fn _init_lock_spans() {
+    LOCK_SPANS.with_borrow_mut(|reference| {
+        if reference.is_empty() {
+            for (span_id, config) in default_lock_spans() {
+                reference.insert(span_id, config);
+            }
+        }
+    });
+}
+
+/// Checks that `amount` can be locked into a lock span
+///
+/// Returns the span's config if the span exists,is enabled and has enough capacity left,capacity
+/// reserved by pending deposits counts as used
+fn _validate_lock_span(
+    asset_id: AssetId,
+    span_id: SpanId,
+    amount: Amount,
+    vault: &Vault,
+) -> Result<LockSpanConfig, &'static str> {
+    let Some(config) = _get_lock_span(span_id) else {
+        return Err("Lock span not found");
+    };
+    if !config.enabled {
+        return Err("Lock span is disabled");
+    }
+    if let Some(max_capacity) = config.max_capacity {
+        let reserved = RESERVED_SPAN_CAPACITY.with_borrow(|reference| {
+            reference
+                .get(&(asset_id, span_id))
+                .copied()
+                .unwrap_or_default()
+        });
+        if vault._span_details(span_id).total_locked + reserved + amount > max_capacity {
+            return Err("Lock span capacity exceeded");
+        }
+    }
+    Ok(config)
+}
+
+/// Validates and reserves span capacity for a deposit before its transfer
+///
+/// The reservation must be released with `_release_span_capacity` once the transfer completed,
+/// so concurrent deposits can not exceed the span's capacity together
+fn _reserve_span_capacity(
+    asset_id: AssetId,
+    span_id: SpanId,
+    amount: Amount,
+) -> Result<(), &'static str> {
+    _validate_lock_span(asset_id, span_id, amount, &_get_vault(asset_id))?;
+    RESERVED_SPAN_CAPACITY
+        .with_borrow_mut(|reference| *reference.entry((asset_id, span_id)).or_default() += amount);
+    Ok(())
+}
+
+fn _release_span_capacity(asset_id: AssetId, span_id: SpanId, amount: Amount) {
+    RESERVED_SPAN_CAPACITY.with_borrow_mut(|reference| {
+        if let Some(reserved) = reference.get_mut(&(asset_id, span_id)) {
+            *reserved -= amount.min(*reserved);
+            if *reserved == 0 {
+                reference.remove(&(asset_id, span_id));
+            }
+        }
+    });
+}
+
+fn _start_timers() {
+    ic_cdk_timers::set_timer_interval(COMPOUNDING_INTERVAL, _compound_locks);
+    ic_cdk_timers::set_timer_interval(EXPIRY_SWEEP_INTERVAL, _sweep_expired_locks);
+    ic_cdk_timers::set_timer_interval(YIELD_SNAPSHOT_INTERVAL, _take_yield_snapshot);
+}
+
+fn _preview_early_unlock(
+    vault: &mut Vault,
+    ref_lock: &mut LockDetails,
+    current_time: Time,
+) -> EarlyUnlockPreview {
+    if ref_lock.compounding {
+        vault._compound_lock(ref_lock);
+    }
+
+    let earnings = vault._calc_lock_earnings(*ref_lock);
+    let span_duration = _get_lock_span(ref_lock.span_id)
+        .map(|config| config.duration)
+        .unwrap_or_default();
+    let penalty = vault._calc_early_unlock_penalty(*ref_lock, span_duration, current_time);
+
+    EarlyUnlockPreview {
+        principal_returned: ref_lock.amount - penalty,
+        penalty,
+        earnings,
+    }
+}
+
+fn _is_admin(user: Principal) -> bool {
+    ADMIN.with_borrow(|admin| &user == admin.get())
+}
+
+/// Checks if a user has been granted a role,the admin has every role
+fn _has_role(user: Principal, role: Role) -> bool {
+    _is_admin(user) || ROLES.with_borrow(|reference| reference.contains_key(&(role, user)))
+}
+
+/// Checks if a user can perform the admin operations of a role,in governance mode only the
+/// governance canister can
+fn _is_authorized(user: Principal, role: Role) -> bool {
+    let governance = GOVERNANCE.with_borrow(|reference| *reference.get());
+    if governance.governance_canister == Some(user) {
+        return true;
+    }
+    governance.mode == GovernanceMode::Admin && _has_role(user, role)
+}
+
+/// Checks if a user can perform the operations reserved to the admin,in governance mode only
+/// the governance canister can
+fn _is_authorized_admin(user: Principal) -> bool {
+    let governance = GOVERNANCE.with_borrow(|reference| *reference.get());
+    if governance.governance_canister == Some(user) {
+        return true;
+    }
+    governance.mode == GovernanceMode::Admin && _is_admin(user)
+}
+
+/// Gets the status of a market,None if the market is not approved
+fn _get_market_status(market: Principal) -> Option<MarketStatus> {
+    MARKETS.with_borrow(|reference| reference.get(&market).map(|details| details.status))
+}
+
+fn _set_market_status(market: Principal, status: MarketStatus) -> Result<(), String> {
+    MARKETS.with_borrow_mut(|reference| {
+        let Some(mut details) = reference.get(&market) else {
+            return Err("Market not found".to_string());
+        };
+        details.status = status;
+        reference.insert(market, details);
+        Ok(())
+    })
+}
+
+/// Updates the outstanding debt of a market in an asset,the debt never goes below zero
+fn _update_market_debt(market: Principal, asset_id: AssetId, taken: Amount, repaid: Amount) {
+    MARKETS.with_borrow_mut(|reference| {
+        let Some(mut details) = reference.get(&market) else {
+            return;
+        };
+        let debt = details.debts.get(&asset_id).copied().unwrap_or_default();
+        let new_debt = (debt + taken).saturating_sub(repaid);
+        if new_debt == 0 {
+            details.debts.remove(&asset_id);
+        } else {
+            details.debts.insert(asset_id, new_debt);
+        }
+        reference.insert(market, details);
+    });
+}
+
+/// Checks a market is suspended and has no outstanding debt so it can be revoked
+fn _check_market_revocable(market: Principal) -> Result<(), String> {
+    let Some(details) = MARKETS.with_borrow(|reference| reference.get(&market)) else {
+        return Err("Market not found".to_string());
+    };
+    if details.status != MarketStatus::Suspended {
+        return Err("Market must be suspended before it is revoked".to_string());
+    }
+    if !details.debts.is_empty() {
+        return Err("Market still has outstanding debt".to_string());
+    }
+    Ok(())
+}
+
+/// Moves markets approved before market details were tracked to the markets table,their
+/// approver is unknown so the current admin is recorded
+fn _init_markets() {
+    let markets: Vec<Principal> = APPROVED_MARKETS
+        .with_borrow(|reference| reference.iter().map(|(market, _)| market).collect());
+    let admin = ADMIN.with_borrow(|reference| *reference.get());
+
+    // debt was not tracked per market,it can only be attributed when a single market exists
+    let debts: BTreeMap<AssetId, Amount> = if markets.len() == 1 {
+        VAULTS.with_borrow(|reference| {
+            reference
+                .iter()
+                .filter(|(_, vault)| vault.debt != 0)
+                .map(|(asset_id, vault)| (asset_id, vault.debt))
+                .collect()
+        })
+    } else {
+        BTreeMap::new()
+    };
+
+    MARKETS.with_borrow_mut(|reference| {
+        for market in &markets {
+            reference.insert(
+                *market,
+                MarketDetails {
+                    name: String::new(),
+                    status: MarketStatus::Active,
+                    added_at: env::time(),
+                    approved_by: admin,
+                    debts: debts.clone(),
+                },
+            );
+        }
+    });
+    APPROVED_MARKETS.with_borrow_mut(|reference| {
+        for market in markets {
+            reference.remove(&market);
+        }
+    });
+}
+
+/// Approved Markets Guard
+///
+/// Ensures that only approved markets can call the specified functions
+fn approved_market_guard() -> Result<(), String> {
+    let caller = env::caller();
+    MARKETS.with_borrow(|reference| {
+        if reference.contains_key(&caller) {
+            return Ok(());
+        } else {
+            return Err("Caller not an approved market".to_string());
+        }
+    })
+}
+
+/// New Debt Guard
+///
+/// Ensures that only approved markets can take debt and that taking debt is not paused
+fn new_debt_guard() -> Result<(), String> {
+    approved_market_guard()?;
+    _check_not_paused(OperationFamily::NewDebt)
+}
+
+fn deposits_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Deposits)
+}
+
+fn withdrawals_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Withdrawals)
+}
+
+fn lending_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Lending)
+}
+
+fn collecting_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Collecting)
+}
+
+fn locking_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Locking)
+}
+
+fn unlocking_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Unlocking)
+}
+
+fn transfers_guard() -> Result<(), String> {
+    _check_not_paused(OperationFamily::Transfers)
+}
+
+/// Checks that an operation family is not paused
+fn _check_not_paused(family: OperationFamily) -> Result<(), String> {
+    match PAUSED_OPERATIONS.with_borrow(|reference| reference.get(&family)) {
+        Some(details) => Err(format!("Operation is paused: {}", details.reason)),
+        None => Ok(()),
+    }
+}
+
+fn _check_asset_exists(asset_id: AssetId) -> Result<(), String> {
+    if _get_asset(asset_id).is_none() {
+        return Err("Asset not found".to_string());
+    }
+    Ok(())
+}
+
+fn _check_market_approvable(market: Principal) -> Result<(), String> {
+    if MARKETS.with_borrow(|reference| reference.contains_key(&market)) {
+        return Err("Market already approved".to_string());
+    }
+    Ok(())
+}
+
+fn _check_market_exists(market: Principal) -> Result<(), String> {
+    if !MARKETS.with_borrow(|reference| reference.contains_key(&market)) {
+        return Err("Market not found".to_string());
+    }
+    Ok(())
+}
+
+fn _check_pausable(family: OperationFamily) -> Result<(), String> {
+    if PAUSED_OPERATIONS.with_borrow(|reference| reference.contains_key(&family)) {
+        return Err("Operation already paused".to_string());
+    }
+    Ok(())
+}
+
+fn _check_unpausable(family: OperationFamily) -> Result<(), String> {
+    if !PAUSED_OPERATIONS.with_borrow(|reference| reference.contains_key(&family)) {
+        return Err("Operation not paused".to_string());
+    }
+    Ok(())
+}
+
+fn _check_role_grantee(user: Principal) -> Result<(), String> {
+    if user == Principal::anonymous() {
+        return Err("Can not grant roles to the anonymous principal".to_string());
+    }
+    Ok(())
+}
+
+fn _check_role_granted(role: Role, user: Principal) -> Result<(), String> {
+    if !ROLES.with_borrow(|reference| reference.contains_key(&(role, user))) {
+        return Err("Role not granted".to_string());
+    }
+    Ok(())
+}
+
+fn _check_early_unlock_penalty(
+    asset_id: AssetId,
+    span_id: SpanId,
+    penalty: u64,
+) -> Result<(), String> {
+    _check_asset_exists(asset_id)?;
+    if _get_lock_span(span_id).is_none() {
+        return Err("Lock span not found".to_string());
+    }
+    if penalty > 100 * _ONE_PERCENT {
+        return Err("Penalty can not exceed 100 percent".to_string());
+    }
+    Ok(())
+}
+
+fn _check_lock_span_config(span_id: SpanId, config: &LockSpanConfig) -> Result<(), String> {
+    if span_id == INSTANT_SPAN_ID {
+        return Err("Span id is reserved for the instant span".to_string());
+    }
+    if config.duration == 0 {
+        return Err("Lock span duration can not be zero".to_string());
+    }
+    Ok(())
+}
+
+fn _check_locked_fees_share(share: u64) -> Result<(), String> {
+    if share > 100 * _ONE_PERCENT {
+        return Err("Share can not exceed 100 percent".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a new asset's types are valid and that its ledgers are not used by another asset
+fn _check_new_asset(details: &LiquidityManagerDetails) -> Result<(), String> {
+    _check_asset_types(details)?;
+    _check_asset_ledgers_unused(details, None)
+}
+
+/// Checks a config update of an asset
+///
+/// Returns the asset's details with the updated ledgers,ledgers are only changed while the
+/// asset holds no balances
+fn _check_config_update(
+    asset_id: AssetId,
+    update: &ConfigUpdate,
+) -> Result<LiquidityManagerDetails, String> {
+    let Some(mut details) = _get_asset(asset_id) else {
+        return Err("Asset not found".to_string());
+    };
+    if let Some(share) = update.locked_fees_share {
+        _check_locked_fees_share(share)?;
+    }
+    if let Some(min_amounts) = &update.min_amounts {
+        if min_amounts
+            .iter()
+            .any(|(family, _)| *family == OperationFamily::NewDebt)
+        {
+            return Err("New debt has no min amount".to_string());
+        }
+    }
+
+    if update.asset.is_some() || update.virtual_asset.is_some() {
+        details.asset = update.asset.unwrap_or(details.asset);
+        details.virtual_asset = update.virtual_asset.unwrap_or(details.virtual_asset);
+
+        _check_asset_types(&details)?;
+        _check_asset_ledgers_unused(&details, Some(asset_id))?;
+        _check_asset_balances_zero(asset_id)?;
+    }
+    Ok(details)
+}
+
+/// Checks vesting grants and the capacity of their spans
+///
+/// Returns the total amount of the grants in each span
+fn _check_vesting_grants(
+    asset_id: AssetId,
+    grants: &[VestingGrant],
+) -> Result<BTreeMap<SpanId, Amount>, String> {
+    if grants.is_empty() {
+        return Err("No vesting grants".to_string());
+    }
+    _check_asset_exists(asset_id)?;
+
+    let mut span_amounts: BTreeMap<SpanId, Amount> = BTreeMap::new();
+    for grant in grants {
+        if grant.amount < _get_min_amount(asset_id, OperationFamily::Locking) || grant.amount == 0 {
+            return Err("Amount less than min amount".to_string());
+        }
+        if grant.beneficiary == Principal::anonymous() {
+            return Err("Invalid beneficiary".to_string());
+        }
+        if grant.span_id == INSTANT_SPAN_ID {
+            return Err("Can not vest in the instant span".to_string());
+        }
+        if grant.start_time >= grant.end_time
+            || grant.cliff_time < grant.start_time
+            || grant.cliff_time > grant.end_time
+        {
+            return Err("Invalid vesting schedule".to_string());
+        }
+        *span_amounts.entry(grant.span_id).or_default() += grant.amount;
+    }
+
+    let vault = _get_vault(asset_id);
+    for (span_id, amount) in &span_amounts {
+        _validate_lock_span(asset_id, *span_id, *amount, &vault)?;
+    }
+    _check_metadata_validated(asset_id)?;
+
+    Ok(span_amounts)
+}
+
+/// Checks governance settings,governance mode requires a governance canister
+fn _check_governance_settings(settings: &GovernanceSettings) -> Result<(), String> {
+    if settings.mode == GovernanceMode::Governance && settings.governance_canister.is_none() {
+        return Err("Governance mode requires a governance canister".to_string());
+    }
+    if settings.governance_canister == Some(Principal::anonymous()) {
+        return Err("Invalid governance canister".to_string());
+    }
+    Ok(())
+}
+
+fn _check_not_timelocked() -> Result<(), String> {
+    if TIMELOCK.with_borrow(|reference| reference.get().delay) != 0 {
+        return Err("Action is timelocked,queue it with queueAdminAction".to_string());
+    }
+    Ok(())
+}
+
+fn _check_timelock_delay(delay: Time) -> Result<(), String> {
+    if delay > MAX_TIMELOCK_DELAY {
+        return Err("Timelock delay can not exceed 30 days".to_string());
+    }
+    Ok(())
+}
+
+/// Checks that a user can queue,cancel or have an admin action executed with their rights,
+/// the action needs the same role as its endpoint
+fn _authorize_admin_action(user: Principal, action: &AdminAction) -> Result<(), String> {
+    let authorized = match action {
+        AdminAction::ApproveMarket { .. } | AdminAction::ResumeMarket { .. } => {
+            _is_authorized(user, Role::MarketManager)
+        }
+        AdminAction::UpdateConfig { update, .. }
+            if update.asset.is_some() || update.virtual_asset.is_some() =>
+        {
+            _is_authorized_admin(user)
+        }
+        AdminAction::SetTimelockDelay { .. }
+        | AdminAction::GrantRole { .. }
+        | AdminAction::SetGovernance { .. } => _is_authorized_admin(user),
+        _ => _is_authorized(user, Role::RiskManager),
+    };
+    if !authorized {
+        return Err("Caller is not authorized for this admin action".to_string());
+    }
+    Ok(())
+}
+
+/// Checks the arguments of an admin action without executing it
+fn _check_admin_action(action: &AdminAction) -> Result<(), String> {
+    match action {
+        AdminAction::ApproveMarket { market, .. } => _check_market_approvable(*market),
+        AdminAction::SetEarlyUnlockPenalty {
+            asset_id,
+            span_id,
+            penalty,
+        } => _check_early_unlock_penalty(*asset_id, *span_id, *penalty),
+        AdminAction::SetLockSpan { span_id, config } => _check_lock_span_config(*span_id, config),
+        AdminAction::SetLockedFeesShare { asset_id, share } => {
+            _check_asset_exists(*asset_id)?;
+            _check_locked_fees_share(*share)
+        }
+        AdminAction::SetFeeStreamWindow { asset_id, .. } => _check_asset_exists(*asset_id),
+        AdminAction::UpdateConfig { asset_id, update } => {
+            _check_config_update(*asset_id, update).map(|_| ())
+        }
+        AdminAction::SetTimelockDelay { delay } => _check_timelock_delay(*delay),
+        AdminAction::ResumeMarket { market } => _check_market_exists(*market),
+        AdminAction::AddAsset { details } => _check_new_asset(details),
+        AdminAction::GrantRole { user, .. } => _check_role_grantee(*user),
+        AdminAction::SetGovernance { settings } => _check_governance_settings(settings),
+    }
+}
+
+/// Executes an admin action with the rights of the principal that queued it
+async fn _execute_admin_action(action: AdminAction, queued_by: Principal) -> Result<(), String> {
+    match action {
+        AdminAction::ApproveMarket { market, name } => _approve_market(market, name, queued_by),
+        AdminAction::SetEarlyUnlockPenalty {
+            asset_id,
+            span_id,
+            penalty,
+        } => _set_early_unlock_penalty(asset_id, span_id, penalty, queued_by),
+        AdminAction::SetLockSpan { span_id, config } => _set_lock_span(span_id, config),
+        AdminAction::SetLockedFeesShare { asset_id, share } => {
+            _set_locked_fees_share(asset_id, share, queued_by)
+        }
+        AdminAction::SetFeeStreamWindow { asset_id, window } => {
+            _set_fee_stream_window(asset_id, window, queued_by)
+        }
+        AdminAction::UpdateConfig { asset_id, update } => {
+            _update_config(asset_id, update, queued_by)
+                .await
+                .map(|_| ())
+        }
+        AdminAction::SetTimelockDelay { delay } => _set_timelock_delay(delay),
+        AdminAction::ResumeMarket { market } => _resume_market(market),
+        AdminAction::AddAsset { details } => _add_asset(details).await.map(|_| ()),
+        AdminAction::GrantRole { role, user } => _grant_role(role, user),
+        AdminAction::SetGovernance { settings } => _set_governance(settings),
+    }
+}
 
-    let mut vault = _get_vault();
+fn _approve_market(market: Principal, name: String, approved_by: Principal) -> Result<(), String> {
+    _check_market_approvable(market)?;
+    MARKETS.with_borrow_mut(|reference| {
+        reference.insert(
+            market,
+            MarketDetails {
+                name,
+                status: MarketStatus::Active,
+                added_at: env::time(),
+                approved_by,
+                debts: BTreeMap::new(),
+            },
+        );
+    });
 
-    let ManageDebtParams {
-        initial_debt,
-        net_debt,
-        amount_repaid,
-    } = &manage_debt_params;
+    Ok(())
+}
+
+fn _resume_market(market: Principal) -> Result<(), String> {
+    _set_market_status(market, MarketStatus::Active)
+}
+
+fn _grant_role(role: Role, user: Principal) -> Result<(), String> {
+    _check_role_grantee(user)?;
+    ROLES.with_borrow_mut(|reference| reference.insert((role, user), true));
+
+    Ok(())
+}
+
+fn _set_governance(settings: GovernanceSettings) -> Result<(), String> {
+    _check_governance_settings(&settings)?;
+    GOVERNANCE.with_borrow_mut(|reference| reference.set(settings).unwrap());
+
+    Ok(())
+}
+
+/// Adds a collateral asset,see `addAsset`
+async fn _add_asset(mut details: LiquidityManagerDetails) -> Result<AssetId, String> {
+    _check_new_asset(&details)?;
+
+    let metadata = _fetch_asset_metadata(&details).await?;
+
+    // another asset with the same ledgers could have been added while fetching the metadata
+    _check_asset_ledgers_unused(&details, None)?;
+
+    details.metadata = Some(metadata);
+    let asset_id = _get_assets()
+        .last()
+        .map_or(INITIAL_ASSET_ID, |(asset_id, _)| asset_id + 1);
+    _insert_asset(asset_id, details, Vault::default());
+
+    Ok(asset_id)
+}
+
+fn _set_early_unlock_penalty(
+    asset_id: AssetId,
+    span_id: SpanId,
+    penalty: u64,
+    changed_by: Principal,
+) -> Result<(), String> {
+    _check_early_unlock_penalty(asset_id, span_id, penalty)?;
+    let previous = _get_asset_config(asset_id);
+
+    let mut vault = _get_vault(asset_id);
+    vault._span_details_mut(span_id).early_unlock_penalty = penalty;
+    _update_vault(asset_id, vault);
+
+    _record_config_change(asset_id, previous, changed_by);
+
+    Ok(())
+}
+
+fn _set_lock_span(span_id: SpanId, config: LockSpanConfig) -> Result<(), String> {
+    _check_lock_span_config(span_id, &config)?;
+
+    LOCK_SPANS.with_borrow_mut(|reference| reference.insert(span_id, config));
+
+    Ok(())
+}
+
+fn _set_locked_fees_share(
+    asset_id: AssetId,
+    share: u64,
+    changed_by: Principal,
+) -> Result<(), String> {
+    _check_asset_exists(asset_id)?;
+    _check_locked_fees_share(share)?;
+    let previous = _get_asset_config(asset_id);
+
+    let mut vault = _get_vault(asset_id);
+    vault.locked_fees_share = share;
+    _update_vault(asset_id, vault);
+
+    _record_config_change(asset_id, previous, changed_by);
+
+    Ok(())
+}
+
+fn _set_fee_stream_window(
+    asset_id: AssetId,
+    window: Time,
+    changed_by: Principal,
+) -> Result<(), String> {
+    _check_asset_exists(asset_id)?;
+    let previous = _get_asset_config(asset_id);
+
+    let mut vault = _get_vault(asset_id);
+    vault.fee_stream.window = window;
+    _update_vault(asset_id, vault);
+
+    _record_config_change(asset_id, previous, changed_by);
+
+    Ok(())
+}
+
+fn _set_timelock_delay(delay: Time) -> Result<(), String> {
+    _check_timelock_delay(delay)?;
+    TIMELOCK.with_borrow_mut(|reference| {
+        let mut state = *reference.get();
+        state.delay = delay;
+        reference.set(state).unwrap()
+    });
+
+    Ok(())
+}
+
+/// Updates the config of an asset,see `updateConfig`
+async fn _update_config(
+    asset_id: AssetId,
+    update: ConfigUpdate,
+    changed_by: Principal,
+) -> Result<AssetConfig, String> {
+    let changes_ledgers = update.asset.is_some() || update.virtual_asset.is_some();
+    let mut details = _check_config_update(asset_id, &update)?;
+
+    if changes_ledgers {
+        details.metadata = Some(_fetch_asset_metadata(&details).await?);
+
+        // the asset could have been used or another asset added while fetching the metadata
+        _check_asset_ledgers_unused(&details, Some(asset_id))?;
+        _check_asset_balances_zero(asset_id)?;
+    }
+
+    let previous = _get_asset_config(asset_id);
+
+    let mut current_details = _get_liquidity_manager_details(asset_id);
+    if changes_ledgers {
+        current_details.asset = details.asset;
+        current_details.virtual_asset = details.virtual_asset;
+        current_details.metadata = details.metadata;
+    }
+    if let Some(min_amount) = update.min_amount {
+        current_details.min_amount = min_amount;
+    }
+    ASSETS.with_borrow_mut(|reference| reference.insert(asset_id, current_details));
+
+    if let Some(min_amounts) = update.min_amounts {
+        MIN_AMOUNTS.with_borrow_mut(|reference| {
+            for (family, _) in &previous.min_amounts {
+                reference.remove(&(asset_id, *family));
+            }
+            for (family, amount) in min_amounts {
+                reference.insert((asset_id, family), amount);
+            }
+        });
+    }
+
+    let mut vault = _get_vault(asset_id);
+    if let Some(share) = update.locked_fees_share {
+        vault.locked_fees_share = share;
+    }
+    if let Some(window) = update.fee_stream_window {
+        vault.fee_stream.window = window;
+    }
+    _update_vault(asset_id, vault);
+
+    Ok(_record_config_change(asset_id, previous, changed_by))
+}
+
+/// Records a change of an asset's config in the config history
+///
+/// # Returns
+/// * `AssetConfig` - The config after the change
+fn _record_config_change(
+    asset_id: AssetId,
+    previous: AssetConfig,
+    changed_by: Principal,
+) -> AssetConfig {
+    let current = _get_asset_config(asset_id);
+    CONFIG_HISTORY.with_borrow_mut(|reference| {
+        let change_id = reference.last_key_value().map_or(0, |(id, _)| id + 1);
+        reference.insert(
+            change_id,
+            ConfigChange {
+                asset_id,
+                changed_at: env::time(),
+                changed_by,
+                previous,
+                current: current.clone(),
+            },
+        );
+    });
+
+    current
+}
+
+// --------------------------------------------------------------------------------------
+// Admin Functions
+// --------------------------------------------------------------------------------------
+//
+// The access control listed for each function applies in admin mode,in governance mode the
+// direct admin path is disabled and only the governance canister set with `setGovernance` can
+// call them (pausers can still pause operations). While the timelock delay is set the
+// sensitive ones can only be queued with `queueAdminAction` and executed after the delay
+
+/// Proposes a new admin for the vault
+///
+/// The admin is only handed over once the proposed admin calls `acceptAdmin`,so control can
+/// not be given to a principal that is unable to use it,proposing the anonymous principal
+/// cancels a pending handover
+///
+/// # Arguments
+/// * `new_admin` - The Principal ID of the proposed admin
+///
+/// # Returns
+/// * `Ok(())` if the admin was proposed
+/// * `Err(String)` if the caller is not the admin
+///
+/// # Access Control
+/// Only the admin can call this function
+#[ic_cdk::update(name = "proposeAdmin")]
+fn propose_admin(new_admin: Principal) -> Result<(), String> {
+    if !_is_authorized_admin(env::caller()) {
+        return Err("Only admin can propose admin".to_string());
+    }
+    PENDING_ADMIN.with_borrow_mut(|reference| reference.set(new_admin).unwrap());
+
+    Ok(())
+}
+
+/// Accepts the admin role proposed through `proposeAdmin`
+///
+/// # Returns
+/// * `Ok(())` if the caller became the admin
+/// * `Err(String)` if the caller is not the proposed admin or,in governance mode,the governance
+///   canister
+///
+/// # Access Control
+/// Only the proposed admin can call this function,in governance mode only when it is the
+/// governance canister
+#[ic_cdk::update(name = "acceptAdmin")]
+fn accept_admin() -> Result<(), String> {
+    let caller = env::caller();
+    let governance = GOVERNANCE.with_borrow(|reference| *reference.get());
+    if governance.mode == GovernanceMode::Governance
+        && governance.governance_canister != Some(caller)
+    {
+        return Err("Only governance can accept admin in governance mode".to_string());
+    }
+    let pending_admin = PENDING_ADMIN.with_borrow(|reference| *reference.get());
+    if pending_admin == Principal::anonymous() || caller != pending_admin {
+        return Err("Caller is not the proposed admin".to_string());
+    }
+    ADMIN.with_borrow_mut(|reference| reference.set(caller).unwrap());
+    PENDING_ADMIN.with_borrow_mut(|reference| reference.set(Principal::anonymous()).unwrap());
+
+    Ok(())
+}
+
+/// Grants a role to a principal
+///
+/// # Arguments
+/// * `role` - The role to grant
+/// * `user` - The Principal ID to grant the role to
+///
+/// # Returns
+/// * `Ok(())` if the role was granted
+/// * `Err(String)` if the caller is not the admin or the principal is anonymous
+///
+/// # Access Control
+/// Only the admin can call this function
+#[ic_cdk::update(name = "grantRole")]
+fn grant_role(role: Role, user: Principal) -> Result<(), String> {
+    if !_is_authorized_admin(env::caller()) {
+        return Err("Only admin can grant roles".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _grant_role(role, user)
+}
+
+/// Revokes a role from a principal
+///
+/// # Arguments
+/// * `role` - The role to revoke
+/// * `user` - The Principal ID to revoke the role from
+///
+/// # Returns
+/// * `Ok(())` if the role was revoked
+/// * `Err(String)` if the caller is not the admin or the principal does not have the role
+///
+/// # Access Control
+/// Only the admin can call this function
+#[ic_cdk::update(name = "revokeRole")]
+fn revoke_role(role: Role, user: Principal) -> Result<(), String> {
+    if !_is_authorized_admin(env::caller()) {
+        return Err("Only admin can revoke roles".to_string());
+    }
+    if ROLES
+        .with_borrow_mut(|reference| reference.remove(&(role, user)))
+        .is_none()
+    {
+        return Err("Role not granted".to_string());
+    }
+
+    Ok(())
+}
+
+/// Gets the admin,the proposed admin and the roles granted to each principal
+///
+/// # Returns
+/// * `RoleAssignments` - The role assignments ordered by principal
+#[ic_cdk::query(name = "getRoles")]
+fn get_roles() -> RoleAssignments {
+    let mut roles: BTreeMap<Principal, Vec<Role>> = BTreeMap::new();
+    ROLES.with_borrow(|reference| {
+        for ((role, user), _) in reference.iter() {
+            roles.entry(user).or_default().push(role);
+        }
+    });
+    let pending_admin = PENDING_ADMIN.with_borrow(|reference| *reference.get());
+
+    RoleAssignments {
+        admin: ADMIN.with_borrow(|reference| *reference.get()),
+        pending_admin: (pending_admin != Principal::anonymous()).then_some(pending_admin),
+        roles: roles.into_iter().collect(),
+    }
+}
+
+/// Sets the governance canister and the governance mode
+///
+/// In governance mode the direct admin path is disabled,admin operations are only performed by
+/// the governance canister executing proposals while pausers can still pause operations
+///
+/// # Arguments
+/// * `settings` - The governance canister and the mode
+///
+/// # Returns
+/// * `Ok(())` if the settings were set
+/// * `Err(String)` if the caller is not the admin or governance mode has no governance canister
+///
+/// # Access Control
+/// Only the admin can call this function,in governance mode only the governance canister can
+#[ic_cdk::update(name = "setGovernance")]
+fn set_governance(settings: GovernanceSettings) -> Result<(), String> {
+    if !_is_authorized_admin(env::caller()) {
+        return Err("Only admin can set governance".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _set_governance(settings)
+}
+
+/// Gets the governance canister and the governance mode
+///
+/// # Returns
+/// * `GovernanceSettings` - The current governance settings
+#[ic_cdk::query(name = "getGovernance")]
+fn get_governance() -> GovernanceSettings {
+    GOVERNANCE.with_borrow(|reference| *reference.get())
+}
+
+/// Approves a market canister to interact with the vault
+///
+/// This function allows the admin to approve new market canisters that can interact with
+/// the vault's functionality. Only approved markets can call guarded functions.
+///
+/// # Arguments
+/// * `market` - The Principal ID of the market canister to approve
+/// * `name` - Name of the market shown in `listMarkets`
+///
+/// # Returns
+/// * `Ok(())` if the market was successfully approved
+/// * `Err(String)` if the caller is not a market manager or the market is already approved
+///
+/// # Access Control
+/// Only the admin or a market manager can call this function
+#[ic_cdk::update(name = "approveMarket")]
+fn approve_market(market: Principal, name: String) -> Result<(), String> {
+    let caller = env::caller();
+    if !_is_authorized(caller, Role::MarketManager) {
+        return Err("Only market manager can approve markets".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _approve_market(market, name, caller)
+}
+
+/// Suspends an approved market
+///
+/// A suspended market can no longer take new debt from the vault but can still repay debt
+/// and close positions through `managePositionUpdate`
+///
+/// # Arguments
+/// * `market` - The Principal ID of the market canister to suspend
+///
+/// # Returns
+/// * `Ok(())` if the market was suspended
+/// * `Err(String)` if the caller is not a market manager or the market is not approved
+///
+/// # Access Control
+/// Only the admin or a market manager can call this function
+#[ic_cdk::update(name = "suspendMarket")]
+fn suspend_market(market: Principal) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::MarketManager) {
+        return Err("Only market manager can suspend markets".to_string());
+    }
+    _set_market_status(market, MarketStatus::Suspended)
+}
+
+/// Resumes a suspended market
+///
+/// # Arguments
+/// * `market` - The Principal ID of the market canister to resume
+///
+/// # Returns
+/// * `Ok(())` if the market was resumed
+/// * `Err(String)` if the caller is not a market manager or the market is not approved
+///
+/// # Access Control
+/// Only the admin or a market manager can call this function
+#[ic_cdk::update(name = "resumeMarket")]
+fn resume_market(market: Principal) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::MarketManager) {
+        return Err("Only market manager can resume markets".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _resume_market(market)
+}
+
+/// Revokes the approval of a market
+///
+/// A revoked market can not call any guarded function,including repayments,so only suspended
+/// markets that have repaid all their debt can be revoked
+///
+/// # Arguments
+/// * `market` - The Principal ID of the market canister to revoke
+///
+/// # Returns
+/// * `Ok(())` if the market was revoked
+/// * `Err(String)` if the caller is not a market manager,the market is not approved,is not
+///   suspended or still has debt
+///
+/// # Access Control
+/// Only the admin or a market manager can call this function
+#[ic_cdk::update(name = "revokeMarket")]
+fn revoke_market(market: Principal) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::MarketManager) {
+        return Err("Only market manager can revoke markets".to_string());
+    }
+    _check_market_revocable(market)?;
+    MARKETS.with_borrow_mut(|reference| reference.remove(&market));
+
+    Ok(())
+}
+
+/// Gets the approved markets
+///
+/// # Returns
+/// * `Vec<(Principal, MarketDetails)>` - Every approved or suspended market with its details
+#[ic_cdk::query(name = "listMarkets")]
+fn list_markets() -> Vec<(Principal, MarketDetails)> {
+    MARKETS.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Pauses an operation family
+///
+/// Calls to the endpoints of a paused family are rejected until it is unpaused,repayments by
+/// markets and queries are never paused
+///
+/// # Arguments
+/// * `family` - The operation family to pause
+/// * `reason` - Why the family is paused,returned to callers of its endpoints
+///
+/// # Returns
+/// * `Ok(())` if the family was paused
+/// * `Err(String)` if the caller is not a pauser or the family is already paused
+///
+/// # Access Control
+/// Only the admin or a pauser can call this function,in governance mode only the governance
+/// canister or a pauser that is not the admin
+#[ic_cdk::update(name = "pause")]
+fn pause(family: OperationFamily, reason: String) -> Result<(), String> {
+    let caller = env::caller();
+    // pausers can pause in governance mode too so incidents are contained without waiting
+    // for a proposal,the admin is not a pauser there since the admin path is disabled
+    let is_granted_pauser = !_is_admin(caller)
+        && ROLES.with_borrow(|reference| reference.contains_key(&(Role::Pauser, caller)));
+    if !_is_authorized(caller, Role::Pauser) && !is_granted_pauser {
+        return Err("Only pauser can pause operations".to_string());
+    }
+    _check_pausable(family)?;
+    PAUSED_OPERATIONS.with_borrow_mut(|reference| {
+        reference.insert(
+            family,
+            PauseDetails {
+                reason,
+                paused_at: env::time(),
+                paused_by: caller,
+            },
+        )
+    });
+
+    Ok(())
+}
+
+/// Unpauses an operation family
+///
+/// # Arguments
+/// * `family` - The operation family to unpause
+///
+/// # Returns
+/// * `Ok(())` if the family was unpaused
+/// * `Err(String)` if the caller is not a pauser or the family is not paused
+///
+/// # Access Control
+/// Only the admin or a pauser can call this function
+#[ic_cdk::update(name = "unpause")]
+fn unpause(family: OperationFamily) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::Pauser) {
+        return Err("Only pauser can unpause operations".to_string());
+    }
+    if PAUSED_OPERATIONS
+        .with_borrow_mut(|reference| reference.remove(&family))
+        .is_none()
+    {
+        return Err("Operation not paused".to_string());
+    }
+
+    Ok(())
+}
+
+/// Gets the paused operation families
+///
+/// # Returns
+/// * `Vec<(OperationFamily, PauseDetails)>` - Every paused family with the reason and time it
+///   was paused
+#[ic_cdk::query(name = "getPauseState")]
+fn get_pause_state() -> Vec<(OperationFamily, PauseDetails)> {
+    PAUSED_OPERATIONS.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Sets the early unlock penalty of a lock span in an asset's vault
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `span_id` - Id of the lock span to set the penalty for
+/// * `penalty` - Percentage of a lock's amount forfeited when unlocking right after locking (100_000 = 1%),
+///   0 lets locks of the span unlock early without a penalty
+///
+/// # Returns
+/// * `Ok(())` if the penalty was set
+/// * `Err(String)` if the caller is not a risk manager,the asset does not exist or the penalty exceeds 100 percent
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "setEarlyUnlockPenalty")]
+fn set_early_unlock_penalty(
+    asset_id: AssetId,
+    span_id: SpanId,
+    penalty: u64,
+) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can set early unlock penalty".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _set_early_unlock_penalty(asset_id, span_id, penalty, env::caller())
+}
+
+/// Adds a lock span to the lock span table or updates an existing one
+///
+/// Spans can not be removed since existing locks reference them,disabling a span stops new locks
+/// while existing locks keep earning
+///
+/// # Arguments
+/// * `span_id` - Id of the lock span,0 is reserved for the instant span
+/// * `config` - Duration,fee weight,enabled flag and max capacity of the span
+///
+/// # Returns
+/// * `Ok(())` if the span was set
+/// * `Err(String)` if the caller is not a risk manager or the config is invalid
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "setLockSpan")]
+fn set_lock_span(span_id: SpanId, config: LockSpanConfig) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can set lock spans".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _set_lock_span(span_id, config)
+}
+
+/// Gets the lock span table
+///
+/// # Returns
+/// * `Vec<(SpanId, LockSpanConfig)>` - Every lock span with its config ordered by span id
+#[ic_cdk::query(name = "getLockSpans")]
+fn get_lock_spans() -> Vec<(SpanId, LockSpanConfig)> {
+    _get_lock_spans().into_iter().collect()
+}
+
+/// Sets the share of fees of an asset paid to the locked spans,the rest is paid to the
+/// instant span
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `share` - Percentage of fees paid to the locked spans (100_000 = 1%)
+///
+/// # Returns
+/// * `Ok(())` if the share was set
+/// * `Err(String)` if the caller is not a risk manager,the asset does not exist or the share exceeds 100 percent
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "setLockedFeesShare")]
+fn set_locked_fees_share(asset_id: AssetId, share: u64) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can set locked fees share".to_string());
+    }
+    _check_not_timelocked()?;
 
-    vault.debt = vault.debt + net_debt - (initial_debt + amount_repaid);
-    vault.free_liquidity += amount_repaid;
+    _set_locked_fees_share(asset_id, share, env::caller())
+}
 
-    let fees_gotten = if amount_repaid > initial_debt {
-        amount_repaid - initial_debt
-    } else {
-        return; // basically no fees
-    };
+/// Sets the window over which incoming fees of an asset are credited to the spans
+///
+/// Fees already received keep being credited by their current end time
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `window` - Duration in nanoseconds,0 credits fees as soon as they are received
+///
+/// # Returns
+/// * `Ok(())` if the window was set
+/// * `Err(String)` if the caller is not a risk manager or the asset does not exist
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "setFeeStreamWindow")]
+fn set_fee_stream_window(asset_id: AssetId, window: Time) -> Result<(), String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can set fee stream window".to_string());
+    }
+    _check_not_timelocked()?;
 
-    vault.lifetime_fees += fees_gotten;
-    vault._update_fees_across_span(fees_gotten);
-    _update_vault(vault);
+    _set_fee_stream_window(asset_id, window, env::caller())
 }
 
-/// Update user balance
+/// Updates the config of an asset
+///
+/// Only the fields set in the update are changed,every change is recorded in the config
+/// history with the config it replaced. Ledgers can only be changed while the asset holds no
+/// balances and the new ledgers are validated like the ledgers of a new asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+/// * `update` - The fields of the config to change
+///
+/// # Returns
+/// * `Ok(AssetConfig)` - The updated config
+/// * `Err(String)` - If the caller is not a risk manager,the asset does not exist,the update is
+///   invalid or a ledger is changed while the asset holds balances
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function,only the admin can change ledgers
+#[ic_cdk::update(name = "updateConfig")]
+async fn update_config(asset_id: AssetId, update: ConfigUpdate) -> Result<AssetConfig, String> {
+    let caller = env::caller();
+    if !_is_authorized(caller, Role::RiskManager) {
+        return Err("Only risk manager can update config".to_string());
+    }
+    let changes_ledgers = update.asset.is_some() || update.virtual_asset.is_some();
+    if changes_ledgers && !_is_authorized_admin(caller) {
+        return Err("Only admin can change ledgers".to_string());
+    }
+    _check_not_timelocked()?;
 
-fn _update_user_balance(user: Principal, delta: Amount, deposit: bool) {
-    USERS_MARGIN_BALANCE.with_borrow_mut(|reference| {
-        let initial_balance = { reference.get(&user).unwrap_or_default() };
-        let new_balance = if deposit {
-            initial_balance + delta
-        } else {
-            initial_balance - delta
-        };
-        if new_balance == 0 {
-            reference.remove(&user)
-        } else {
-            reference.insert(user, new_balance)
-        }
-    });
+    _update_config(asset_id, update, caller).await
 }
 
-fn _get_vault() -> Vault {
-    VAULT.with(|reference| reference.borrow().get().clone())
+/// Gets the config of an asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Option<AssetConfig>` - The config of the asset,None if the asset does not exist
+#[ic_cdk::query(name = "getConfig")]
+fn get_config(asset_id: AssetId) -> Option<AssetConfig> {
+    _get_asset(asset_id).map(|_| _get_asset_config(asset_id))
 }
 
-fn _update_vault(new_details: Vault) {
-    VAULT.with_borrow_mut(|reference| [reference.set(new_details).unwrap()]);
+/// Gets the config changes of an asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Vec<ConfigChange>` - Every change of the asset's config,oldest first
+#[ic_cdk::query(name = "getConfigHistory")]
+fn get_config_history(asset_id: AssetId) -> Vec<ConfigChange> {
+    CONFIG_HISTORY.with_borrow(|reference| {
+        reference
+            .iter()
+            .map(|(_, change)| change)
+            .filter(|change| change.asset_id == asset_id)
+            .collect()
+    })
 }
 
-fn _get_liquidity_manager_details() -> LiquidityManagerDetails {
-    LIQUIDTY_MANAGER_DETAILS.with(|reference| reference.borrow().get().clone())
+/// Creates vesting locks in bulk
+///
+/// The total amount of the grants is transferred from the caller,each grant then becomes a
+/// vesting lock owned by its beneficiary that earns fees in its span and unlocks its principal
+/// linearly between its start and end time
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset whose virtual tokens are vested
+/// * `grants` - The vesting locks to create
+/// * `from_subaccount` - Optional subaccount of the caller to transfer tokens from
+///
+/// # Returns
+/// * `Ok(Vec<Time>)` - The timestamps of the created locks in the order of the grants
+/// * `Err(String)` - If the caller is not a treasurer,the asset does not exist,a grant is invalid
+///   or the transfer fails
+///
+/// # Access Control
+/// Only the admin or a treasurer can call this function
+#[ic_cdk::update(name = "createVestingLocks", guard = "locking_guard")]
+async fn create_vesting_locks(
+    asset_id: AssetId,
+    grants: Vec<VestingGrant>,
+    from_subaccount: Option<Subaccount>,
+) -> Result<Vec<Time>, String> {
+    let treasurer = env::caller();
+    if !_is_authorized(treasurer, Role::Treasurer) {
+        return Err("Only treasurer can create vesting locks".to_string());
+    }
+    let span_amounts = _check_vesting_grants(asset_id, &grants)?;
+
+    let vault_details = _get_liquidity_manager_details(asset_id);
+
+    _check_metadata_validated(asset_id)?;
+
+    for (span_id, amount) in &span_amounts {
+        if let Err(err) = _reserve_span_capacity(asset_id, *span_id, *amount) {
+            for (span_id, amount) in span_amounts.range(..span_id) {
+                _release_span_capacity(asset_id, *span_id, *amount);
+            }
+            return Err(err.to_string());
+        }
+    }
+
+    let total_amount: Amount = span_amounts.values().sum();
+
+    let tx_valid = vault_details
+        .virtual_asset
+        .move_asset(
+            total_amount,
+            Account {
+                owner: treasurer,
+                subaccount: from_subaccount,
+            },
+            Account {
+                owner: env::canister_id(),
+                subaccount: None,
+            },
+            false,
+        )
+        .await;
+    for (span_id, amount) in &span_amounts {
+        _release_span_capacity(asset_id, *span_id, *amount);
+    }
+    if !tx_valid {
+        return Err("Deposit transaction failed".to_string());
+    }
+
+    let mut vault = _get_vault(asset_id);
+
+    let lock_timestamps = grants
+        .iter()
+        .map(|grant| {
+            let mut lock = vault._create_lock(grant.amount, grant.span_id, 0);
+            lock.expiry_time = grant.end_time;
+            lock.funded_by = Some(treasurer);
+            lock.asset_id = asset_id;
+            lock.vesting = Some(VestingSchedule {
+                start_time: grant.start_time,
+                cliff_time: grant.cliff_time,
+                end_time: grant.end_time,
+                claimed: 0,
+            });
+            _insert_user_lock(grant.beneficiary, lock)
+        })
+        .collect();
+
+    _update_vault(asset_id, vault);
+
+    Ok(lock_timestamps)
 }
 
-fn _update_liquidity_manager_details(new_details: LiquidityManagerDetails) {
-    LIQUIDTY_MANAGER_DETAILS.with_borrow_mut(|reference| [reference.set(new_details).unwrap()]);
+/// Adds a collateral asset to the vault
+///
+/// The asset gets its own vault and margin balances while the lock span table is shared by all
+/// assets,the canister must be the minter of the asset's virtual asset
+///
+/// # Arguments
+/// * `details` - The asset,its virtual asset and its min amount
+///
+/// # Returns
+/// * `Ok(AssetId)` - The id of the added asset
+/// * `Err(String)` - If the caller is not a risk manager or either ledger is already used by an asset
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "addAsset")]
+async fn add_asset(details: LiquidityManagerDetails) -> Result<AssetId, String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can add assets".to_string());
+    }
+    _check_not_timelocked()?;
+
+    _add_asset(details).await
 }
 
-fn _get_user_margin_balance(user: Principal) -> Amount {
-    USERS_MARGIN_BALANCE.with_borrow_mut(|reference| {
-        return reference.get(&user).or(Some(0)).unwrap();
-    })
+/// Refreshes the ledger metadata of an asset
+///
+/// # Arguments
+/// * `asset_id` - Id of the collateral asset
+///
+/// # Returns
+/// * `Ok(AssetMetadata)` - The fetched metadata of the asset and its virtual asset
+/// * `Err(String)` - If the caller is not a risk manager,the asset does not exist,a ledger call fails
+///   or the metadata is invalid
+///
+/// # Access Control
+/// Only the admin or a risk manager can call this function
+#[ic_cdk::update(name = "refreshAssetMetadata")]
+async fn refresh_asset_metadata(asset_id: AssetId) -> Result<AssetMetadata, String> {
+    if !_is_authorized(env::caller(), Role::RiskManager) {
+        return Err("Only risk manager can refresh asset metadata".to_string());
+    }
+
+    _refresh_asset_metadata(asset_id).await
 }
 
-fn _get_user_lock(user: Principal, timestamp: Time) -> LockDetails {
-    USERS_LOCKS.with_borrow(|reference| reference.get(&(user, timestamp)).unwrap())
+/// Gets the collateral assets of the vault
+///
+/// # Returns
+/// * `Vec<(AssetId, LiquidityManagerDetails)>` - Every asset with its details ordered by asset id
+#[ic_cdk::query(name = "listAssets")]
+fn list_assets() -> Vec<(AssetId, LiquidityManagerDetails)> {
+    _get_assets()
 }
 
-fn _get_user_locks(user: Principal) -> Vec<(Time, LockDetails, Amount)> {
-    USERS_LOCKS.with_borrow(|reference| {
-        let iter_map = reference.iter().filter_map(|entries| {
-            if entries.0 .0 == user {
-                let ref_lock = entries.1;
-                let vault = _get_vault();
-                let fees_earned = vault._calc_lock_earnings(ref_lock);
-                return Some((entries.0 .1, ref_lock, fees_earned));
-            }
-            None
-        });
+/// Sets the delay between queueing an admin action and its execution
+///
+/// Increasing the delay takes effect immediately while decreasing it must be queued like the
+/// other timelocked actions,so lenders are warned before the timelock is weakened
+///
+/// # Arguments
+/// * `delay` - Delay in nanoseconds,0 disables the timelock
+///
+/// # Returns
+/// * `Ok(())` if the delay was set
+/// * `Err(String)` if the caller is not the admin,the delay exceeds 30 days or a decrease is
+///   timelocked
+///
+/// # Access Control
+/// Only the admin can call this function
+#[ic_cdk::update(name = "setTimelockDelay")]
+fn set_timelock_delay(delay: Time) -> Result<(), String> {
+    if !_is_authorized_admin(env::caller()) {
+        return Err("Only admin can set timelock delay".to_string());
+    }
+    if delay < TIMELOCK.with_borrow(|reference| reference.get().delay) {
+        _check_not_timelocked()?;
+    }
 
-        let mut array = Vec::new();
-        for stake in iter_map {
-            array.push(stake)
-        }
-        return array;
-    })
+    _set_timelock_delay(delay)
 }
 
-fn _insert_user_lock(user: Principal, stake: LockDetails) {
-    let timestamp = ic_cdk::api::time();
-    USERS_LOCKS.with_borrow_mut(|reference| reference.insert((user, timestamp), stake));
+/// Gets the delay between queueing an admin action and its execution
+///
+/// # Returns
+/// * `Time` - The delay in nanoseconds,0 when the timelock is disabled
+#[ic_cdk::query(name = "getTimelockDelay")]
+fn get_timelock_delay() -> Time {
+    TIMELOCK.with_borrow(|reference| reference.get().delay)
 }
 
-fn _remove_user_lock(user: Principal, timestamp: Time) {
-    USERS_LOCKS.with_borrow_mut(|reference| reference.remove(&(user, timestamp)));
+/// Queues a sensitive admin action
+///
+/// While the timelock delay is set,approving and resuming markets,adding assets,changing lock
+/// spans,penalties,the locked fees share,the fee stream window or an asset's config,granting
+/// roles,setting governance and decreasing the delay can only be done through this function. The
+/// action can be cancelled until its execution time,after which anyone can execute it with
+/// `executeAdminAction`. Every queued action is kept in `getAdminActionHistory`
+///
+/// # Arguments
+/// * `action` - The admin action with the arguments of its endpoint
+///
+/// # Returns
+/// * `Ok(u64)` - The id of the queued action
+/// * `Err(String)` - If the caller does not have the role of the action's endpoint or the action
+///   is invalid
+///
+/// # Access Control
+/// Callers need the role required by the action's endpoint
+#[ic_cdk::update(name = "queueAdminAction")]
+fn queue_admin_action(action: AdminAction) -> Result<u64, String> {
+    let caller = env::caller();
+    _authorize_admin_action(caller, &action)?;
+    _check_admin_action(&action)?;
+
+    let now = env::time();
+    let action_id = TIMELOCK.with_borrow_mut(|reference| {
+        let mut state = *reference.get();
+        let action_id = state.next_action_id;
+        state.next_action_id += 1;
+        reference.set(state).unwrap();
+        action_id
+    });
+    let pending_action = PendingAdminAction {
+        action,
+        queued_by: caller,
+        queued_at: now,
+        executable_at: now + get_timelock_delay(),
+    };
+    _record_admin_action(action_id, pending_action.clone());
+    PENDING_ADMIN_ACTIONS.with_borrow_mut(|reference| reference.insert(action_id, pending_action));
+
+    Ok(action_id)
 }
 
-/// Approved Markets Guard
+/// Cancels a queued admin action
 ///
-/// Ensures that only approved markets can call the specified functions
-fn approved_market_guard() -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    APPROVED_MARKETS.with_borrow(|reference| {
-        if reference.contains_key(&caller) {
-            return Ok(());
-        } else {
-            return Err("Caller not an approved market".to_string());
-        }
-    })
-}
+/// # Arguments
+/// * `action_id` - Id of the queued action
+///
+/// # Returns
+/// * `Ok(())` if the action was cancelled
+/// * `Err(String)` if the action does not exist or the caller does not have the role of its
+///   endpoint
+///
+/// # Access Control
+/// Callers need the role required by the action's endpoint
+#[ic_cdk::update(name = "cancelAdminAction")]
+fn cancel_admin_action(action_id: u64) -> Result<(), String> {
+    let Some(pending_action) =
+        PENDING_ADMIN_ACTIONS.with_borrow(|reference| reference.get(&action_id))
+    else {
+        return Err("Admin action not found".to_string());
+    };
+    let caller = env::caller();
+    _authorize_admin_action(caller, &pending_action.action)?;
+    PENDING_ADMIN_ACTIONS.with_borrow_mut(|reference| reference.remove(&action_id));
+    _set_admin_action_status(
+        action_id,
+        AdminActionStatus::Cancelled {
+            cancelled_by: caller,
+            cancelled_at: env::time(),
+        },
+    );
 
-// --------------------------------------------------------------------------------------
-// Admin Functions
-// --------------------------------------------------------------------------------------
+    Ok(())
+}
 
-/// Approves a market canister to interact with the vault
+/// Executes a queued admin action once its execution time has passed
 ///
-/// This function allows the admin to approve new market canisters that can interact with
-/// the vault's functionality. Only approved markets can call guarded functions.
+/// The action is executed with the rights of the principal that queued it,so it fails if that
+/// principal lost the role during the delay. A failed action stays queued
 ///
 /// # Arguments
-/// * `market` - The Principal ID of the market canister to approve
+/// * `action_id` - Id of the queued action
 ///
 /// # Returns
-/// * `Ok(())` if the market was successfully approved
-/// * `Err(String)` if the caller is not the admin
+/// * `Ok(())` if the action was executed
+/// * `Err(String)` if the action does not exist,its execution time has not passed or it fails
 ///
 /// # Access Control
-/// Only the admin (set during initialization) can call this function
-#[ic_cdk::update(name = "approveMarket")]
-fn approve_market(market: Principal) -> Result<(), String> {
-    // Only allow canister owner/admin to approve markets
-    let caller = ic_cdk::caller();
-    ADMIN.with_borrow(|admin| {
-        if &caller != admin.get() {
-            return Err("Only admin can approve markets".to_string());
-        };
-        APPROVED_MARKETS.with_borrow_mut(|reference| {
-            reference.insert(market, true);
+/// Anyone can call this function
+#[ic_cdk::update(name = "executeAdminAction")]
+async fn execute_admin_action(action_id: u64) -> Result<(), String> {
+    let caller = env::caller();
+    let Some(pending_action) =
+        PENDING_ADMIN_ACTIONS.with_borrow(|reference| reference.get(&action_id))
+    else {
+        return Err("Admin action not found".to_string());
+    };
+    if env::time() < pending_action.executable_at {
+        return Err("Admin action is not executable yet".to_string());
+    }
+    _authorize_admin_action(pending_action.queued_by, &pending_action.action)?;
+
+    // removed before executing so the action can not be executed again while awaiting ledgers
+    PENDING_ADMIN_ACTIONS.with_borrow_mut(|reference| reference.remove(&action_id));
+    let tx_result =
+        _execute_admin_action(pending_action.action.clone(), pending_action.queued_by).await;
+    match tx_result {
+        Ok(()) => _set_admin_action_status(
+            action_id,
+            AdminActionStatus::Executed {
+                executed_by: caller,
+                executed_at: env::time(),
+            },
+        ),
+        Err(_) => {
+            PENDING_ADMIN_ACTIONS
+                .with_borrow_mut(|reference| reference.insert(action_id, pending_action));
+        }
+    }
+
+    tx_result
+}
+
+/// Gets the queued admin actions
+///
+/// # Returns
+/// * `Vec<(u64, PendingAdminAction)>` - Every queued action with its id and execution time
+///   ordered by id
+#[ic_cdk::query(name = "getPendingAdminActions")]
+fn get_pending_admin_actions() -> Vec<(u64, PendingAdminAction)> {
+    PENDING_ADMIN_ACTIONS.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Gets every admin action queued with `queueAdminAction`
+///
+/// # Returns
+/// * `Vec<(u64, AdminActionRecord)>` - Every queued,cancelled or executed action with its id
+///   ordered by id,with who cancelled or executed it and when
+#[ic_cdk::query(name = "getAdminActionHistory")]
+fn get_admin_action_history() -> Vec<(u64, AdminActionRecord)> {
+    ADMIN_ACTION_HISTORY.with_borrow(|reference| reference.iter().collect())
+}
+
+/// Records a queued admin action in the admin action history
+fn _record_admin_action(action_id: u64, pending_action: PendingAdminAction) {
+    let record = AdminActionRecord {
+        action: pending_action.action,
+        queued_by: pending_action.queued_by,
+        queued_at: pending_action.queued_at,
+        executable_at: pending_action.executable_at,
+        status: AdminActionStatus::Queued,
+    };
+    ADMIN_ACTION_HISTORY.with_borrow_mut(|reference| reference.insert(action_id, record));
+}
+
+/// Sets the status of an admin action in the admin action history
+fn _set_admin_action_status(action_id: u64, status: AdminActionStatus) {
+    ADMIN_ACTION_HISTORY.with_borrow_mut(|reference| {
+        if let Some(mut record) = reference.get(&action_id) {
+            record.status = status;
+            reference.insert(action_id, record);
+        }
+    });
+}
+
+// --------------------------------------------------------------------------------------
+// SNS Generic Function Validators
+// --------------------------------------------------------------------------------------
+//
+// Each admin operation is registered with the SNS as a generic function,the validator checks
+// the payload of a proposal and renders it for voters while the operation itself is executed
+// by the governance canister once the proposal is adopted. Validators perform the checks of
+// their operation that do not depend on the caller.
+
+/// Formats a percentage (100_000 = 1%)
+fn _format_percent(value: u64) -> String {
+    let fraction = format!("{:05}", value % _ONE_PERCENT);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}%", value / _ONE_PERCENT)
+    } else {
+        format!("{}.{}%", value / _ONE_PERCENT, fraction)
+    }
+}
+
+fn _format_asset(asset: &Asset) -> String {
+    format!("{:?} ledger {}", asset.asset_type, asset.ledger_id)
+}
+
+#[ic_cdk::query(name = "validate_approveMarket")]
+fn validate_approve_market(market: Principal, name: String) -> Result<String, String> {
+    _check_market_approvable(market)?;
+    Ok(format!("Approve market {} named \"{}\"", market, name))
+}
+
+#[ic_cdk::query(name = "validate_suspendMarket")]
+fn validate_suspend_market(market: Principal) -> Result<String, String> {
+    _check_market_exists(market)?;
+    Ok(format!("Suspend market {}", market))
+}
+
+#[ic_cdk::query(name = "validate_resumeMarket")]
+fn validate_resume_market(market: Principal) -> Result<String, String> {
+    _check_market_exists(market)?;
+    Ok(format!("Resume market {}", market))
+}
+
+#[ic_cdk::query(name = "validate_revokeMarket")]
+fn validate_revoke_market(market: Principal) -> Result<String, String> {
+    _check_market_revocable(market)?;
+    Ok(format!("Revoke market {}", market))
+}
+
+#[ic_cdk::query(name = "validate_pause")]
+fn validate_pause(family: OperationFamily, reason: String) -> Result<String, String> {
+    _check_pausable(family)?;
+    Ok(format!("Pause {:?} operations: {}", family, reason))
+}
+
+#[ic_cdk::query(name = "validate_unpause")]
+fn validate_unpause(family: OperationFamily) -> Result<String, String> {
+    _check_unpausable(family)?;
+    Ok(format!("Unpause {:?} operations", family))
+}
+
+#[ic_cdk::query(name = "validate_setEarlyUnlockPenalty")]
+fn validate_set_early_unlock_penalty(
+    asset_id: AssetId,
+    span_id: SpanId,
+    penalty: u64,
+) -> Result<String, String> {
+    _check_early_unlock_penalty(asset_id, span_id, penalty)?;
+    Ok(format!(
+        "Set the early unlock penalty of span {} of asset {} to {}",
+        span_id,
+        asset_id,
+        _format_percent(penalty)
+    ))
+}
+
+#[ic_cdk::query(name = "validate_setLockSpan")]
+fn validate_set_lock_span(span_id: SpanId, config: LockSpanConfig) -> Result<String, String> {
+    _check_lock_span_config(span_id, &config)?;
+    let max_capacity = config
+        .max_capacity
+        .map_or("unlimited".to_string(), |capacity| capacity.to_string());
+    Ok(format!(
+        "Set lock span {} to a duration of {} ns,weight {},{},max capacity {}",
+        span_id,
+        config.duration,
+        config.weight,
+        if config.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        },
+        max_capacity
+    ))
+}
+
+#[ic_cdk::query(name = "validate_setLockedFeesShare")]
+fn validate_set_locked_fees_share(asset_id: AssetId, share: u64) -> Result<String, String> {
+    _check_asset_exists(asset_id)?;
+    _check_locked_fees_share(share)?;
+    Ok(format!(
+        "Set the locked fees share of asset {} to {}",
+        asset_id,
+        _format_percent(share)
+    ))
+}
+
+#[ic_cdk::query(name = "validate_setFeeStreamWindow")]
+fn validate_set_fee_stream_window(asset_id: AssetId, window: Time) -> Result<String, String> {
+    _check_asset_exists(asset_id)?;
+    Ok(format!(
+        "Set the fee stream window of asset {} to {} ns",
+        asset_id, window
+    ))
+}
+
+#[ic_cdk::query(name = "validate_updateConfig")]
+fn validate_update_config(asset_id: AssetId, update: ConfigUpdate) -> Result<String, String> {
+    _check_config_update(asset_id, &update)?;
+
+    let mut changes = Vec::new();
+    if let Some(asset) = &update.asset {
+        changes.push(format!("asset to {}", _format_asset(asset)));
+    }
+    if let Some(virtual_asset) = &update.virtual_asset {
+        changes.push(format!("virtual asset to {}", _format_asset(virtual_asset)));
+    }
+    if let Some(min_amount) = update.min_amount {
+        changes.push(format!("min amount to {}", min_amount));
+    }
+    if let Some(min_amounts) = &update.min_amounts {
+        let min_amounts: Vec<String> = min_amounts
+            .iter()
+            .map(|(family, amount)| format!("{:?} {}", family, amount))
+            .collect();
+        changes.push(format!("min amounts to [{}]", min_amounts.join(",")));
+    }
+    if let Some(share) = update.locked_fees_share {
+        changes.push(format!("locked fees share to {}", _format_percent(share)));
+    }
+    if let Some(window) = update.fee_stream_window {
+        changes.push(format!("fee stream window to {} ns", window));
+    }
+    if changes.is_empty() {
+        return Err("Config update changes nothing".to_string());
+    }
+    Ok(format!(
+        "Update the config of asset {}: set {}",
+        asset_id,
+        changes.join(",")
+    ))
+}
+
+#[ic_cdk::query(name = "validate_addAsset")]
+fn validate_add_asset(details: LiquidityManagerDetails) -> Result<String, String> {
+    _check_new_asset(&details)?;
+    Ok(format!(
+        "Add asset {} with virtual asset {} and min amount {}",
+        _format_asset(&details.asset),
+        _format_asset(&details.virtual_asset),
+        details.min_amount
+    ))
+}
+
+#[ic_cdk::query(name = "validate_refreshAssetMetadata")]
+fn validate_refresh_asset_metadata(asset_id: AssetId) -> Result<String, String> {
+    _check_asset_exists(asset_id)?;
+    Ok(format!("Refresh the ledger metadata of asset {}", asset_id))
+}
+
+#[ic_cdk::query(name = "validate_createVestingLocks")]
+fn validate_create_vesting_locks(
+    asset_id: AssetId,
+    grants: Vec<VestingGrant>,
+    _from_subaccount: Option<Subaccount>,
+) -> Result<String, String> {
+    let total_amount: Amount = _check_vesting_grants(asset_id, &grants)?.values().sum();
+    Ok(format!(
+        "Create {} vesting locks of asset {} for a total amount of {}",
+        grants.len(),
+        asset_id,
+        total_amount
+    ))
+}
+
+#[ic_cdk::query(name = "validate_grantRole")]
+fn validate_grant_role(role: Role, user: Principal) -> Result<String, String> {
+    _check_role_grantee(user)?;
+    Ok(format!("Grant the {:?} role to {}", role, user))
+}
+
+#[ic_cdk::query(name = "validate_revokeRole")]
+fn validate_revoke_role(role: Role, user: Principal) -> Result<String, String> {
+    _check_role_granted(role, user)?;
+    Ok(format!("Revoke the {:?} role from {}", role, user))
+}
+
+#[ic_cdk::query(name = "validate_proposeAdmin")]
+fn validate_propose_admin(new_admin: Principal) -> Result<String, String> {
+    if new_admin == Principal::anonymous() {
+        return Ok("Cancel the pending admin handover".to_string());
+    }
+    Ok(format!("Propose {} as the new admin", new_admin))
+}
+
+#[ic_cdk::query(name = "validate_setGovernance")]
+fn validate_set_governance(settings: GovernanceSettings) -> Result<String, String> {
+    _check_governance_settings(&settings)?;
+    let governance_canister = settings
+        .governance_canister
+        .map_or("no governance canister".to_string(), |canister| {
+            format!("governance canister {}", canister)
         });
+    Ok(format!(
+        "Set {:?} mode with {}",
+        settings.mode, governance_canister
+    ))
+}
 
-        return Ok(());
-    })
+#[ic_cdk::query(name = "validate_setTimelockDelay")]
+fn validate_set_timelock_delay(delay: Time) -> Result<String, String> {
+    _check_timelock_delay(delay)?;
+    Ok(format!("Set the timelock delay to {} ns", delay))
+}
+
+#[ic_cdk::query(name = "validate_queueAdminAction")]
+fn validate_queue_admin_action(action: AdminAction) -> Result<String, String> {
+    let description = match action {
+        AdminAction::ApproveMarket { market, name } => validate_approve_market(market, name),
+        AdminAction::SetEarlyUnlockPenalty {
+            asset_id,
+            span_id,
+            penalty,
+        } => validate_set_early_unlock_penalty(asset_id, span_id, penalty),
+        AdminAction::SetLockSpan { span_id, config } => validate_set_lock_span(span_id, config),
+        AdminAction::SetLockedFeesShare { asset_id, share } => {
+            validate_set_locked_fees_share(asset_id, share)
+        }
+        AdminAction::SetFeeStreamWindow { asset_id, window } => {
+            validate_set_fee_stream_window(asset_id, window)
+        }
+        AdminAction::UpdateConfig { asset_id, update } => validate_update_config(asset_id, update),
+        AdminAction::SetTimelockDelay { delay } => validate_set_timelock_delay(delay),
+        AdminAction::ResumeMarket { market } => validate_resume_market(market),
+        AdminAction::AddAsset { details } => validate_add_asset(details),
+        AdminAction::GrantRole { role, user } => validate_grant_role(role, user),
+        AdminAction::SetGovernance { settings } => validate_set_governance(settings),
+    }?;
+    Ok(format!(
+        "Queue with a delay of {} ns: {}",
+        get_timelock_delay(),
+        description
+    ))
+}
+
+#[ic_cdk::query(name = "validate_cancelAdminAction")]
+fn validate_cancel_admin_action(action_id: u64) -> Result<String, String> {
+    let Some(pending_action) =
+        PENDING_ADMIN_ACTIONS.with_borrow(|reference| reference.get(&action_id))
+    else {
+        return Err("Admin action not found".to_string());
+    };
+    Ok(format!(
+        "Cancel admin action {}: {:?}",
+        action_id, pending_action.action
+    ))
 }
 
 #[derive(Copy, Clone, Default, Deserialize, CandidType)]
@@ -640,3 +5477,5 @@ ic_cdk::export_candid!();
 pub mod closed_integration_tests;
 pub mod core_lib;
 pub mod types;
+#[cfg(test)]
+pub mod unit_tests;
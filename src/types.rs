@@ -1,31 +1,518 @@
-use crate::core_lib::asset::Asset;
+use crate::core_lib::asset::{Asset, TokenMetadata};
+use crate::core_lib::lock::{LockSpanConfig, SpanId};
+use crate::core_lib::versioned::{
+    _from_versioned_bytes, _to_versioned_bytes, Versioned, UNVERSIONED,
+};
 
-use candid::CandidType;
+use candid::{CandidType, Principal};
 
 use bincode;
 use serde::{Deserialize, Serialize};
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 
 use ic_stable_structures::{storable::Bound, Storable};
 
 type Amount = u128;
 
-#[derive(CandidType, Serialize, Default, Deserialize, Clone)]
+/// Identifier of a collateral asset managed by the vault,assets are numbered in the order
+/// they are added starting from the asset the canister is installed with
+pub type AssetId = u64;
+
+/// The id of the asset the canister is installed with
+pub const INITIAL_ASSET_ID: AssetId = 0;
+
+/// Asset Metadata
+///
+/// The ledger metadata of an asset and its virtual asset
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AssetMetadata {
+    pub asset: TokenMetadata,
+    pub virtual_asset: TokenMetadata,
+    /// Time the metadata was fetched
+    pub updated_at: u64,
+}
+
+/// Metadata Status
+///
+/// Whether the ledger metadata of an asset has been fetched and validated,lending and vesting
+/// in an asset are rejected until it is
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum MetadataStatus {
+    /// The metadata has not been fetched yet
+    Pending,
+    /// The metadata was fetched and validated
+    Validated,
+    /// The last fetch failed,the fetch is retried periodically until it succeeds
+    Failed { error: String, failed_at: u64 },
+}
+
+/// Role
+///
+/// A permission the admin can grant to a principal,the admin implicitly holds every role
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    /// Can approve markets
+    MarketManager = 0,
+    /// Can add assets and set the lock span parameters of the vaults
+    RiskManager = 1,
+    /// Can pause the vault's operations
+    Pauser = 2,
+    /// Can create vesting locks funded by the treasury
+    Treasurer = 3,
+}
+
+impl Storable for Role {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1,
+        is_fixed_size: true,
+    };
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes[0] {
+            0 => Role::MarketManager,
+            1 => Role::RiskManager,
+            2 => Role::Pauser,
+            3 => Role::Treasurer,
+            _ => panic!("Invalid role"),
+        }
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![*self as u8])
+    }
+}
+
+/// Role Assignments
+///
+/// The admin of the vault,the proposed admin and the roles granted to each principal
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RoleAssignments {
+    pub admin: Principal,
+    /// Admin proposed through `proposeAdmin`,None if there is no pending handover
+    pub pending_admin: Option<Principal>,
+    pub roles: Vec<(Principal, Vec<Role>)>,
+}
+
+/// Operation Family
+///
+/// A group of the vault's operations that can be paused together
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum OperationFamily {
+    /// Funding accounts and claiming deposits
+    Deposits = 0,
+    /// Withdrawing from accounts
+    Withdrawals = 1,
+    /// Lending to the vault
+    Lending = 2,
+    /// Collecting lent liquidity from the vault
+    Collecting = 3,
+    /// Creating,extending,increasing and managing locks
+    Locking = 4,
+    /// Unlocking locks and claiming vested tokens
+    Unlocking = 5,
+    /// Markets taking debt from the vault
+    NewDebt = 6,
+    /// Transferring lock tokens and QTokens
+    Transfers = 7,
+}
+
+impl Storable for OperationFamily {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1,
+        is_fixed_size: true,
+    };
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        match bytes[0] {
+            0 => OperationFamily::Deposits,
+            1 => OperationFamily::Withdrawals,
+            2 => OperationFamily::Lending,
+            3 => OperationFamily::Collecting,
+            4 => OperationFamily::Locking,
+            5 => OperationFamily::Unlocking,
+            6 => OperationFamily::NewDebt,
+            7 => OperationFamily::Transfers,
+            _ => panic!("Invalid operation family"),
+        }
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![*self as u8])
+    }
+}
+
+/// Pause Details
+///
+/// Why and when an operation family was paused
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PauseDetails {
+    pub reason: String,
+    /// Time the operation family was paused
+    pub paused_at: u64,
+    /// Principal that paused the operation family
+    pub paused_by: Principal,
+}
+
+impl Storable for PauseDetails {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for PauseDetails {
+    const VERSION: u8 = 1;
+}
+
+/// Asset Config
+///
+/// The parameters of an asset that can be changed with `updateConfig`,`setLockedFeesShare`,
+/// `setFeeStreamWindow` and `setEarlyUnlockPenalty`
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AssetConfig {
+    pub asset: Asset,
+    pub virtual_asset: Asset,
+    /// Min amount of operations without their own min amount
+    pub min_amount: Amount,
+    /// Min amount of each operation family that has its own
+    pub min_amounts: Vec<(OperationFamily, Amount)>,
+    /// Share of fees paid to the locked spans (100_000 = 1%)
+    pub locked_fees_share: u64,
+    /// Window over which fees are credited to the spans in nanoseconds
+    pub fee_stream_window: u64,
+    /// Early unlock penalty of each span that has been used (100_000 = 1%)
+    pub early_unlock_penalties: Vec<(SpanId, u64)>,
+}
+
+/// Config Update
+///
+/// A partial update of an asset's config,fields left as None are unchanged
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct ConfigUpdate {
+    /// New asset ledger,only allowed while the asset holds no balances
+    pub asset: Option<Asset>,
+    /// New virtual asset ledger,only allowed while the asset holds no balances
+    pub virtual_asset: Option<Asset>,
+    pub min_amount: Option<Amount>,
+    /// Replaces the min amounts of the operation families
+    pub min_amounts: Option<Vec<(OperationFamily, Amount)>>,
+    pub locked_fees_share: Option<u64>,
+    pub fee_stream_window: Option<u64>,
+}
+
+/// Config Change
+///
+/// A change of an asset's config made with `updateConfig`,`setLockedFeesShare`,
+/// `setFeeStreamWindow` or `setEarlyUnlockPenalty`,directly or through a queued admin action
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ConfigChange {
+    pub asset_id: AssetId,
+    /// Time the config was changed
+    pub changed_at: u64,
+    /// Principal that changed the config
+    pub changed_by: Principal,
+    /// The config before the change
+    pub previous: AssetConfig,
+    /// The config after the change
+    pub current: AssetConfig,
+}
+
+impl Storable for ConfigChange {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for ConfigChange {
+    const VERSION: u8 = 1;
+}
+
+/// Governance Mode
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GovernanceMode {
+    /// Admin operations are performed by the admin and the holders of each role
+    #[default]
+    Admin,
+    /// Admin operations are only performed by the governance canister through proposals
+    Governance,
+}
+
+/// Governance Settings
+///
+/// The governance canister allowed to perform admin operations and whether the direct admin
+/// path is disabled
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default)]
+pub struct GovernanceSettings {
+    /// Governance canister (e.g an SNS governance canister) executing admin operations
+    pub governance_canister: Option<Principal>,
+    pub mode: GovernanceMode,
+}
+
+impl Storable for GovernanceSettings {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for GovernanceSettings {
+    const VERSION: u8 = 1;
+}
+
+/// Admin Action
+///
+/// A sensitive admin operation that is queued with `queueAdminAction` and executed once the
+/// timelock delay has passed,each variant takes the arguments of its endpoint
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum AdminAction {
+    ApproveMarket {
+        market: Principal,
+        name: String,
+    },
+    SetEarlyUnlockPenalty {
+        asset_id: AssetId,
+        span_id: SpanId,
+        penalty: u64,
+    },
+    SetLockSpan {
+        span_id: SpanId,
+        config: LockSpanConfig,
+    },
+    SetLockedFeesShare {
+        asset_id: AssetId,
+        share: u64,
+    },
+    SetFeeStreamWindow {
+        asset_id: AssetId,
+        window: u64,
+    },
+    UpdateConfig {
+        asset_id: AssetId,
+        update: ConfigUpdate,
+    },
+    SetTimelockDelay {
+        delay: u64,
+    },
+    ResumeMarket {
+        market: Principal,
+    },
+    AddAsset {
+        details: LiquidityManagerDetails,
+    },
+    GrantRole {
+        role: Role,
+        user: Principal,
+    },
+    SetGovernance {
+        settings: GovernanceSettings,
+    },
+}
+
+/// Pending Admin Action
+///
+/// A queued admin action waiting for its execution time
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PendingAdminAction {
+    pub action: AdminAction,
+    /// Principal that queued the action,the action is executed with its rights
+    pub queued_by: Principal,
+    pub queued_at: u64,
+    /// Time from which anyone can execute the action
+    pub executable_at: u64,
+}
+
+impl Storable for PendingAdminAction {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for PendingAdminAction {
+    const VERSION: u8 = 1;
+}
+
+/// Timelock State
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default)]
+pub struct TimelockState {
+    /// Delay between queueing an admin action and its execution in nanoseconds,0 disables the
+    /// timelock
+    pub delay: u64,
+    /// Id of the next queued admin action
+    pub next_action_id: u64,
+}
+
+impl Storable for TimelockState {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for TimelockState {
+    const VERSION: u8 = 1;
+}
+
+/// Admin Action Status
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminActionStatus {
+    /// Waiting for its execution time or to be executed
+    Queued,
+    Cancelled {
+        cancelled_by: Principal,
+        cancelled_at: u64,
+    },
+    Executed {
+        executed_by: Principal,
+        executed_at: u64,
+    },
+}
+
+/// Admin Action Record
+///
+/// An admin action queued with `queueAdminAction` and what happened to it,kept after the
+/// action is cancelled or executed
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AdminActionRecord {
+    pub action: AdminAction,
+    pub queued_by: Principal,
+    pub queued_at: u64,
+    pub executable_at: u64,
+    pub status: AdminActionStatus,
+}
+
+impl Storable for AdminActionRecord {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for AdminActionRecord {
+    const VERSION: u8 = 1;
+}
+
+/// Market Status
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketStatus {
+    /// The market can open positions with debt and repay them
+    Active,
+    /// The market can only repay debt and close positions
+    Suspended,
+}
+
+/// Market Details
+///
+/// A market canister approved to take debt from the vault
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MarketDetails {
+    pub name: String,
+    pub status: MarketStatus,
+    /// Time the market was approved
+    pub added_at: u64,
+    /// Principal that approved the market
+    pub approved_by: Principal,
+    /// Outstanding debt of the market per asset,assets without debt are left out
+    #[serde(default)]
+    pub debts: BTreeMap<AssetId, Amount>,
+}
+
+impl Storable for MarketDetails {
+    const BOUND: Bound = Bound::Unbounded;
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        _from_versioned_bytes(bytes.as_ref())
+    }
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for MarketDetails {
+    const VERSION: u8 = 1;
+}
+
+#[derive(CandidType, Serialize, Default, Deserialize, Clone, Debug)]
 pub struct LiquidityManagerDetails {
     pub asset: Asset,
     pub virtual_asset: Asset,
     pub min_amount: Amount,
+    /// Ledger metadata,None until it has been fetched from the ledgers
+    pub metadata: Option<AssetMetadata>,
+}
+
+/// Liquidity manager details as stored before ledger metadata was tracked
+#[derive(Deserialize)]
+struct LegacyLiquidityManagerDetails {
+    asset: Asset,
+    virtual_asset: Asset,
+    min_amount: Amount,
+}
+
+impl From<LegacyLiquidityManagerDetails> for LiquidityManagerDetails {
+    fn from(value: LegacyLiquidityManagerDetails) -> Self {
+        LiquidityManagerDetails {
+            asset: value.asset,
+            virtual_asset: value.virtual_asset,
+            min_amount: value.min_amount,
+            metadata: None,
+        }
+    }
 }
 
 impl Storable for LiquidityManagerDetails {
     const BOUND: Bound = Bound::Unbounded;
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        bincode::deserialize(bytes.as_ref()).expect("Failed to deserialize VaultDetails")
+        _from_versioned_bytes(bytes.as_ref())
     }
 
     fn to_bytes(&self) -> Cow<[u8]> {
-        let serialized = bincode::serialize(self).expect("Failed to serialize MarketDetails");
-        Cow::Owned(serialized)
+        _to_versioned_bytes(self)
+    }
+}
+
+impl Versioned for LiquidityManagerDetails {
+    const VERSION: u8 = 1;
+
+    fn _decode_version(version: u8, payload: &[u8]) -> Self {
+        match version {
+            // unversioned details are bincode,details stored before ledger metadata was tracked
+            // have no metadata
+            UNVERSIONED => bincode::deserialize(payload).unwrap_or_else(|_| {
+                bincode::deserialize::<LegacyLiquidityManagerDetails>(payload)
+                    .expect("Failed to deserialize VaultDetails")
+                    .into()
+            }),
+            _ => panic!("Unsupported liquidity manager details version {}", version),
+        }
     }
 }
@@ -0,0 +1,109 @@
+use super::*;
+
+#[test]
+fn test_that_config_updates_are_partial_and_recorded() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(100);
+
+    env::_set_caller(Principal::anonymous());
+    let update = ConfigUpdate {
+        min_amounts: Some(vec![(OperationFamily::Deposits, 500)]),
+        locked_fees_share: Some(50 * _ONE_PERCENT),
+        ..Default::default()
+    };
+    let tx_result = block_on(update_config(INITIAL_ASSET_ID, update));
+    assert!(tx_result.is_ok_and(|config| config.min_amount == 100
+        && config.min_amounts == vec![(OperationFamily::Deposits, 500)]
+        && config.locked_fees_share == 50 * _ONE_PERCENT));
+
+    // deposits use their own min amount while other operations keep the asset's
+    ledger._mint(_account(user), 1000);
+    ledger._approve(_account(user), env::canister_id(), 1000);
+    env::_set_caller(user);
+    let tx_result = block_on(fund_account(INITIAL_ASSET_ID, 400, None, user));
+    assert!(tx_result.is_err_and(|err| err == "Amount is less than min amount"));
+
+    _mock_fund_account(&ledger, user, 600);
+    let tx_result = block_on(withdraw_from_account(INITIAL_ASSET_ID, 100, _account(user)));
+    assert!(tx_result.is_ok());
+
+    let history = get_config_history(INITIAL_ASSET_ID);
+    assert_eq!(history.len(), 1);
+    assert!(history[0].previous.min_amounts.is_empty());
+    assert_eq!(
+        history[0].current.min_amounts,
+        vec![(OperationFamily::Deposits, 500)]
+    );
+    assert_eq!(history[0].changed_by, Principal::anonymous());
+    assert_eq!(history[0].changed_at, DAY);
+
+    env::_set_caller(user);
+    let tx_result = block_on(update_config(INITIAL_ASSET_ID, ConfigUpdate::default()));
+    assert!(tx_result.is_err_and(|err| err == "Only risk manager can update config"));
+}
+
+#[test]
+fn test_that_ledgers_can_only_change_without_balances() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    let new_virtual_asset = Asset {
+        ledger_id: _mock_principal(103),
+        asset_type: AssetType::Mock,
+    };
+    MockLedger {
+        ledger_id: new_virtual_asset.ledger_id,
+    }
+    ._set_minter(_account(env::canister_id()));
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    env::_set_caller(Principal::anonymous());
+    let update = ConfigUpdate {
+        virtual_asset: Some(new_virtual_asset),
+        ..Default::default()
+    };
+    let tx_result = block_on(update_config(INITIAL_ASSET_ID, update.clone()));
+    assert!(tx_result.is_err_and(|err| err == "Asset balances must be zero to change ledgers"));
+
+    env::_set_caller(user);
+    let _ = block_on(withdraw_from_account(
+        INITIAL_ASSET_ID,
+        1000,
+        _account(user),
+    ));
+
+    env::_set_caller(Principal::anonymous());
+    let tx_result = block_on(update_config(INITIAL_ASSET_ID, update));
+    assert!(tx_result.is_ok_and(|config| config.virtual_asset.ledger_id == _mock_principal(103)));
+    assert!(_get_liquidity_manager_details(INITIAL_ASSET_ID)
+        .metadata
+        .is_some());
+}
+
+#[test]
+fn test_that_single_parameter_changes_are_recorded() {
+    let (_, _) = _setup_mock_vault(100);
+    let span_id = *_get_lock_spans().keys().next().unwrap();
+
+    env::_set_caller(Principal::anonymous());
+    assert!(set_locked_fees_share(INITIAL_ASSET_ID, 40 * _ONE_PERCENT).is_ok());
+    assert!(set_fee_stream_window(INITIAL_ASSET_ID, DAY).is_ok());
+    assert!(set_early_unlock_penalty(INITIAL_ASSET_ID, span_id, 3 * _ONE_PERCENT).is_ok());
+
+    let history = get_config_history(INITIAL_ASSET_ID);
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[0].current.locked_fees_share, 40 * _ONE_PERCENT);
+    assert_eq!(
+        history[1].previous.fee_stream_window,
+        history[0].current.fee_stream_window
+    );
+    assert_eq!(history[1].current.fee_stream_window, DAY);
+    assert!(history[2]
+        .current
+        .early_unlock_penalties
+        .contains(&(span_id, 3 * _ONE_PERCENT)));
+    assert!(history
+        .iter()
+        .all(|change| change.changed_by == Principal::anonymous()));
+}
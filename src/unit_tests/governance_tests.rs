@@ -0,0 +1,112 @@
+use super::*;
+
+#[test]
+fn test_that_governance_mode_disables_the_admin_path() {
+    let governance = _mock_principal(200);
+    let pauser = _mock_principal(2);
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    assert!(grant_role(Role::Pauser, pauser).is_ok());
+    let settings = GovernanceSettings {
+        governance_canister: None,
+        mode: GovernanceMode::Governance,
+    };
+    assert!(set_governance(settings)
+        .is_err_and(|err| err == "Governance mode requires a governance canister"));
+    let settings = GovernanceSettings {
+        governance_canister: Some(governance),
+        mode: GovernanceMode::Governance,
+    };
+    assert!(set_governance(settings).is_ok());
+
+    // the admin can no longer perform admin operations
+    let tx_result = set_locked_fees_share(INITIAL_ASSET_ID, 10 * _ONE_PERCENT);
+    assert!(tx_result.is_err_and(|err| err == "Only risk manager can set locked fees share"));
+    assert!(set_governance(GovernanceSettings::default())
+        .is_err_and(|err| err == "Only admin can set governance"));
+
+    env::_set_caller(governance);
+    assert!(set_locked_fees_share(INITIAL_ASSET_ID, 10 * _ONE_PERCENT).is_ok());
+    assert_eq!(
+        _get_vault(INITIAL_ASSET_ID).locked_fees_share,
+        10 * _ONE_PERCENT
+    );
+
+    // the admin is no longer a pauser while granted pausers can still pause,only governance
+    // can unpause
+    env::_set_caller(Principal::anonymous());
+    assert!(pause(OperationFamily::Deposits, "Incident".to_string())
+        .is_err_and(|err| err == "Only pauser can pause operations"));
+    env::_set_caller(pauser);
+    assert!(pause(OperationFamily::Deposits, "Incident".to_string()).is_ok());
+    assert!(unpause(OperationFamily::Deposits)
+        .is_err_and(|err| err == "Only pauser can unpause operations"));
+    env::_set_caller(governance);
+    assert!(unpause(OperationFamily::Deposits).is_ok());
+
+    // a proposed admin can only accept while governance is not in control
+    env::_set_caller(Principal::anonymous());
+    assert!(propose_admin(pauser).is_err());
+    env::_set_caller(governance);
+    assert!(propose_admin(pauser).is_ok());
+    env::_set_caller(pauser);
+    assert!(accept_admin()
+        .is_err_and(|err| err == "Only governance can accept admin in governance mode"));
+    env::_set_caller(governance);
+    assert!(propose_admin(Principal::anonymous()).is_ok());
+
+    // governance can hand the admin path back
+    assert!(set_governance(GovernanceSettings::default()).is_ok());
+    env::_set_caller(Principal::anonymous());
+    assert!(set_locked_fees_share(INITIAL_ASSET_ID, 0).is_ok());
+}
+
+#[test]
+fn test_that_validators_render_and_check_payloads() {
+    let market = _mock_principal(3);
+    _setup_mock_vault(0);
+
+    assert_eq!(
+        validate_set_locked_fees_share(INITIAL_ASSET_ID, 12 * _ONE_PERCENT + 50_000),
+        Ok(format!(
+            "Set the locked fees share of asset {} to 12.5%",
+            INITIAL_ASSET_ID
+        ))
+    );
+    assert!(
+        validate_set_locked_fees_share(INITIAL_ASSET_ID, 101 * _ONE_PERCENT)
+            .is_err_and(|err| err == "Share can not exceed 100 percent")
+    );
+    assert!(validate_set_fee_stream_window(INITIAL_ASSET_ID + 1, DAY)
+        .is_err_and(|err| err == "Asset not found"));
+
+    assert_eq!(
+        validate_approve_market(market, "Perps".to_string()),
+        Ok(format!("Approve market {} named \"Perps\"", market))
+    );
+    assert!(validate_suspend_market(market).is_err_and(|err| err == "Market not found"));
+
+    let update = ConfigUpdate {
+        min_amounts: Some(vec![(OperationFamily::NewDebt, 1)]),
+        ..Default::default()
+    };
+    assert!(validate_update_config(INITIAL_ASSET_ID, update)
+        .is_err_and(|err| err == "New debt has no min amount"));
+    assert!(
+        validate_update_config(INITIAL_ASSET_ID, ConfigUpdate::default())
+            .is_err_and(|err| err == "Config update changes nothing")
+    );
+
+    assert!(
+        validate_unpause(OperationFamily::Lending).is_err_and(|err| err == "Operation not paused")
+    );
+    assert!(
+        validate_revoke_role(Role::Treasurer, market).is_err_and(|err| err == "Role not granted")
+    );
+    assert!(validate_grant_role(Role::Treasurer, Principal::anonymous())
+        .is_err_and(|err| err == "Can not grant roles to the anonymous principal"));
+
+    // validators do not change state
+    assert!(list_markets().is_empty());
+}
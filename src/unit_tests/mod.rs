@@ -0,0 +1,108 @@
+use futures::executor::block_on;
+
+use super::*;
+
+use crate::core_lib::asset::{Asset, AssetType};
+use crate::core_lib::ledger::{MockLedger, QTokenLedger};
+
+pub mod config_tests;
+pub mod governance_tests;
+pub mod qtoken_tests;
+pub mod timelock_tests;
+pub mod upgrade_tests;
+pub mod vault_tests;
+
+const MONTH2_SPAN_ID: SpanId = 2;
+
+/// Sets up the initial asset held in mock ledgers,the vault being the minter of the
+/// virtual asset
+///
+/// Returns the ledgers of the asset and of the virtual asset
+pub fn _setup_mock_vault(min_amount: Amount) -> (MockLedger, MockLedger) {
+    env::_set_canister_id(_mock_principal(100));
+    env::_set_time(DAY);
+
+    let asset = Asset {
+        ledger_id: _mock_principal(101),
+        asset_type: AssetType::Mock,
+    };
+    let virtual_asset = Asset {
+        ledger_id: _mock_principal(102),
+        asset_type: AssetType::Mock,
+    };
+
+    let details = LiquidityManagerDetails {
+        asset,
+        virtual_asset,
+        min_amount,
+        metadata: None,
+    };
+    _insert_asset(INITIAL_ASSET_ID, details, Vault::default());
+    _init_lock_spans();
+
+    let virtual_ledger = MockLedger {
+        ledger_id: virtual_asset.ledger_id,
+    };
+    virtual_ledger._set_minter(_account(env::canister_id()));
+    block_on(_refresh_asset_metadata(INITIAL_ASSET_ID)).unwrap();
+
+    (
+        MockLedger {
+            ledger_id: asset.ledger_id,
+        },
+        virtual_ledger,
+    )
+}
+
+/// Sets up the initial asset held in a mock ledger,its virtual asset being held in the
+/// built-in QToken ledger
+///
+/// Returns the ledger of the asset
+pub fn _setup_qtoken_vault(min_amount: Amount) -> MockLedger {
+    env::_set_canister_id(_mock_principal(100));
+    env::_set_time(DAY);
+
+    let asset = Asset {
+        ledger_id: _mock_principal(101),
+        asset_type: AssetType::Mock,
+    };
+    let virtual_asset = Asset {
+        ledger_id: env::canister_id(),
+        asset_type: AssetType::QToken,
+    };
+
+    let details = LiquidityManagerDetails {
+        asset,
+        virtual_asset,
+        min_amount,
+        metadata: None,
+    };
+    _insert_asset(INITIAL_ASSET_ID, details, Vault::default());
+    _init_lock_spans();
+    block_on(_refresh_asset_metadata(INITIAL_ASSET_ID)).unwrap();
+
+    MockLedger {
+        ledger_id: asset.ledger_id,
+    }
+}
+
+/// Mints `amount` of the asset to the user and funds the user's margin balance with it
+pub fn _mock_fund_account(ledger: &MockLedger, user: Principal, amount: Amount) {
+    ledger._mint(_account(user), amount);
+    ledger._approve(_account(user), env::canister_id(), amount);
+
+    env::_set_caller(user);
+    let tx_result = block_on(fund_account(INITIAL_ASSET_ID, amount, None, user));
+    assert!(tx_result.is_ok());
+}
+
+pub fn _account(owner: Principal) -> Account {
+    Account {
+        owner,
+        subaccount: None,
+    }
+}
+
+pub fn _mock_principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
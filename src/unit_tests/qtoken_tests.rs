@@ -0,0 +1,215 @@
+use super::*;
+
+fn _qtoken_transfer_arg(to: Principal, amount: Amount) -> Icrc1TransferArg {
+    Icrc1TransferArg {
+        from_subaccount: None,
+        to: _account(to),
+        fee: None,
+        created_at_time: None,
+        memo: None,
+        amount: Nat::from(amount),
+    }
+}
+
+#[test]
+fn test_that_lending_and_collecting_mint_and_burn_qtokens() {
+    let user = _mock_principal(1);
+    let ledger = _setup_qtoken_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    let tx_result = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    assert!(tx_result.is_ok());
+    assert_eq!(icrc1_balance_of(_account(user)), Nat::from(1000u64));
+    assert_eq!(icrc1_total_supply(), Nat::from(1000u64));
+
+    let tx_result = block_on(collect_from_vault(INITIAL_ASSET_ID, 400, None));
+
+    assert!(tx_result.is_ok());
+    assert_eq!(icrc1_balance_of(_account(user)), Nat::from(600u64));
+    assert_eq!(icrc1_total_supply(), Nat::from(600u64));
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 400);
+}
+
+#[test]
+fn test_that_qtokens_are_transferred_with_icrc1_and_icrc2() {
+    let user = _mock_principal(1);
+    let spender = _mock_principal(2);
+    let receiver = _mock_principal(3);
+    let ledger = _setup_qtoken_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    env::_set_caller(user);
+    let tx_result = icrc1_transfer(_qtoken_transfer_arg(receiver, 300));
+    assert!(tx_result.is_ok());
+
+    let tx_result = icrc1_transfer(_qtoken_transfer_arg(receiver, 800));
+    assert!(tx_result.is_err_and(|err| err
+        == Icrc1TransferError::InsufficientFunds {
+            balance: Nat::from(700u64)
+        }));
+
+    let tx_result = icrc2_approve(ApproveArgs {
+        from_subaccount: None,
+        spender: _account(spender),
+        amount: Nat::from(200u64),
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    });
+    assert!(tx_result.is_ok());
+
+    env::_set_caller(spender);
+    let transfer_from_args = TransferFromArgs {
+        spender_subaccount: None,
+        from: _account(user),
+        to: _account(receiver),
+        amount: Nat::from(200u64),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+    let tx_result = icrc2_transfer_from(transfer_from_args.clone());
+    assert!(tx_result.is_ok());
+
+    let tx_result = icrc2_transfer_from(transfer_from_args);
+    assert!(tx_result.is_err_and(|err| err
+        == TransferFromError::InsufficientAllowance {
+            allowance: Nat::from(0u64)
+        }));
+
+    assert_eq!(icrc1_balance_of(_account(user)), Nat::from(500u64));
+    assert_eq!(icrc1_balance_of(_account(receiver)), Nat::from(500u64));
+    assert_eq!(icrc1_total_supply(), Nat::from(1000u64));
+}
+
+#[test]
+fn test_that_qtoken_blocks_are_chained() {
+    let user = _mock_principal(1);
+    let receiver = _mock_principal(3);
+    let ledger = _setup_qtoken_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    env::_set_caller(user);
+    let block_index = icrc1_transfer(_qtoken_transfer_arg(receiver, 300)).unwrap();
+
+    let result = icrc3_get_blocks(vec![GetBlocksRequest {
+        start: Nat::from(0u64),
+        length: Nat::from(10u64),
+    }]);
+    assert_eq!(result.log_length, Nat::from(2u64));
+    assert_eq!(result.blocks[1].id, block_index);
+
+    let ICRC3Value::Map(block) = &result.blocks[1].block else {
+        panic!("Block is not a map")
+    };
+    assert_eq!(
+        block.get("phash"),
+        Some(&ICRC3Value::Blob(
+            result.blocks[0].block.clone().hash().to_vec().into()
+        ))
+    );
+    assert_eq!(
+        block.get("btype"),
+        Some(&ICRC3Value::Text("1xfer".to_string()))
+    );
+
+    let transfer = block_on(QTokenLedger.get_transfer(1)).unwrap().unwrap();
+    assert_eq!(transfer.amount, 300);
+    assert_eq!(transfer.to, _to_account_identifier(_account(receiver)));
+}
+
+#[test]
+fn test_that_builtin_qtoken_ledger_must_be_the_vault() {
+    _setup_qtoken_vault(0);
+
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            ledger_id: _mock_principal(103),
+            asset_type: AssetType::Mock,
+        },
+        virtual_asset: Asset {
+            ledger_id: _mock_principal(104),
+            asset_type: AssetType::QToken,
+        },
+        min_amount: 0,
+        metadata: None,
+    };
+
+    env::_set_caller(Principal::anonymous());
+    let tx_result = block_on(add_asset(details));
+
+    assert!(tx_result.is_err_and(|err| err == "Built-in QToken ledger id must be the vault's id"));
+}
+
+#[test]
+fn test_that_qtoken_metadata_follows_the_asset() {
+    _setup_qtoken_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    let tx_result = block_on(refresh_asset_metadata(INITIAL_ASSET_ID));
+
+    assert!(tx_result.is_ok_and(|metadata| metadata.virtual_asset.symbol == "qMOCK"));
+    assert_eq!(icrc1_symbol(), "qMOCK".to_string());
+    assert_eq!(icrc1_decimals(), 8);
+}
+
+#[test]
+fn test_that_duplicate_qtoken_transfers_are_rejected() {
+    let user = _mock_principal(1);
+    let receiver = _mock_principal(3);
+    let ledger = _setup_qtoken_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    env::_set_caller(user);
+    let mut transfer_arg = _qtoken_transfer_arg(receiver, 300);
+    transfer_arg.created_at_time = Some(env::time());
+
+    let block_index = icrc1_transfer(transfer_arg.clone()).unwrap();
+
+    let tx_result = icrc1_transfer(transfer_arg.clone());
+    assert!(tx_result.is_err_and(|err| err
+        == Icrc1TransferError::Duplicate {
+            duplicate_of: block_index.clone()
+        }));
+    assert_eq!(icrc1_balance_of(_account(receiver)), Nat::from(300u64));
+
+    // the same transfer with another memo is a new transaction
+    transfer_arg.memo = Some(Memo::from(1u64));
+    let tx_result = icrc1_transfer(transfer_arg);
+    assert!(tx_result.is_ok_and(|index| index != block_index));
+    assert_eq!(icrc1_balance_of(_account(receiver)), Nat::from(600u64));
+}
+
+#[test]
+fn test_that_qtoken_tip_is_certified() {
+    let user = _mock_principal(1);
+    let receiver = _mock_principal(3);
+    let ledger = _setup_qtoken_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    env::_set_caller(user);
+    let block_index = icrc1_transfer(_qtoken_transfer_arg(receiver, 300)).unwrap();
+
+    let result = icrc3_get_blocks(vec![GetBlocksRequest {
+        start: block_index.clone(),
+        length: Nat::from(1u64),
+    }]);
+    let last_block_hash = result.blocks[0].block.clone().hash();
+
+    assert_eq!(
+        env::_certified_data(),
+        _tip_root_hash(1, last_block_hash).to_vec()
+    );
+}
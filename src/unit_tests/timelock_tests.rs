@@ -0,0 +1,159 @@
+use super::*;
+
+#[test]
+fn test_that_timelocked_actions_are_executed_after_the_delay() {
+    let user = _mock_principal(1);
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    assert!(set_timelock_delay(DAY).is_ok());
+
+    let tx_result = set_locked_fees_share(INITIAL_ASSET_ID, 10 * _ONE_PERCENT);
+    assert!(
+        tx_result.is_err_and(|err| err == "Action is timelocked,queue it with queueAdminAction")
+    );
+
+    let action = AdminAction::SetLockedFeesShare {
+        asset_id: INITIAL_ASSET_ID,
+        share: 10 * _ONE_PERCENT,
+    };
+    env::_set_caller(user);
+    assert!(queue_admin_action(action.clone())
+        .is_err_and(|err| err == "Caller is not authorized for this admin action"));
+    env::_set_caller(Principal::anonymous());
+    let action_id = queue_admin_action(action).unwrap();
+
+    let pending_actions = get_pending_admin_actions();
+    assert_eq!(pending_actions.len(), 1);
+    assert_eq!(pending_actions[0].0, action_id);
+    assert_eq!(pending_actions[0].1.executable_at, 2 * DAY);
+
+    // anyone can execute the action once the delay has passed
+    env::_set_caller(user);
+    assert!(block_on(execute_admin_action(action_id))
+        .is_err_and(|err| err == "Admin action is not executable yet"));
+    env::_set_time(2 * DAY);
+    assert!(block_on(execute_admin_action(action_id)).is_ok());
+    assert_eq!(
+        _get_vault(INITIAL_ASSET_ID).locked_fees_share,
+        10 * _ONE_PERCENT
+    );
+    assert!(get_pending_admin_actions().is_empty());
+    assert!(
+        block_on(execute_admin_action(action_id)).is_err_and(|err| err == "Admin action not found")
+    );
+}
+
+#[test]
+fn test_that_queued_actions_can_be_cancelled() {
+    let user = _mock_principal(1);
+    let market = _mock_principal(3);
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    assert!(set_timelock_delay(DAY).is_ok());
+
+    let action = AdminAction::ApproveMarket {
+        market,
+        name: "Perps".to_string(),
+    };
+    let action_id = queue_admin_action(action).unwrap();
+
+    env::_set_caller(user);
+    assert!(cancel_admin_action(action_id)
+        .is_err_and(|err| err == "Caller is not authorized for this admin action"));
+    env::_set_caller(Principal::anonymous());
+    assert!(cancel_admin_action(action_id).is_ok());
+
+    env::_set_time(2 * DAY);
+    assert!(
+        block_on(execute_admin_action(action_id)).is_err_and(|err| err == "Admin action not found")
+    );
+    assert!(list_markets().is_empty());
+
+    // the delay can be increased directly but decreasing it is timelocked
+    assert!(set_timelock_delay(2 * DAY).is_ok());
+    assert!(set_timelock_delay(0)
+        .is_err_and(|err| err == "Action is timelocked,queue it with queueAdminAction"));
+    assert!(set_timelock_delay(31 * DAY)
+        .is_err_and(|err| err == "Timelock delay can not exceed 30 days"));
+}
+
+#[test]
+fn test_that_failed_actions_stay_queued() {
+    let market = _mock_principal(3);
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    assert!(set_timelock_delay(DAY).is_ok());
+
+    let action = AdminAction::ApproveMarket {
+        market,
+        name: "Perps".to_string(),
+    };
+    let first_id = queue_admin_action(action.clone()).unwrap();
+    let second_id = queue_admin_action(action).unwrap();
+    assert_ne!(first_id, second_id);
+
+    env::_set_time(2 * DAY);
+    assert!(block_on(execute_admin_action(first_id)).is_ok());
+    assert!(block_on(execute_admin_action(second_id))
+        .is_err_and(|err| err == "Market already approved"));
+
+    let pending_actions = get_pending_admin_actions();
+    assert_eq!(pending_actions.len(), 1);
+    assert_eq!(pending_actions[0].0, second_id);
+    assert_eq!(list_markets()[0].1.approved_by, Principal::anonymous());
+}
+
+#[test]
+fn test_that_role_and_governance_changes_are_timelocked() {
+    let user = _mock_principal(1);
+    let governance = _mock_principal(5);
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    assert!(set_timelock_delay(DAY).is_ok());
+
+    let timelocked = |tx_result: Result<(), String>| {
+        tx_result.is_err_and(|err| err == "Action is timelocked,queue it with queueAdminAction")
+    };
+    assert!(timelocked(grant_role(Role::Pauser, user)));
+    assert!(timelocked(resume_market(_mock_principal(3))));
+    let settings = GovernanceSettings {
+        mode: GovernanceMode::Admin,
+        governance_canister: Some(governance),
+    };
+    assert!(timelocked(set_governance(settings)));
+
+    let grant_id = queue_admin_action(AdminAction::GrantRole {
+        role: Role::Pauser,
+        user,
+    })
+    .unwrap();
+    let governance_id = queue_admin_action(AdminAction::SetGovernance { settings }).unwrap();
+    assert!(cancel_admin_action(governance_id).is_ok());
+
+    env::_set_time(2 * DAY);
+    env::_set_caller(user);
+    assert!(block_on(execute_admin_action(grant_id)).is_ok());
+    assert!(_is_authorized(user, Role::Pauser));
+    assert_eq!(get_governance().governance_canister, None);
+
+    let history = get_admin_action_history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(
+        history[0].1.status,
+        AdminActionStatus::Executed {
+            executed_by: user,
+            executed_at: 2 * DAY,
+        }
+    );
+    assert_eq!(
+        history[1].1.status,
+        AdminActionStatus::Cancelled {
+            cancelled_by: Principal::anonymous(),
+            cancelled_at: DAY,
+        }
+    );
+}
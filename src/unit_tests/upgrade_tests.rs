@@ -0,0 +1,90 @@
+use candid::Encode;
+use ic_stable_structures::Storable;
+
+use std::borrow::Cow;
+
+use super::*;
+
+use crate::core_lib::versioned::{_open_envelope, UNVERSIONED};
+
+#[test]
+fn test_that_stored_values_are_versioned() {
+    let config = LockSpanConfig {
+        duration: DAY,
+        weight: 3,
+        enabled: true,
+        max_capacity: Some(1000),
+    };
+
+    let bytes = config.to_bytes();
+    let (version, _) = _open_envelope(bytes.as_ref());
+    assert_eq!(version, 1);
+
+    let decoded = LockSpanConfig::from_bytes(bytes);
+    assert_eq!(decoded.duration, DAY);
+    assert_eq!(decoded.max_capacity, Some(1000));
+
+    // values stored before envelopes are plain Candid
+    let legacy_bytes = Encode!(&config).unwrap();
+    assert_eq!(_open_envelope(&legacy_bytes).0, UNVERSIONED);
+    let decoded = LockSpanConfig::from_bytes(Cow::Owned(legacy_bytes));
+    assert_eq!(decoded.weight, 3);
+}
+
+#[test]
+fn test_that_unversioned_bincode_details_are_decoded() {
+    let details = LiquidityManagerDetails {
+        asset: Asset {
+            ledger_id: _mock_principal(101),
+            asset_type: AssetType::ICRC,
+        },
+        virtual_asset: Asset {
+            ledger_id: _mock_principal(102),
+            asset_type: AssetType::ICRC,
+        },
+        min_amount: 10,
+        metadata: None,
+    };
+
+    let legacy_bytes = bincode::serialize(&details).unwrap();
+    let decoded = LiquidityManagerDetails::from_bytes(Cow::Owned(legacy_bytes));
+    assert_eq!(decoded.asset.ledger_id, _mock_principal(101));
+    assert_eq!(decoded.min_amount, 10);
+
+    let bytes = details.to_bytes();
+    assert_eq!(_open_envelope(bytes.as_ref()).0, 1);
+    assert_eq!(
+        LiquidityManagerDetails::from_bytes(bytes)
+            .virtual_asset
+            .ledger_id,
+        _mock_principal(102)
+    );
+}
+
+#[test]
+fn test_that_migrations_run_once_in_order() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    assert_eq!(get_schema_version(), 0);
+
+    _run_migrations();
+    assert_eq!(get_schema_version(), _latest_schema_version());
+
+    // migrated values keep their contents
+    assert_eq!(_get_vault(INITIAL_ASSET_ID).free_liquidity, 1000);
+    assert_eq!(
+        _get_liquidity_manager_details(INITIAL_ASSET_ID)
+            .asset
+            .ledger_id,
+        _mock_principal(101)
+    );
+
+    // migrations already run are skipped on later upgrades
+    SCHEMA_VERSION.with_borrow_mut(|reference| reference.set(5).unwrap());
+    _run_migrations();
+    assert_eq!(get_schema_version(), _latest_schema_version());
+}
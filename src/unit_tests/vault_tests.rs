@@ -0,0 +1,368 @@
+use super::*;
+
+use crate::core_lib::lock::{base_units, DEFAULT_EARLY_UNLOCK_PENALTY};
+
+#[test]
+fn test_that_funding_and_withdrawing_moves_the_asset() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 1000);
+    assert_eq!(ledger._balance(_account(user)), 0);
+    assert_eq!(ledger._balance(_account(env::canister_id())), 1000);
+
+    let tx_result = block_on(withdraw_from_account(INITIAL_ASSET_ID, 400, _account(user)));
+
+    assert!(tx_result.is_ok_and(|amount| amount == 400));
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 600);
+    assert_eq!(ledger._balance(_account(user)), 400);
+}
+
+#[test]
+fn test_that_failed_withdrawal_restores_margin_balance() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    ledger._set_failing(true);
+    let tx_result = block_on(withdraw_from_account(INITIAL_ASSET_ID, 400, _account(user)));
+
+    assert!(tx_result.is_err());
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 1000);
+}
+
+#[test]
+fn test_that_lending_mints_virtual_tokens() {
+    let user = _mock_principal(1);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    let tx_result = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    assert!(tx_result.is_ok());
+    assert_eq!(virtual_ledger._balance(_account(user)), 1000);
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 0);
+    assert_eq!(_get_vault(INITIAL_ASSET_ID).free_liquidity, 1000);
+}
+
+#[test]
+fn test_that_lock_is_returned_after_expiry() {
+    let user = _mock_principal(1);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    virtual_ledger._approve(_account(user), env::canister_id(), 1000);
+    let tx_result = block_on(lock_qtokens(
+        INITIAL_ASSET_ID,
+        1000,
+        MONTH2_SPAN_ID,
+        None,
+        None,
+    ));
+    assert!(tx_result.is_ok());
+    assert_eq!(virtual_ledger._balance(_account(user)), 0);
+
+    let (lock_timestamp, lock, _) = _get_user_locks(user)
+        .into_iter()
+        .find(|(_, lock, _)| lock.span_id == MONTH2_SPAN_ID)
+        .unwrap();
+
+    let tx_result = block_on(unlock_qtokens(lock_timestamp));
+    assert!(tx_result.is_err_and(|err| err == "Expiry time in the future"));
+
+    env::_set_time(lock.expiry_time);
+    let tx_result = block_on(unlock_qtokens(lock_timestamp));
+
+    assert!(tx_result.is_ok_and(|amount| amount == 1000));
+    assert_eq!(virtual_ledger._balance(_account(user)), 1000);
+    assert!(_try_get_user_lock(user, lock_timestamp).is_none());
+}
+
+#[test]
+fn test_that_failed_unlock_restores_lock() {
+    let user = _mock_principal(1);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+
+    virtual_ledger._approve(_account(user), env::canister_id(), 1000);
+    let _ = block_on(lock_qtokens(
+        INITIAL_ASSET_ID,
+        1000,
+        MONTH2_SPAN_ID,
+        None,
+        None,
+    ));
+
+    let (lock_timestamp, lock, _) = _get_user_locks(user)
+        .into_iter()
+        .find(|(_, lock, _)| lock.span_id == MONTH2_SPAN_ID)
+        .unwrap();
+
+    env::_set_time(lock.expiry_time);
+    virtual_ledger._set_failing(true);
+    let tx_result = block_on(unlock_qtokens(lock_timestamp));
+
+    assert!(tx_result.is_err());
+    assert_eq!(_get_user_lock(user, lock_timestamp).amount, 1000);
+    assert_eq!(
+        _get_vault(INITIAL_ASSET_ID)
+            ._span_details(MONTH2_SPAN_ID)
+            .total_locked,
+        1000
+    );
+}
+
+#[test]
+fn test_that_asset_metadata_is_refreshed_from_ledgers() {
+    _setup_mock_vault(0);
+
+    env::_set_caller(Principal::anonymous());
+    let tx_result = block_on(refresh_asset_metadata(INITIAL_ASSET_ID));
+
+    assert!(
+        tx_result.is_ok_and(|metadata| metadata.asset.symbol == "MOCK"
+            && metadata.virtual_asset.decimals == 8
+            && metadata.updated_at == DAY)
+    );
+    assert!(_get_liquidity_manager_details(INITIAL_ASSET_ID)
+        .metadata
+        .is_some());
+}
+
+#[test]
+fn test_that_asset_metadata_requires_vault_to_mint_virtual_asset() {
+    let user = _mock_principal(1);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+    virtual_ledger._set_minter(_account(_mock_principal(1)));
+
+    let mut details = _get_liquidity_manager_details(INITIAL_ASSET_ID);
+    details.metadata = None;
+    ASSETS.with_borrow_mut(|reference| reference.insert(INITIAL_ASSET_ID, details));
+
+    env::_set_caller(Principal::anonymous());
+    let tx_result = block_on(refresh_asset_metadata(INITIAL_ASSET_ID));
+
+    assert!(tx_result
+        .is_err_and(|err| { err == "Vault is not the minting account of the virtual asset" }));
+    assert_eq!(
+        get_asset_metadata_status(INITIAL_ASSET_ID),
+        Some(MetadataStatus::Pending)
+    );
+
+    // lending is rejected until the metadata is validated
+    _mock_fund_account(&ledger, user, 1000);
+    let tx_result = block_on(lend_to_vault(INITIAL_ASSET_ID, 1000, None));
+    assert!(tx_result.is_err_and(|err| err == "Asset metadata not validated"));
+}
+
+#[test]
+fn test_that_penalty_is_kept_when_no_span_can_earn_it() {
+    _setup_mock_vault(0);
+    let lock_spans = _get_lock_spans();
+
+    let mut vault = Vault::default();
+    assert_eq!(
+        vault._span_details(MONTH2_SPAN_ID).early_unlock_penalty,
+        DEFAULT_EARLY_UNLOCK_PENALTY
+    );
+
+    vault._distribute_penalty(MONTH2_SPAN_ID, 100, 0, &lock_spans);
+    assert_eq!(vault.fee_stream.undistributed_fees, 100);
+
+    // the kept penalty is credited once liquidity is lent
+    let lock = vault._create_lock(1000, INSTANT_SPAN_ID, 0);
+    vault._advance_fee_stream(DAY + 1, &lock_spans);
+    assert_eq!(vault.fee_stream.undistributed_fees, 0);
+    assert_eq!(vault._calc_lock_earnings(lock), 100);
+}
+
+#[test]
+fn test_that_increasing_lock_settles_earnings_first() {
+    let user = _mock_principal(1);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 2000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 2000, None));
+
+    virtual_ledger._approve(_account(user), env::canister_id(), 2000);
+    let _ = block_on(lock_qtokens(
+        INITIAL_ASSET_ID,
+        1000,
+        MONTH2_SPAN_ID,
+        None,
+        None,
+    ));
+    let (lock_timestamp, lock, _) = _get_user_locks(user)
+        .into_iter()
+        .find(|(_, lock, _)| lock.span_id == MONTH2_SPAN_ID)
+        .unwrap();
+
+    let mut vault = _get_vault(INITIAL_ASSET_ID);
+    vault
+        ._span_details_mut(MONTH2_SPAN_ID)
+        ._update_earnings(100);
+    _update_vault(INITIAL_ASSET_ID, vault);
+
+    let tx_result = block_on(increase_lock(lock_timestamp, 1000, None));
+    assert!(tx_result.is_ok_and(|amount| amount == 2000));
+
+    // the earnings accrued before the increase are paid out and not shared with the new amount
+    assert_eq!(virtual_ledger._balance(_account(user)), 100);
+    let lock_after = _get_user_lock(user, lock_timestamp);
+    assert_eq!(
+        _get_vault(INITIAL_ASSET_ID)._calc_lock_earnings(lock_after),
+        0
+    );
+
+    env::_set_time(lock.expiry_time);
+    let tx_result = block_on(increase_lock(lock_timestamp, 100, None));
+    assert!(tx_result.is_err_and(|err| err == "Can not increase expired locks"));
+}
+
+#[test]
+fn test_that_split_locks_never_have_pre_earnings_above_earnings() {
+    _setup_mock_vault(0);
+
+    let mut vault = Vault::default();
+    vault
+        ._span_details_mut(MONTH2_SPAN_ID)
+        .lifetime_earnings_per_token = base_units() / 2;
+    let mut lock = vault._create_lock(3, MONTH2_SPAN_ID, 0);
+    assert_eq!(lock.pre_earnings, 1);
+
+    // proportional pre earnings would leave the remaining lock of 1 with pre earnings of 1
+    let split_lock = lock._split(2, base_units() / 2);
+    assert_eq!(lock.pre_earnings, 0);
+    assert_eq!(vault._calc_lock_earnings(lock), 0);
+    assert_eq!(vault._calc_lock_earnings(split_lock), 1);
+}
+
+#[test]
+fn test_that_lock_tokens_are_indexed_by_owner() {
+    let user = _mock_principal(1);
+    let other_user = _mock_principal(2);
+    let (ledger, virtual_ledger) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 3000);
+    let _ = block_on(lend_to_vault(INITIAL_ASSET_ID, 3000, None));
+    virtual_ledger._approve(_account(user), env::canister_id(), 3000);
+    for _ in 0..3 {
+        let tx_result = block_on(lock_qtokens(
+            INITIAL_ASSET_ID,
+            1000,
+            MONTH2_SPAN_ID,
+            None,
+            None,
+        ));
+        assert!(tx_result.is_ok());
+    }
+
+    let token_ids = icrc7_tokens_of(_account(user), None, None);
+    assert_eq!(token_ids.len(), 3);
+    assert_eq!(
+        icrc7_tokens_of(
+            _account(user),
+            Some(token_ids[0].clone()),
+            Some(Nat::from(1u64))
+        ),
+        vec![token_ids[1].clone()]
+    );
+
+    let tx_results = icrc7_transfer(vec![TransferArg {
+        from_subaccount: None,
+        to: _account(other_user),
+        token_id: token_ids[1].clone(),
+        memo: None,
+        created_at_time: None,
+    }]);
+    assert!(tx_results[0].as_ref().is_some_and(|result| result.is_ok()));
+
+    assert_eq!(
+        icrc7_balance_of(vec![_account(user), _account(other_user)]),
+        vec![Nat::from(2u64), Nat::from(1u64)]
+    );
+    assert_eq!(
+        icrc7_tokens_of(_account(other_user), None, None),
+        vec![token_ids[1].clone()]
+    );
+
+    let transfers = vec![
+        TransferArg {
+            from_subaccount: None,
+            to: _account(other_user),
+            token_id: token_ids[0].clone(),
+            memo: None,
+            created_at_time: None,
+        };
+        MAX_UPDATE_BATCH_SIZE as usize + 1
+    ];
+    let tx_results = icrc7_transfer(transfers);
+    assert_eq!(tx_results.len(), 1);
+    assert!(tx_results[0].as_ref().is_some_and(|result| result.is_err()));
+}
+
+#[test]
+fn test_that_small_fees_do_not_push_back_streamed_fees() {
+    _setup_mock_vault(0);
+    let lock_spans = _get_lock_spans();
+
+    let mut vault = Vault::default();
+    vault.fee_stream.window = DAY;
+    vault._create_lock(1000, INSTANT_SPAN_ID, 0);
+
+    vault._stream_fees(1000, 0, &lock_spans);
+    assert_eq!(vault.fee_stream.end_time, DAY);
+
+    // a small fee half way only moves the end time by its share of the undistributed fees
+    vault._stream_fees(10, DAY / 2, &lock_spans);
+    assert_eq!(vault.fee_stream.undistributed_fees, 510);
+    assert!(vault.fee_stream.end_time < DAY + DAY / 50);
+
+    vault._advance_fee_stream(DAY + DAY / 50, &lock_spans);
+    assert_eq!(vault.fee_stream.undistributed_fees, 0);
+}
+
+#[test]
+fn test_that_transferred_deposit_is_claimed_once() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    ledger._mint(_account(user), 1000);
+    let block_index = ledger
+        ._transfer(1000, _account(user), _account(env::canister_id()))
+        .unwrap();
+    let block_index = u64::try_from(block_index.0).unwrap();
+
+    env::_set_caller(user);
+    let tx_result = block_on(claim_deposit(INITIAL_ASSET_ID, block_index, None));
+
+    assert!(tx_result.is_ok_and(|amount| amount == 1000));
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 1000);
+
+    let tx_result = block_on(claim_deposit(INITIAL_ASSET_ID, block_index, None));
+
+    assert!(tx_result.is_err_and(|err| err == "Deposit already claimed"));
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 1000);
+}
+
+#[test]
+fn test_that_funded_deposit_can_not_be_claimed() {
+    let user = _mock_principal(1);
+    let (ledger, _) = _setup_mock_vault(0);
+
+    _mock_fund_account(&ledger, user, 1000);
+
+    env::_set_caller(user);
+    let tx_result = block_on(claim_deposit(INITIAL_ASSET_ID, 0, None));
+
+    assert!(tx_result.is_err_and(|err| err == "Deposit already claimed"));
+    assert_eq!(_get_user_margin_balance(INITIAL_ASSET_ID, user), 1000);
+}